@@ -0,0 +1,118 @@
+#ifndef CURRUST_H
+#define CURRUST_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Status returned by fallible functions.
+typedef enum CurrustStatus {
+  // The call succeeded.
+  CURRUST_STATUS_OK = 0,
+  // A pointer was null, a string wasn't valid UTF-8, or an enum value is unknown.
+  CURRUST_STATUS_INVALID_ARGUMENT = 1,
+  // The call failed, see [`currust_last_error`].
+  CURRUST_STATUS_ERROR = 2,
+  // currust panicked, which is a bug.
+  CURRUST_STATUS_PANIC = 3,
+} CurrustStatus;
+
+// Algorithms for scaling cursors, passed as a `uint32_t`.
+typedef enum CurrustScaling {
+  CURRUST_SCALING_NEAREST = 0,
+  CURRUST_SCALING_BOX = 1,
+  CURRUST_SCALING_BILINEAR = 2,
+  CURRUST_SCALING_MITCHELL = 3,
+  CURRUST_SCALING_LANCZOS3 = 4,
+} CurrustScaling;
+
+// Opaque handle to a cursor.
+typedef struct CurrustCursor CurrustCursor;
+
+// A buffer allocated by currust, which must be freed with [`currust_buffer_free`].
+typedef struct CurrustBuffer {
+  // Pointer to the data, or null if empty.
+  uint8_t *data;
+  // Length of the data in bytes.
+  uintptr_t len;
+} CurrustBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the message of the last error on this thread, or null if there's none.
+//
+// The string is owned by currust, and is valid until the next call on this thread.
+const char *currust_last_error(void);
+
+// Reads a cursor (e.g., ANI or CUR) from `data` with length `len`, and stores it in `out`.
+//
+// The format is detected from the contents. The cursor must be freed with [`currust_cursor_free`].
+//
+// ## Safety
+//
+// `data` must be valid for reads of `len` bytes, and `out` must be valid for writes.
+enum CurrustStatus currust_cursor_from_buffer(const uint8_t *data,
+                                              uintptr_t len,
+                                              struct CurrustCursor **out);
+
+// Adds a scaled size to `cursor` by `scale_factor`, using `algorithm` (a [`CurrustScaling`]).
+//
+// ## Safety
+//
+// `cursor` must be a cursor from [`currust_cursor_from_buffer`] that hasn't been freed.
+enum CurrustStatus currust_cursor_add_scale(struct CurrustCursor *cursor,
+                                            double scale_factor,
+                                            uint32_t algorithm);
+
+// Writes `cursor` as Xcursor to a new buffer, stored in `out`.
+//
+// The buffer must be freed with [`currust_buffer_free`].
+//
+// ## Safety
+//
+// `cursor` must be a cursor from [`currust_cursor_from_buffer`] that hasn't
+// been freed, and `out` must be valid for writes.
+enum CurrustStatus currust_cursor_write_xcursor(const struct CurrustCursor *cursor,
+                                                struct CurrustBuffer *out);
+
+// Frees `cursor`. Does nothing if `cursor` is null.
+//
+// ## Safety
+//
+// `cursor` must be null or a cursor from [`currust_cursor_from_buffer`] that hasn't been freed.
+void currust_cursor_free(struct CurrustCursor *cursor);
+
+// Frees `buffer`. Does nothing if its data is null.
+//
+// ## Safety
+//
+// `buffer` must be from currust, and must not have been freed.
+void currust_buffer_free(struct CurrustBuffer buffer);
+
+// Converts the theme in the directory `theme_dir`, which must contain one installer
+// file (INF or CRS), to an X11 cursor theme, which is written inside of the directory `out_dir`.
+//
+// Each of the `num_scales` scale factors in `scales` are added using
+// `algorithm` (a [`CurrustScaling`]).
+// `scales` may be null if `num_scales` is zero.
+//
+// ## Safety
+//
+// `theme_dir` and `out_dir` must be valid null-terminated strings,
+// and `scales` must be valid for reads of `num_scales` elements.
+enum CurrustStatus currust_convert_theme(const char *theme_dir,
+                                         const char *out_dir,
+                                         const double *scales,
+                                         uintptr_t num_scales,
+                                         uint32_t algorithm);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CURRUST_H */
@@ -1,18 +1,28 @@
 //! Module for [`clap`] code.
 //!
-//! This contains the [`Args`] struct, which has the [`Parser`] trait,
-//! and the [`ParsedArgs`] struct, which is just plain old data.
+//! This contains the [`Args`] struct, which has the [`Parser`] trait and a [`Command`]
+//! for each workflow, and the [`ParsedArgs`] struct, which is just plain old data.
 
 use crate::{
-    fs_utils::find_extensions_icase,
-    themes::theme::{CursorMapping, CursorTheme, CursorType, TypedCursor},
-    warn,
+    config::{Config, ScalingConfig, ThemeConfig},
+    cursors::{
+        decode::{DecodeOptions, InvertedPixels, Limits, MissingSizes},
+        generic_cursor::{GenericCursor, parse_hotspot},
+    },
+    formats::registry::{CursorEncoder, Registry},
+    fs_utils::find_files,
+    logger::LogFormat,
+    themes::theme::{CursorMapping, CursorTheme, CursorType, is_installer},
 };
 
-use std::{fs, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    env, fs, iter,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Result, anyhow, bail};
-use clap::{Parser, ValueEnum};
+use anyhow::{Context, Result, anyhow, bail};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use fast_image_resize::{FilterType, ResizeAlg};
 
 use dialoguer::{
@@ -21,11 +31,71 @@ use dialoguer::{
     theme::ColorfulTheme,
 };
 use documented::DocumentedVariants;
+use log::{info, warn};
+use serde::Deserialize;
 
 /// Raw arguments from CLI. Has the [`Parser`] trait.
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    /// What to do.
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Reads defaults from this config file, instead of "currust.toml" files.
+    ///
+    /// Otherwise, the nearest "currust.toml" in the current directory or its parents is
+    /// read, along with "currust.toml" in `$XDG_CONFIG_HOME` (or "~/.config").
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub logging: LogArgs,
+}
+
+/// Options for logging, shared between every subcommand.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Logging")]
+pub struct LogArgs {
+    /// Logs more: skipped steps and other details (-v), and everything (-vv).
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Logs less: only warnings and errors (-q), only errors (-qq), and nothing (-qqq).
+    ///
+    /// Errors that stop currust are always printed.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub quiet: u8,
+
+    /// The format of logged messages.
+    ///
+    /// format description
+    /// text   human-readable lines, tagged with the theme and file being processed.
+    /// json   a JSON object per line, with "level", "message", "theme" and "file" keys.
+    #[arg(long, default_value = "text", global = true, verbatim_doc_comment)]
+    pub log_format: LogFormat,
+}
+
+/// Subcommands, one for each workflow.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Converts cursor themes and cursor files.
+    Convert(ConvertArgs),
+    /// Converts cursor themes and installs them for the current user.
+    Install(InstallArgs),
+    /// Prints the parsed structure of cursor themes and cursor files.
+    Inspect(InspectArgs),
+    /// Extracts every frame and size of cursors as PNG images.
+    Extract(ExtractArgs),
+    /// Previews cursors in the terminal.
+    Preview(PreviewArgs),
+    /// Checks that cursor themes and cursor files can be converted, without writing anything.
+    Validate(ValidateArgs),
+}
+
+/// Arguments for [`Command::Convert`].
+#[derive(clap::Args)]
+pub struct ConvertArgs {
     /// The paths to cursor theme installers, cursor files, directories.
     ///
     /// Supported theme installer formats include INF and CRS as of now.
@@ -33,6 +103,9 @@ pub struct Args {
     /// Cursor file paths are converted to Xcursor (named the same as the cursor file, bar
     /// extension), while directories are expanded to all the cursor files it contains
     /// (non-recursively). This acts as an alternative for shells that can't glob (e.g., cmd).
+    ///
+    /// Cursor files (including ICO, PNG and BMP images) are recognised by their
+    /// contents, so misnamed files are still read.
     #[arg(required = true)]
     paths: Vec<PathBuf>,
 
@@ -49,6 +122,275 @@ pub struct Args {
     #[arg(long, verbatim_doc_comment)]
     manual: bool,
 
+    /// The format to write themes as.
+    ///
+    /// format  description
+    /// xcursor an X11 cursor theme (directory), used on Linux.
+    /// cape    a Mousecape cape (file), used on macOS. requires a theme.
+    #[arg(
+        long,
+        default_value = "xcursor",
+        value_name = "FORMAT",
+        verbatim_doc_comment
+    )]
+    to: String,
+
+    /// The directory to place the parsed themes/files.
+    ///
+    /// If the provided path doesn't exist yet, this attempts to create it, including parents.
+    /// Defaults to the current directory, unless set in a config file.
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    conflicts: ConflictArgs,
+
+    #[command(flatten)]
+    hotspots: HotspotArgs,
+
+    #[command(flatten)]
+    scaling: ScalingArgs,
+
+    #[command(flatten)]
+    decoding: DecodeArgs,
+}
+
+/// Arguments for [`Command::Install`].
+#[derive(clap::Args)]
+pub struct InstallArgs {
+    /// The paths to cursor theme installers (INF or CRS).
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// The directory to install themes in, instead of "~/.icons".
+    #[arg(long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    conflicts: ConflictArgs,
+
+    #[command(flatten)]
+    hotspots: HotspotArgs,
+
+    #[command(flatten)]
+    scaling: ScalingArgs,
+
+    #[command(flatten)]
+    decoding: DecodeArgs,
+}
+
+/// Arguments for [`Command::Inspect`].
+#[derive(clap::Args)]
+pub struct InspectArgs {
+    /// The paths to cursor theme installers, cursor files, directories.
+    ///
+    /// Installers are shown with their mappings, and whether each cursor path exists. Cursor
+    /// files (ANI, CUR, ICO, Xcursor and images) are shown with their headers and images.
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Prints the structure as JSON, instead of as an indented tree.
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub decoding: DecodeArgs,
+}
+
+/// Arguments for [`Command::Extract`].
+#[derive(clap::Args)]
+pub struct ExtractArgs {
+    /// The paths to cursor theme installers, cursor files, directories.
+    ///
+    /// Each image is written as "STEM-WxH-FRAME.png", where STEM is the file name of the
+    /// cursor without its extension. A ".hotspot" sidecar file is written next to each image,
+    /// so they can be converted back into cursors.
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// The directory to place the extracted images.
+    ///
+    /// If the provided path doesn't exist yet, this attempts to create it, including parents.
+    #[arg(short, long, default_value = "./")]
+    pub out: PathBuf,
+
+    #[command(flatten)]
+    pub scaling: ScalingArgs,
+
+    #[command(flatten)]
+    pub decoding: DecodeArgs,
+}
+
+/// Arguments for [`Command::Preview`].
+#[derive(clap::Args)]
+pub struct PreviewArgs {
+    /// The paths to cursor theme installers, cursor files, directories.
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Shows every frame of animated cursors, instead of just the first.
+    #[arg(long)]
+    pub all_frames: bool,
+
+    #[command(flatten)]
+    pub decoding: DecodeArgs,
+}
+
+/// Arguments for [`Command::Validate`].
+#[derive(clap::Args)]
+pub struct ValidateArgs {
+    /// The paths to cursor theme installers, cursor files, directories.
+    ///
+    /// Each path is read, scaled and encoded as Xcursor in memory.
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub scaling: ScalingArgs,
+
+    #[command(flatten)]
+    pub decoding: DecodeArgs,
+}
+
+/// Options for writing outputs, shared between conversions.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Output")]
+struct OutputArgs {
+    /// Prints what would be converted and written, without writing anything.
+    ///
+    /// For each cursor, this lists the file it's read from, the sizes it would have, and the
+    /// paths it would be written to. For Xcursor themes, this includes each alias (symlink).
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Continues past files that fail to convert, then prints a summary of the failures.
+    ///
+    /// This works per theme, not per cursor: a theme with a cursor that fails to
+    /// convert is skipped as a whole (and reported), rather than written without it.
+    ///
+    /// If some files were converted but others failed, this exits with code 3
+    /// (instead of 1), so that partial success can be told apart from failure.
+    #[arg(short, long)]
+    keep_going: bool,
+}
+
+/// Options for existing outputs, shared between conversions.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Conflicts")]
+#[group(multiple = false)]
+struct ConflictArgs {
+    /// Overwrites themes and files that already exist.
+    ///
+    /// By default, the conversion fails before anything is written if any of them exist.
+    #[arg(long)]
+    force: bool,
+
+    /// Skips themes and files that already exist, leaving them as they are.
+    #[arg(long)]
+    skip_existing: bool,
+
+    /// Writes to a numbered path (e.g., "theme-2") if the theme or file already exists.
+    ///
+    /// This also applies to outputs that would collide with each other,
+    /// such as themes with the same (or a blank) name.
+    #[arg(long)]
+    suffix: bool,
+}
+
+impl ConflictArgs {
+    /// Returns the chosen [`Conflict`] policy.
+    const fn policy(&self) -> Conflict {
+        if self.force {
+            Conflict::Overwrite
+        } else if self.skip_existing {
+            Conflict::Skip
+        } else if self.suffix {
+            Conflict::Suffix
+        } else {
+            Conflict::Error
+        }
+    }
+}
+
+/// What to do if an output (theme or file) already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Fail before anything is written.
+    Error,
+    /// Overwrite the existing output.
+    Overwrite,
+    /// Leave the existing output and don't convert.
+    Skip,
+    /// Write to a numbered path instead.
+    Suffix,
+}
+
+/// Options for hotspots, shared between subcommands.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Hotspots")]
+struct HotspotArgs {
+    /// Overrides the hotspot of a cursor, given as "KEY=X,Y".
+    ///
+    /// KEY is either the file name of the cursor (e.g., "arrow.png=9,2") or, for themes, the
+    /// role of the cursor (e.g., "hand=9,2", "nwse-resize=16,16"). Keys that name a role are
+    /// read as the role, so prefix file names like these with "file:" to use the file instead.
+    /// File names take priority over roles, and both take priority over hotspots stored in
+    /// cursors and ".hotspot" sidecar files (e.g., "arrow.png.hotspot").
+    ///
+    /// Images (ICO, PNG and BMP) with neither use the topmost-leftmost opaque pixel instead.
+    ///
+    /// The hotspot is for the original size of the cursor, and is scaled for other sizes.
+    #[arg(long, num_args(1..), value_name = "KEY=X,Y", value_parser = parse_hotspot_override)]
+    hotspot: Vec<HotspotOverride>,
+
+    /// Reads hotspot overrides from a file, with one "KEY=X,Y" per line.
+    ///
+    /// See "--hotspot" for the format. Blank lines and lines starting with '#' are ignored.
+    /// Overrides passed with "--hotspot" take priority over ones in this file.
+    #[arg(long, value_name = "PATH")]
+    hotspot_file: Option<PathBuf>,
+
+    /// Detects hotspots from the shape of cursors in themes, if they're missing or suspicious.
+    ///
+    /// Hotspots are missing for images without a sidecar file, and are suspicious if they're
+    /// at the top-left corner with nothing drawn there. The heuristic used depends on the
+    /// role of the cursor (e.g., the centre of a crosshair, the fingertip of a hand).
+    ///
+    /// Detected hotspots are reported, since they may need to be reviewed. This has no effect
+    /// on cursors with a hotspot set through "--hotspot".
+    #[arg(long)]
+    detect_hotspots: bool,
+}
+
+impl HotspotArgs {
+    /// Returns all overrides, reading [`Self::hotspot_file`] if provided.
+    ///
+    /// ## Errors
+    ///
+    /// If the hotspot file can't be read or parsed.
+    fn overrides(self) -> Result<Vec<HotspotOverride>> {
+        let mut hotspots = match &self.hotspot_file {
+            Some(path) => read_hotspot_file(path)?,
+            None => Vec::new(),
+        };
+
+        // later overrides take priority
+        hotspots.extend(self.hotspot);
+
+        Ok(hotspots)
+    }
+}
+
+/// Options for scaling, shared between subcommands.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Scaling")]
+pub struct ScalingArgs {
     /// Uses the provided scaling algorithm.
     ///
     /// This is overridden by "--upscale-with" and "--downscale-with", if set.
@@ -59,13 +401,10 @@ pub struct Args {
     /// bilinear  smooth shapes, not recommended if sharpness is desired.
     /// mitchell  general-purpose upscaling, balances smoothness and sharpness.
     /// lanczos3  general-purpose downscaling, preserves details but may cause artifacts.
-    #[arg(
-        long,
-        default_value = "box",
-        value_name = "ALGORITHM",
-        verbatim_doc_comment
-    )]
-    scale_with: ScalingAlgorithm,
+    ///
+    /// Defaults to box, unless set in a config file.
+    #[arg(long, value_name = "ALGORITHM", verbatim_doc_comment)]
+    scale_with: Option<ScalingAlgorithm>,
 
     /// Uses the provided scaling algorithm for upscaling.
     ///
@@ -85,19 +424,296 @@ pub struct Args {
     /// etc. Any negative values are considered invalid scale factors.
     ///
     /// All scaled variations and the original cursor are included in the produced Xcursor file(s).
+    /// If not provided, scale factors are read from a config file (if any).
     #[arg(long, value_parser, num_args(1..), value_name = "F64_SCALE_FACTORS")]
     scale_to: Vec<f64>,
+}
 
-    /// The directory to place the parsed themes/files.
+/// Options for decoding, shared between subcommands.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Decoding")]
+pub struct DecodeArgs {
+    /// Renders inverted pixels of legacy monochrome cursors with the provided strategy.
     ///
-    /// If the provided path doesn't exist yet, this attempts to create it, including parents.
-    #[arg(short, long, default_value = "./")]
-    out: PathBuf,
+    /// Old cursors (e.g., from Windows 3.x/9x) can have pixels that invert the screen
+    /// underneath them, which have no equivalent in Xcursor, so they're approximated.
+    ///
+    /// strategy  description
+    /// outline   black, with a white outline so it's visible on dark backgrounds.
+    /// black     solid black.
+    /// white     solid white.
+    /// checker   a checker pattern of black and white.
+    #[arg(
+        long,
+        default_value = "outline",
+        value_name = "STRATEGY",
+        verbatim_doc_comment
+    )]
+    inverted_pixels: InvertedPixelsStrategy,
+
+    /// Skips unknown chunks in ANI files instead of failing.
+    ///
+    /// Windows still renders these files, so use this if a cursor fails to parse with
+    /// "unexpected fourcc" or similar. Each skipped chunk is reported as a warning.
+    #[arg(long)]
+    lenient: bool,
+
+    /// Places frames of animated cursors on a common canvas if their sizes differ.
+    ///
+    /// Frames are aligned by their hotspots, so the animation stays in place
+    /// relative to the pointer. Without this, these cursors fail to convert.
+    #[arg(long)]
+    align_frames: bool,
+
+    /// What to do with sizes that only some frames of an animated cursor have.
+    ///
+    /// Every size needs all frames, so these sizes are incomplete.
+    ///
+    /// strategy  description
+    /// drop      drops the incomplete size.
+    /// scale     fills in missing frames by scaling from the base size, using the scaling algorithms
+    ///           (including any for the cursor's theme or role in a config file).
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        default_value = "drop",
+        verbatim_doc_comment
+    )]
+    missing_sizes: MissingSizesStrategy,
+
+    /// Max size of a single chunk (e.g., a frame) of an ANI file, in bytes.
+    ///
+    /// This guards against malformed files. Raise it for very large animated cursors.
+    #[arg(
+        long,
+        value_name = "BYTES",
+        default_value_t = Limits::default().max_chunk_size,
+        help_heading = "Limits"
+    )]
+    max_chunk_size: u32,
+
+    /// Max number of frames stored in an animated cursor.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = Limits::default().max_frames,
+        help_heading = "Limits"
+    )]
+    max_frames: u32,
+
+    /// Max number of steps in an animation (frames can be repeated in its sequence).
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = Limits::default().max_steps,
+        help_heading = "Limits"
+    )]
+    max_steps: u32,
+
+    /// Max number of pixels in a single image, including scaled images.
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = Limits::default().max_image_pixels,
+        help_heading = "Limits"
+    )]
+    max_image_pixels: u64,
+
+    /// Max number of pixels across all images of a cursor, including scaled images.
+    ///
+    /// Pixels take 4 bytes each, so this also limits memory usage and the size of the output.
+    #[arg(
+        long,
+        value_name = "PIXELS",
+        default_value_t = Limits::default().max_total_pixels,
+        help_heading = "Limits"
+    )]
+    max_total_pixels: u64,
+}
+
+impl DecodeArgs {
+    /// Returns the [`DecodeOptions`] for these arguments.
+    ///
+    /// `scaling` is used for filling in missing sizes, if provided. Otherwise, box is used.
+    /// For cursors in a theme, see [`Scaling::decode_options`] instead.
+    #[must_use]
+    pub fn options(&self, scaling: Option<&Scaling>) -> DecodeOptions {
+        let missing_sizes = match self.missing_sizes {
+            MissingSizesStrategy::Drop => MissingSizes::Drop,
+            MissingSizesStrategy::Scale => {
+                let box_alg = ResizeAlg::from(&ScalingAlgorithm::Box);
+
+                MissingSizes::Scale {
+                    upscale_with: box_alg,
+                    downscale_with: box_alg,
+                }
+            }
+        };
+
+        let options = DecodeOptions {
+            inverted_pixels: InvertedPixels::from(&self.inverted_pixels),
+            lenient: self.lenient,
+            align_frames: self.align_frames,
+            missing_sizes,
+            limits: Limits {
+                max_chunk_size: self.max_chunk_size,
+                max_frames: self.max_frames,
+                max_steps: self.max_steps,
+                max_image_pixels: self.max_image_pixels,
+                max_total_pixels: self.max_total_pixels,
+            },
+        };
+
+        match scaling {
+            Some(scaling) => scaling.decode_options(&options),
+            None => options,
+        }
+    }
+}
+
+/// A hotspot provided from the CLI for a specific cursor.
+#[derive(Debug, Clone)]
+pub struct HotspotOverride {
+    /// Which cursor(s) to apply this to.
+    pub target: HotspotTarget,
+    /// The hotspot as (x, y).
+    pub hotspot: (u32, u32),
+}
+
+/// Cursor(s) that a [`HotspotOverride`] applies to.
+///
+/// Keys that name a role (e.g., "hand") are read as [`Self::Type`], even if
+/// they're also the name of a file, unless they're prefixed with "file:".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotspotTarget {
+    /// Cursors with this file name.
+    File(String),
+    /// Cursors with this role, in themes.
+    Type(CursorType),
+}
+
+/// Parses `s` as a [`HotspotOverride`], formatted as "KEY=X,Y".
+///
+/// KEY is read as a role if it names one (see [`CursorType::from_name`]), unless
+/// it's prefixed with "file:", and as a file name otherwise.
+fn parse_hotspot_override(s: &str) -> Result<HotspotOverride> {
+    let (key, hotspot) = s
+        .rsplit_once('=')
+        .ok_or_else(|| anyhow!("expected 'KEY=X,Y', got {s}"))?;
+
+    let target = match key.strip_prefix("file:") {
+        Some(file_name) => HotspotTarget::File(file_name.to_string()),
+        None => CursorType::from_name(key)
+            .map_or_else(|| HotspotTarget::File(key.to_string()), HotspotTarget::Type),
+    };
+
+    Ok(HotspotOverride {
+        target,
+        hotspot: parse_hotspot(hotspot)?,
+    })
+}
+
+/// Returns true if `path` is a cursor file that `registry` can read, see [`Registry::detect`].
+///
+/// Unless `explicit` (i.e., the path was passed rather than found in a directory), only files
+/// that are always cursors are included, see
+/// [`is_cursor_format`](crate::formats::registry::CursorDecoder::is_cursor_format).
+///
+/// Files that can't be read (e.g., due to permissions) are treated as not being cursors.
+fn is_cursor_file(registry: &Registry, path: &Path, explicit: bool) -> bool {
+    registry
+        .detect(path)
+        .inspect_err(|err| warn!("{err}"))
+        .is_ok_and(|decoder| decoder.is_some_and(|d| explicit || d.is_cursor_format()))
+}
+
+/// Splits `paths` into installer files and cursor files (in that order), where
+/// cursor files are the ones `registry` can read, see [`split_paths_by`].
+///
+/// ## Errors
+///
+/// See [`split_paths_by`].
+pub(super) fn split_paths(
+    paths: Vec<PathBuf>,
+    registry: &Registry,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    split_paths_by(paths, |path, explicit| {
+        is_cursor_file(registry, path, explicit)
+    })
+}
+
+/// Splits `paths` into installer files and cursor files (in that order), where cursor
+/// files are recognised with `is_cursor`. This is passed each path, along with whether
+/// it was passed explicitly (rather than found in a directory).
+///
+/// Directories are expanded to the cursor files they contain, see [`find_files`].
+/// Paths that are neither are ignored, with a warning.
+///
+/// ## Errors
+///
+/// If any of `paths` don't exist, or a directory can't be read.
+pub(super) fn split_paths_by(
+    paths: Vec<PathBuf>,
+    is_cursor: impl Fn(&Path, bool) -> bool,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let mut installer_files = Vec::new();
+    let mut cursor_files = Vec::new();
+
+    for path in paths {
+        let path_display = path.display();
+
+        if !path.exists() {
+            // this is not my problem. https://github.com/rust-lang/rust/issues/72653
+            #[cfg(windows)]
+            bail!(
+                "path={path_display} doesn't exist. \n\
+                note that if you use powershell and your path looks similar to the \
+                first, convert it to the second by removing the trailing backslash: \n\
+                .\\currust.exe '.\\a path\\to a\\dir\\' -> .\\currust.exe '.\\a path\\to a\\dir'"
+            );
+
+            bail!("path={path_display} doesn't exist");
+        }
+
+        if path.is_dir() {
+            cursor_files.extend(find_files(&path, |p| is_cursor(p, false))?);
+        } else if path.is_file() {
+            if is_installer(&path) {
+                installer_files.push(path);
+            } else if is_cursor(&path, true) {
+                cursor_files.push(path);
+            } else {
+                warn!("ignoring file {path_display} as it is not a cursor");
+            }
+        } else {
+            warn!("ignoring path={path_display} as it is neither a dir or a file");
+        }
+    }
+
+    Ok((installer_files, cursor_files))
+}
+
+/// Reads `path` as a list of [`HotspotOverride`], see [`HotspotArgs::hotspot_file`].
+fn read_hotspot_file(path: &Path) -> Result<Vec<HotspotOverride>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read hotspot_file={}", path.display()))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(n, line)| {
+            parse_hotspot_override(line)
+                .with_context(|| format!("on line {n} of hotspot_file={}", path.display()))
+        })
+        .collect()
 }
 
 /// User-facing enum for usable scaling algorithms.
-#[derive(Debug, Clone, ValueEnum)]
-enum ScalingAlgorithm {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScalingAlgorithm {
     Nearest,
     Box,
     Bilinear,
@@ -105,6 +721,33 @@ enum ScalingAlgorithm {
     Lanczos3,
 }
 
+/// User-facing enum for [`InvertedPixels`].
+#[derive(Debug, Clone, ValueEnum)]
+enum InvertedPixelsStrategy {
+    Outline,
+    Black,
+    White,
+    Checker,
+}
+
+impl From<&InvertedPixelsStrategy> for InvertedPixels {
+    fn from(strategy: &InvertedPixelsStrategy) -> Self {
+        match strategy {
+            InvertedPixelsStrategy::Outline => Self::Outline,
+            InvertedPixelsStrategy::Black => Self::Black,
+            InvertedPixelsStrategy::White => Self::White,
+            InvertedPixelsStrategy::Checker => Self::Checker,
+        }
+    }
+}
+
+/// User-facing enum for [`MissingSizes`].
+#[derive(Debug, Clone, ValueEnum)]
+enum MissingSizesStrategy {
+    Drop,
+    Scale,
+}
+
 // not meant to be used directly; use ResizeAlg impl.
 impl From<&ScalingAlgorithm> for FilterType {
     fn from(alg: &ScalingAlgorithm) -> Self {
@@ -127,121 +770,454 @@ impl From<&ScalingAlgorithm> for ResizeAlg {
     }
 }
 
-/// Parsed CLI arguments.
+/// Parsed scaling options.
 #[derive(Debug)]
-pub struct ParsedArgs {
-    /// All installer files.
-    pub installer_files: Vec<PathBuf>,
-    /// All cursor files.
-    pub cursor_files: Vec<PathBuf>,
-    /// Installation is manual. Or not.
-    pub manual: bool,
-    /// Scale factors.
+pub struct Scaling {
+    /// Scale factors, sorted and deduplicated.
     pub scale_to: Vec<f64>,
     /// Algorithm for upscaling.
     pub upscale_with: ResizeAlg,
     /// Algorithm for downscaling.
     pub downscale_with: ResizeAlg,
-    /// Where to put parsed Xcursor files.
-    pub out: PathBuf,
+    /// Overrides for cursors with a role, see [`Config::roles`].
+    roles: BTreeMap<String, ScalingConfig>,
+    /// Overrides for themes, see [`Config::themes`].
+    themes: BTreeMap<String, ThemeConfig>,
 }
 
-impl ParsedArgs {
-    /// Parses `args`.
-    ///
-    /// ## Panics
+impl Scaling {
+    /// Parses `args`, with defaults and overrides from `config`.
     ///
-    /// If `NaN` is in `Args::scale_to` (should be impossible).
+    /// Flags in `args` take priority over the defaults in `config`, but not its overrides.
     ///
     /// ## Errors
     ///
-    /// If any provided paths don't exist or `out` directory can't be made.
-    pub fn from_args(args: Args) -> Result<Self> {
-        let paths = args.paths;
-        let manual = args.manual;
-        let mut installer_files = Vec::new();
-        let mut cursor_files = Vec::new();
-
-        for path in paths {
-            let path_display = path.display();
-
-            if !path.exists() {
-                // this is not my problem. https://github.com/rust-lang/rust/issues/72653
-                #[cfg(windows)]
-                bail!(
-                    "path={path_display} doesn't exist. \n\
-                    note that if you use powershell and your path looks similar to the \
-                    first, convert it to the second by removing the trailing backslash: \n\
-                    .\\currust.exe '.\\a path\\to a\\dir\\' -> .\\currust.exe '.\\a path\\to a\\dir'"
-                );
+    /// If any scale factor is invalid, including ones in `config`.
+    pub fn from_args(args: ScalingArgs, config: &Config) -> Result<Self> {
+        let defaults = &config.scaling;
+        let scale_with = args
+            .scale_with
+            .or(defaults.scale_with)
+            .unwrap_or(ScalingAlgorithm::Box);
 
-                bail!("path={path_display} doesn't exist");
-            }
+        // up/downscaling algorithms from the config don't apply over "--scale-with"
+        let default_with =
+            |alg: Option<ScalingAlgorithm>| alg.filter(|_| args.scale_with.is_none());
+        let (upscale_with, downscale_with) = (
+            args.upscale_with
+                .or_else(|| default_with(defaults.upscale_with))
+                .unwrap_or(scale_with),
+            args.downscale_with
+                .or_else(|| default_with(defaults.downscale_with))
+                .unwrap_or(scale_with),
+        );
 
-            if path.is_dir() {
-                cursor_files.extend(find_extensions_icase(&path, &["cur", "ani"])?);
-            } else if path.is_file() {
-                let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
-                    warn!("ignoring file {path_display} as it has no extension");
-                    continue;
-                };
-
-                match ext.to_ascii_lowercase().as_str() {
-                    "inf" | "crs" => installer_files.push(path),
-                    "cur" | "ani" => cursor_files.push(path),
-                    _ => warn!("ignoring file {path_display} as it is not a cursor"),
-                }
-            } else {
-                warn!("ignoring path={path_display} as it is neither a dir or a file",);
-            }
+        let mut scale_to = if args.scale_to.is_empty() {
+            defaults.scale_to.clone().unwrap_or_default()
+        } else {
+            args.scale_to
+        };
+
+        let overrides = config.roles.values().chain(
+            config
+                .themes
+                .values()
+                .flat_map(|t| iter::once(&t.scaling).chain(t.roles.values())),
+        );
+
+        validate_scale_factors(&scale_to)?;
+        for o in overrides {
+            validate_scale_factors(o.scale_to.as_deref().unwrap_or_default())?;
         }
 
-        let mut scale_to = args.scale_to;
+        scale_to.sort_unstable_by(f64::total_cmp);
+        scale_to.dedup();
+
+        Ok(Self {
+            scale_to,
+            upscale_with: ResizeAlg::from(&upscale_with),
+            downscale_with: ResizeAlg::from(&downscale_with),
+            roles: config.roles.clone(),
+            themes: config.themes.clone(),
+        })
+    }
+
+    /// Returns the scaling for a cursor with `role` in the theme named `theme`,
+    /// as in its installer (see [`CursorTheme::name`]).
+    ///
+    /// Overrides for the theme apply first, then ones for the role,
+    /// then ones for the role in the theme (see [`Config`]).
+    #[must_use]
+    pub fn resolve(&self, theme: Option<&str>, role: Option<&CursorType>) -> Self {
+        let theme = theme.and_then(|name| self.themes.get(name));
+        let for_role = |roles: &'_ BTreeMap<String, ScalingConfig>| {
+            roles
+                .iter()
+                .filter(|(name, _)| {
+                    role.is_some_and(|r| CursorType::from_name(name).as_ref() == Some(r))
+                })
+                .map(|(_, o)| o.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let overrides = theme
+            .map(|t| t.scaling.clone())
+            .into_iter()
+            .chain(for_role(&self.roles))
+            .chain(theme.map(|t| for_role(&t.roles)).unwrap_or_default());
+
+        let mut resolved = Self {
+            scale_to: self.scale_to.clone(),
+            upscale_with: self.upscale_with,
+            downscale_with: self.downscale_with,
+            roles: BTreeMap::new(),
+            themes: BTreeMap::new(),
+        };
 
-        for &sf in &scale_to {
-            if sf.is_nan() || sf.is_infinite() {
-                bail!("invalid sf={sf}: can't be NaN or pos/neg infinity")
+        for o in overrides {
+            resolved.scale_to.extend(o.scale_to.unwrap_or_default());
+
+            if let Some(alg) = &o.scale_with {
+                resolved.upscale_with = ResizeAlg::from(alg);
+                resolved.downscale_with = ResizeAlg::from(alg);
             }
 
-            if sf <= 0.1 {
-                bail!("invalid sf={sf}: can't be 0.1 or less");
+            if let Some(alg) = &o.upscale_with {
+                resolved.upscale_with = ResizeAlg::from(alg);
             }
 
-            if sf > 100.0 {
-                bail!("invalid sf={sf}: can't be greater than 100.0")
+            if let Some(alg) = &o.downscale_with {
+                resolved.downscale_with = ResizeAlg::from(alg);
             }
         }
 
-        scale_to.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
-        scale_to.dedup();
+        resolved.scale_to.sort_unstable_by(f64::total_cmp);
+        resolved.scale_to.dedup();
+        resolved
+    }
 
-        let (upscale_with, downscale_with) = (
-            ResizeAlg::from(args.upscale_with.as_ref().unwrap_or(&args.scale_with)),
-            ResizeAlg::from(args.downscale_with.as_ref().unwrap_or(&args.scale_with)),
-        );
+    /// Logs a warning for each theme override (see [`Config::themes`])
+    /// that isn't for any of the themes named `names`.
+    pub fn warn_unmatched<'a>(&self, names: impl IntoIterator<Item = &'a str>) {
+        let names: Vec<_> = names.into_iter().collect();
+
+        for key in self.themes.keys().filter(|k| !names.contains(&k.as_str())) {
+            warn!("no theme named {key:?}, so its overrides in the config are unused");
+        }
+    }
+
+    /// Returns the appropriate algorithm for the `scale_factor`.
+    #[must_use]
+    pub const fn get_algorithm(&self, scale_factor: f64) -> ResizeAlg {
+        if scale_factor > 1.0 {
+            self.upscale_with
+        } else {
+            self.downscale_with
+        }
+    }
+
+    /// Returns `options`, with missing sizes (if scaled, see [`MissingSizes`])
+    /// filled in with the algorithms of this.
+    ///
+    /// Use this on a [`Self::resolve`]d scaling, so that overrides apply to missing sizes too.
+    #[must_use]
+    pub fn decode_options(&self, options: &DecodeOptions) -> DecodeOptions {
+        let missing_sizes = match options.missing_sizes {
+            MissingSizes::Drop => MissingSizes::Drop,
+            MissingSizes::Scale { .. } => MissingSizes::Scale {
+                upscale_with: self.upscale_with,
+                downscale_with: self.downscale_with,
+            },
+        };
+
+        DecodeOptions {
+            missing_sizes,
+            ..options.clone()
+        }
+    }
+
+    /// Adds each scale factor to `cursor`.
+    ///
+    /// ## Errors
+    ///
+    /// From [`GenericCursor::add_scale`].
+    pub fn scale_cursor(&self, cursor: &mut GenericCursor, limits: &Limits) -> Result<()> {
+        for &sf in &self.scale_to {
+            cursor.add_scale(sf, self.get_algorithm(sf), limits)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds each scale factor to `theme`, with overrides for the theme and each role.
+    ///
+    /// ## Errors
+    ///
+    /// From [`CursorTheme::add_scale`].
+    pub fn scale_theme(&self, theme: &mut CursorTheme, limits: &Limits) -> Result<()> {
+        let name = theme.name().to_owned();
+
+        theme.add_scales_by(
+            |r#type| {
+                let scaling = self.resolve(Some(&name), Some(r#type));
+                let scales = scaling.scale_to.iter();
+                scales.map(|&sf| (sf, scaling.get_algorithm(sf))).collect()
+            },
+            limits,
+        )
+    }
+}
+
+/// Checks that each scale factor in `scale_to` is valid.
+///
+/// ## Errors
+///
+/// If any scale factor is non-finite, 0.1 or less, or greater than 100.0.
+fn validate_scale_factors(scale_to: &[f64]) -> Result<()> {
+    for &sf in scale_to {
+        if sf.is_nan() || sf.is_infinite() {
+            bail!("invalid sf={sf}: can't be NaN or pos/neg infinity")
+        }
+
+        if sf <= 0.1 {
+            bail!("invalid sf={sf}: can't be 0.1 or less");
+        }
+
+        if sf > 100.0 {
+            bail!("invalid sf={sf}: can't be greater than 100.0")
+        }
+    }
+
+    Ok(())
+}
+
+/// Parsed CLI arguments for conversions ([`Command::Convert`] and [`Command::Install`]).
+#[derive(Debug)]
+#[expect(clippy::struct_excessive_bools, reason = "these are CLI flags")]
+pub struct ParsedArgs<'a> {
+    /// All installer files.
+    pub installer_files: Vec<PathBuf>,
+    /// All cursor files.
+    pub cursor_files: Vec<PathBuf>,
+    /// Installation is manual. Or not.
+    pub manual: bool,
+    /// Hotspots to apply to cursors, overriding stored ones.
+    pub hotspots: Vec<HotspotOverride>,
+    /// Detect missing or suspicious hotspots in themes.
+    pub detect_hotspots: bool,
+    /// Options for decoding cursors, see [`Self::decode_options_for`] for cursors in themes.
+    pub decode_options: DecodeOptions,
+    /// The decoders to read cursors with.
+    pub registry: &'a Registry,
+    /// Scale factors and algorithms.
+    pub scaling: Scaling,
+    /// The encoder to write cursors and themes with, from [`Self::registry`].
+    pub to: &'a dyn CursorEncoder,
+    /// Where to put parsed Xcursor files.
+    pub out: PathBuf,
+    /// Print what would be written instead of writing.
+    pub dry_run: bool,
+    /// Continue past failed files, summarising them at the end.
+    pub keep_going: bool,
+    /// What to do with existing outputs.
+    pub conflict: Conflict,
+}
 
-        let out = args.out;
-        fs::create_dir_all(&out)?;
+impl<'a> ParsedArgs<'a> {
+    /// Parses `args`, with defaults from `config` and formats from `registry`.
+    ///
+    /// ## Errors
+    ///
+    /// - If any provided paths don't exist or `out` directory can't be made (unless dry-running).
+    /// - If `registry` has no encoder for [`ConvertArgs::to`].
+    /// - If cursor files are to be converted individually to a theme-only format.
+    /// - From [`Scaling::from_args`].
+    pub fn from_args(args: ConvertArgs, config: &Config, registry: &'a Registry) -> Result<Self> {
+        let manual = args.manual;
+        let detect_hotspots = args.hotspots.detect_hotspots;
+        let hotspots = args.hotspots.overrides()?;
+        let scaling = Scaling::from_args(args.scaling, config)?;
+        let decode_options = args.decoding.options(Some(&scaling));
+        let (installer_files, cursor_files) = split_paths(args.paths, registry)?;
+
+        let to = registry.encoder(&args.to).ok_or_else(|| {
+            let names: Vec<_> = registry.encoder_names().collect();
+            anyhow!(
+                "unknown output format={}; expected one of {}",
+                args.to,
+                names.join(", ")
+            )
+        })?;
+
+        if to.theme_only() && !manual && !cursor_files.is_empty() {
+            bail!(
+                "{} output requires a theme; use an installer file or \
+                '--manual' instead of converting cursor files individually",
+                to.name()
+            );
+        }
+
+        let out = args
+            .out
+            .or_else(|| config.out.clone())
+            .unwrap_or_else(|| PathBuf::from("./"));
+
+        if !args.output.dry_run {
+            fs::create_dir_all(&out)?;
+        }
 
         Ok(Self {
             installer_files,
             cursor_files,
             manual,
-            scale_to,
-            upscale_with,
-            downscale_with,
+            hotspots,
+            detect_hotspots,
+            decode_options,
+            registry,
+            scaling,
+            to,
             out,
+            dry_run: args.output.dry_run,
+            keep_going: args.output.keep_going,
+            conflict: args.conflicts.policy(),
         })
     }
 
-    /// Returns the appropriate algorithm for the `scale_factor`.
+    /// Parses `args` with defaults from `config` and formats from `registry`,
+    /// installing to [`InstallArgs::dir`] or "~/.icons".
+    ///
+    /// ## Errors
+    ///
+    /// - If any provided paths aren't installer files.
+    /// - If no directory is provided, and the home directory is unknown.
+    /// - If `registry` has no encoder for Xcursor.
+    /// - From [`Scaling::from_args`].
+    pub fn from_install_args(
+        args: InstallArgs,
+        config: &Config,
+        registry: &'a Registry,
+    ) -> Result<Self> {
+        let detect_hotspots = args.hotspots.detect_hotspots;
+        let hotspots = args.hotspots.overrides()?;
+        let scaling = Scaling::from_args(args.scaling, config)?;
+        let decode_options = args.decoding.options(Some(&scaling));
+        let (installer_files, cursor_files) = split_paths(args.paths, registry)?;
+
+        if let Some(f) = cursor_files.first() {
+            bail!(
+                "can't install cursor_file={}; only theme installers (INF or CRS) can be \
+                installed, see 'currust convert --manual' for themes without one",
+                f.display()
+            );
+        }
+
+        let out = match args.dir {
+            Some(dir) => dir,
+            None => env::home_dir()
+                .ok_or_else(|| anyhow!("home directory is unknown; use '--dir' instead"))?
+                .join(".icons"),
+        };
+
+        if !args.output.dry_run {
+            fs::create_dir_all(&out)?;
+        }
+
+        Ok(Self {
+            installer_files,
+            cursor_files,
+            manual: false,
+            hotspots,
+            detect_hotspots,
+            decode_options,
+            registry,
+            scaling,
+            to: registry
+                .encoder("xcursor")
+                .ok_or_else(|| anyhow!("no encoder for xcursor"))?,
+            out,
+            dry_run: args.output.dry_run,
+            keep_going: args.output.keep_going,
+            conflict: args.conflicts.policy(),
+        })
+    }
+
+    /// Returns the hotspot override for the cursor at `path`, if any.
+    ///
+    /// Overrides for the file name take priority over ones for `r#type`, if
+    /// provided. If there are multiple, the last one provided is used.
     #[must_use]
-    pub const fn get_algorithm(&self, scale_factor: f64) -> ResizeAlg {
-        if scale_factor > 1.0 {
-            self.upscale_with
-        } else {
-            self.downscale_with
+    pub fn get_hotspot(&self, r#type: Option<&CursorType>, path: &Path) -> Option<(u32, u32)> {
+        let file_name = path.file_name()?;
+        let find = |is_match: &dyn Fn(&HotspotTarget) -> bool| {
+            self.hotspots
+                .iter()
+                .rev()
+                .find(|h| is_match(&h.target))
+                .map(|h| h.hotspot)
+        };
+
+        let hotspot =
+            find(&|t| matches!(t, HotspotTarget::File(f) if file_name.eq_ignore_ascii_case(f)));
+
+        if hotspot.is_none()
+            && let Some(role) = self.ambiguous_hotspot(r#type, path)
+        {
+            warn!(
+                "hotspot override for {role:?} doesn't apply to path={}, as its key is read \
+                as a role. prefix the key with \"file:\" to use the file name instead",
+                path.display()
+            );
         }
+
+        hotspot.or_else(|| find(&|t| matches!(t, HotspotTarget::Type(t) if Some(t) == r#type)))
+    }
+
+    /// Returns the role of a hotspot override if the file name of `path` also names
+    /// that role, but the cursor at `path` doesn't have it (i.e., `r#type`).
+    ///
+    /// Keys like these are ambiguous, since they're read as roles, so
+    /// the override doesn't apply to the file, see [`HotspotTarget`].
+    fn ambiguous_hotspot(&self, r#type: Option<&CursorType>, path: &Path) -> Option<&CursorType> {
+        let role = CursorType::from_name(path.file_name()?.to_str()?)?;
+
+        self.hotspots.iter().find_map(|h| match &h.target {
+            HotspotTarget::Type(t) if *t == role && Some(t) != r#type => Some(t),
+            _ => None,
+        })
+    }
+
+    /// Returns the [`DecodeOptions`] for a cursor with `role` in the theme named `theme`,
+    /// filling in missing sizes with its [`Scaling::resolve`]d algorithms.
+    #[must_use]
+    pub fn decode_options_for(&self, theme: &str, role: &CursorType) -> DecodeOptions {
+        self.scaling
+            .resolve(Some(theme), Some(role))
+            .decode_options(&self.decode_options)
+    }
+
+    /// Applies hotspot overrides to each cursor in `theme`, and
+    /// detects hotspots for the rest if [`Self::detect_hotspots`] is set.
+    ///
+    /// ## Errors
+    ///
+    /// If an override is out of bounds for its cursor.
+    pub fn apply_hotspots(&self, theme: &mut CursorTheme) -> Result<()> {
+        for (r#type, path, cursor) in theme.cursors_mut() {
+            if let Some((x, y)) = self.get_hotspot(Some(r#type), path) {
+                cursor
+                    .set_hotspot(x, y)
+                    .with_context(|| format!("for hotspot override of {}", path.display()))?;
+            } else if self.detect_hotspots
+                && let Some((x, y)) = cursor.detect_hotspot(r#type)?
+            {
+                info!(
+                    "detected hotspot=({x}, {y}) for type={type:?}, path={}",
+                    path.display()
+                );
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -253,7 +1229,12 @@ impl ParsedArgs {
 ///
 /// - any path in `cursor_paths` has no filename
 /// - [`Select`] prompt fails (e.g., if user is not in a terminal)
-pub(super) fn prompt_for_theme(cursor_files: &[PathBuf]) -> Result<CursorTheme> {
+/// - any cursor can't be read, with `options` for its theme name and role
+pub(super) fn prompt_for_theme(
+    cursor_files: &[PathBuf],
+    registry: &Registry,
+    options: impl Fn(&str, &CursorType) -> DecodeOptions,
+) -> Result<CursorTheme> {
     let mut mappings = Vec::with_capacity(cursor_files.len());
     let mut cursor_paths_display: Vec<_> = cursor_files
         .iter()
@@ -296,12 +1277,220 @@ pub(super) fn prompt_for_theme(cursor_files: &[PathBuf]) -> Result<CursorTheme>
         }
     };
 
-    let typed_cursors = mappings
-        .into_iter()
-        .map(TypedCursor::from_mapping)
-        .collect::<Result<_>>()?;
+    CursorTheme::from_mappings_by(name, mappings, registry, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `config`, with default scaling arguments.
+    fn scaling(config: &str) -> Scaling {
+        let args = Args::try_parse_from(["currust", "validate", "cursor.ani"]).unwrap();
+        let Command::Validate(args) = args.command else {
+            unreachable!()
+        };
+
+        Scaling::from_args(args.scaling, &toml::from_str(config).unwrap()).unwrap()
+    }
+
+    /// Parses `args` as the arguments of a dry-run conversion of a fixture.
+    fn convert_args<'a>(args: &[&str], registry: &'a Registry) -> ParsedArgs<'a> {
+        const FIXTURE: &str = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/testing/fixtures/neuro/Neuro help.ani"
+        );
+
+        let args = ["currust", "convert", FIXTURE, "--dry-run"]
+            .into_iter()
+            .chain(args.iter().copied());
+        let Command::Convert(args) = Args::try_parse_from(args).unwrap().command else {
+            unreachable!()
+        };
+
+        ParsedArgs::from_args(args, &Config::default(), registry).unwrap()
+    }
+
+    /// Doesn't overwrite existing outputs unless asked to.
+    #[test]
+    fn conflict_policy() {
+        let registry = Registry::with_builtins();
+        let policy = |args: &[&str]| convert_args(args, &registry).conflict;
+
+        assert_eq!(policy(&[]), Conflict::Error);
+        assert_eq!(policy(&["--force"]), Conflict::Overwrite);
+        assert_eq!(policy(&["--skip-existing"]), Conflict::Skip);
+        assert_eq!(policy(&["--suffix"]), Conflict::Suffix);
+    }
+
+    /// Parses hotspot overrides from arguments and hotspot files.
+    #[test]
+    fn hotspot_overrides() {
+        let parse = |s| parse_hotspot_override(s).map(|h| (h.target, h.hotspot));
+        let file = |name: &str| HotspotTarget::File(name.to_string());
+        let hand = HotspotTarget::Type(CursorType::from_name("hand").unwrap());
+
+        assert_eq!(parse("arrow.png=9,2").unwrap(), (file("arrow.png"), (9, 2)));
+        assert_eq!(parse("Hand=9,2").unwrap(), (hand.clone(), (9, 2)));
+        assert_eq!(parse("file:hand=1,2").unwrap(), (file("hand"), (1, 2)));
+        assert!(parse("hand").is_err());
+        assert!(parse("hand=9").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hotspots.txt");
+        fs::write(&path, "# comment\n\n  arrow.png=9,2 \nhand=1,2\n").unwrap();
+
+        let overrides = read_hotspot_file(&path).unwrap();
+        let overrides: Vec<_> = overrides
+            .into_iter()
+            .map(|h| (h.target, h.hotspot))
+            .collect();
+        assert_eq!(overrides, [(file("arrow.png"), (9, 2)), (hand, (1, 2))]);
+
+        fs::write(&path, "arrow.png=9,2\n\nhand=1\n").unwrap();
+        let err = read_hotspot_file(&path).unwrap_err();
+        assert!(format!("{err}").starts_with("on line 3 "), "{err}");
+    }
+
+    /// Reads keys that name a role as the role, even for a file with the same name.
+    #[test]
+    fn ambiguous_hotspots() {
+        let registry = Registry::with_builtins();
+        let hand = CursorType::from_name("hand").unwrap();
+        let text = CursorType::from_name("text").unwrap();
+        let path = Path::new("theme/hand");
+
+        let args = convert_args(&["--hotspot", "hand=9,2"], &registry);
+        assert_eq!(args.get_hotspot(Some(&text), path), None);
+        assert_eq!(args.ambiguous_hotspot(Some(&text), path), Some(&hand));
+        assert_eq!(args.get_hotspot(None, path), None);
+        assert_eq!(args.ambiguous_hotspot(None, path), Some(&hand));
+        assert_eq!(args.get_hotspot(Some(&hand), path), Some((9, 2)));
+        assert_eq!(args.ambiguous_hotspot(Some(&hand), path), None);
+        assert_eq!(args.ambiguous_hotspot(None, Path::new("hand.cur")), None);
+
+        let args = convert_args(&["--hotspot", "hand=9,2", "file:hand=1,2"], &registry);
+        assert_eq!(args.get_hotspot(Some(&text), path), Some((1, 2)));
+        assert_eq!(
+            args.get_hotspot(Some(&hand), Path::new("link.cur")),
+            Some((9, 2))
+        );
+    }
 
-    let theme = CursorTheme::new(typed_cursors, name)?;
+    /// Expands a directory to the images it contains, which are read as cursors.
+    #[test]
+    fn image_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = DecodeOptions::default();
 
-    Ok(theme)
+        for (name, size) in [("arrow.png", 32), ("hand.PNG", 48)] {
+            let image = image::RgbaImage::from_pixel(size, size, image::Rgba([0, 0, 0, 255]));
+            image
+                .save_with_format(dir.path().join(name), image::ImageFormat::Png)
+                .unwrap();
+        }
+
+        fs::write(dir.path().join("hand.PNG.hotspot"), "9,2").unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a cursor").unwrap();
+
+        let (installer_files, mut cursor_files) =
+            split_paths(vec![dir.path().to_path_buf()], Registry::builtin()).unwrap();
+        cursor_files.sort();
+
+        assert!(installer_files.is_empty());
+        assert_eq!(
+            cursor_files,
+            [dir.path().join("arrow.png"), dir.path().join("hand.PNG")]
+        );
+
+        let expected = [((32, 32), (0, 0)), ((48, 48), (9, 2))];
+
+        for (path, (dimensions, hotspot)) in cursor_files.iter().zip(expected) {
+            let cursor = GenericCursor::from_path(path, &options).unwrap();
+            let image = cursor.base_images().first();
+
+            assert_eq!(image.dimensions(), dimensions);
+            assert_eq!(image.hotspot(), hotspot);
+        }
+    }
+
+    /// Resolves overrides for the theme, then the role, then the role in the theme.
+    #[test]
+    fn resolve_order() {
+        let scaling = scaling(
+            "scale_to = [1.5]\nscale_with = \"box\"\n\
+            [roles.arrow]\nscale_to = [2]\nscale_with = \"mitchell\"\n\
+            [themes.\"My.Theme\"]\nscale_to = [3]\nscale_with = \"bilinear\"\n\
+            [themes.\"My.Theme\".roles.arrow]\nscale_to = [4]\nupscale_with = \"lanczos3\"\n",
+        );
+        let alg = |alg| ResizeAlg::from(&alg);
+        let arrow = CursorType::from_name("arrow").unwrap();
+        let text = CursorType::from_name("text").unwrap();
+
+        let resolved = scaling.resolve(Some("My.Theme"), Some(&arrow));
+        assert_eq!(resolved.scale_to, [1.5, 2.0, 3.0, 4.0]);
+        assert_eq!(resolved.upscale_with, alg(ScalingAlgorithm::Lanczos3));
+        assert_eq!(resolved.downscale_with, alg(ScalingAlgorithm::Mitchell));
+
+        let resolved = scaling.resolve(Some("My.Theme"), Some(&text));
+        assert_eq!(resolved.scale_to, [1.5, 3.0]);
+        assert_eq!(resolved.upscale_with, alg(ScalingAlgorithm::Bilinear));
+        assert_eq!(resolved.downscale_with, alg(ScalingAlgorithm::Bilinear));
+
+        // keyed by the name in the installer, not the sanitized one
+        let resolved = scaling.resolve(Some("My_Theme"), Some(&arrow));
+        assert_eq!(resolved.scale_to, [1.5, 2.0]);
+        assert_eq!(resolved.upscale_with, alg(ScalingAlgorithm::Mitchell));
+
+        let resolved = scaling.resolve(None, None);
+        assert_eq!(resolved.scale_to, [1.5]);
+        assert_eq!(resolved.upscale_with, alg(ScalingAlgorithm::Box));
+    }
+
+    /// Fills in missing sizes with the algorithms resolved for each cursor.
+    #[test]
+    fn missing_sizes_by_role() {
+        const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testing/fixtures/neuro");
+
+        let registry = Registry::with_builtins();
+        let config =
+            toml::from_str("scale_with = \"nearest\"\n[roles.text]\nscale_with = \"mitchell\"\n")
+                .unwrap();
+        let parse = |args: &[&str]| {
+            let args = ["currust", "convert", FIXTURE, "--dry-run"]
+                .into_iter()
+                .chain(args.iter().copied());
+            let Command::Convert(args) = Args::try_parse_from(args).unwrap().command else {
+                unreachable!()
+            };
+
+            ParsedArgs::from_args(args, &config, &registry).unwrap()
+        };
+        let scaled_with = |options: DecodeOptions| match options.missing_sizes {
+            MissingSizes::Scale {
+                upscale_with,
+                downscale_with,
+            } => Some((upscale_with, downscale_with)),
+            MissingSizes::Drop => None,
+        };
+        let alg = |alg| ResizeAlg::from(&alg);
+        let text = CursorType::from_name("text").unwrap();
+        let arrow = CursorType::from_name("arrow").unwrap();
+
+        let args = parse(&["--missing-sizes", "scale"]);
+        let mitchell = alg(ScalingAlgorithm::Mitchell);
+        let nearest = alg(ScalingAlgorithm::Nearest);
+        assert_eq!(
+            scaled_with(args.decode_options_for("Theme", &text)),
+            Some((mitchell, mitchell))
+        );
+        assert_eq!(
+            scaled_with(args.decode_options_for("Theme", &arrow)),
+            Some((nearest, nearest))
+        );
+        assert_eq!(scaled_with(args.decode_options), Some((nearest, nearest)));
+
+        let args = parse(&[]);
+        assert_eq!(scaled_with(args.decode_options_for("Theme", &text)), None);
+    }
 }
@@ -0,0 +1,1142 @@
+//! Module for running each [`Command`], see [`run`].
+//!
+//! Conversions share [`ParsedArgs`], while the other commands only read cursors.
+
+use crate::{
+    cli::{Args, Command, Conflict, ParsedArgs, Scaling, prompt_for_theme, split_paths},
+    config::Config,
+    cursors::{cursor_image::CursorImage, decode::DecodeOptions, generic_cursor::GenericCursor},
+    formats::registry::Registry,
+    inspect, logger, logging,
+    themes::theme::{CursorMapping, CursorTheme, CursorType, parse_installer, sanitize_name},
+};
+
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::Parser;
+use image::{ExtendedColorType, ImageFormat};
+use log::{Level, error, info, log_enabled};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use thiserror::Error;
+
+/// Exit code for when [`convert`] partially succeeds with [`ParsedArgs::keep_going`].
+pub const PARTIAL_FAILURE_CODE: u8 = 3;
+
+/// Error for when some, but not all, files fail to convert.
+#[derive(Debug, Error)]
+#[error("{failed} of {total} file(s) failed to convert")]
+pub struct PartialFailure {
+    /// Number of files that failed.
+    pub failed: usize,
+    /// Number of files attempted.
+    pub total: usize,
+}
+
+/// Runs the CLI with the arguments of the process, reading and writing cursors with the
+/// formats in `registry`. This is what the `currust` binary runs, with
+/// [`Registry::with_builtins`], so a CLI with more formats only needs to register them.
+///
+/// Failures are logged (or printed, if logging is off), and returned as the exit code ([`PARTIAL_FAILURE_CODE`] if
+/// some, but not all, files fail to convert).
+///
+/// ## Errors
+///
+/// If the logger can't be set up.
+pub fn run(registry: &Registry) -> Result<ExitCode> {
+    let args = Args::parse();
+    let level = logger::level(args.logging.verbose, args.logging.quiet);
+    logger::init(level, args.logging.log_format)?;
+
+    let Err(err) = run_command(args.command, args.config.as_deref(), registry) else {
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    if let Some(partial) = err.downcast_ref::<PartialFailure>() {
+        report_failure(partial);
+        return Ok(ExitCode::from(PARTIAL_FAILURE_CODE));
+    }
+
+    report_failure(&format_args!("{err:#}"));
+    Ok(ExitCode::FAILURE)
+}
+
+/// Helper function for [`run`], logging `failure` as an error, or printing it
+/// if errors aren't logged (e.g., with `-qqq`), so that it's never silent.
+fn report_failure(failure: &dyn Display) {
+    if log_enabled!(Level::Error) {
+        error!("{failure}");
+    } else {
+        eprintln!("{failure}");
+    }
+}
+
+/// Runs `command`, with defaults from the config at `config_path` (or "currust.toml" files).
+///
+/// The config is only loaded for commands that use it, so that a broken
+/// config doesn't stop cursors from being inspected or previewed.
+fn run_command(command: Command, config_path: Option<&Path>, registry: &Registry) -> Result<()> {
+    let config = || Config::load(config_path);
+
+    match command {
+        Command::Convert(args) => convert(&ParsedArgs::from_args(args, &config()?, registry)?),
+        Command::Install(args) => {
+            let args = ParsedArgs::from_install_args(args, &config()?, registry)?;
+            convert(&args)?;
+
+            if !args.dry_run {
+                println!("installed theme(s) in dir={}", args.out.display());
+            }
+
+            Ok(())
+        }
+        Command::Inspect(args) => inspect::inspect(
+            args.paths,
+            args.json,
+            registry,
+            &args.decoding.options(None),
+        ),
+        Command::Extract(args) => {
+            let scaling = Scaling::from_args(args.scaling, &config()?)?;
+            let options = args.decoding.options(Some(&scaling));
+            extract(args.paths, &args.out, &scaling, registry, &options)
+        }
+        Command::Preview(args) => preview(
+            args.paths,
+            args.all_frames,
+            registry,
+            &args.decoding.options(None),
+        ),
+        Command::Validate(args) => {
+            let scaling = Scaling::from_args(args.scaling, &config()?)?;
+            let options = args.decoding.options(Some(&scaling));
+            validate(args.paths, &scaling, registry, &options)
+        }
+    }
+}
+
+/// A theme or cursor to convert, named (see [`resolve_inputs`]) before it's read.
+#[derive(Debug)]
+enum Input {
+    /// A theme installer, with the theme's name and cursors.
+    Installer(PathBuf, String, Vec<CursorMapping>),
+    /// A theme made with [`prompt_for_theme`], which reads its cursors.
+    Manual(CursorTheme),
+    /// A cursor file, with the file name it's written as.
+    Cursor(PathBuf, OsString),
+}
+
+impl Input {
+    /// Returns the name this is written as.
+    fn name(&self) -> OsString {
+        match self {
+            Self::Installer(_, name, _) => sanitize_name(name).into(),
+            Self::Manual(theme) => theme.sanitized_name().into(),
+            Self::Cursor(_, name) => name.clone(),
+        }
+    }
+
+    /// Returns the name of the theme this is, as in its installer (see [`CursorTheme::name`]).
+    fn theme_name(&self) -> Option<&str> {
+        match self {
+            Self::Installer(_, name, _) => Some(name),
+            Self::Manual(theme) => Some(theme.name()),
+            Self::Cursor(..) => None,
+        }
+    }
+
+    /// Returns the path this is written to as `name`, see [`Self::name`].
+    fn path(&self, args: &ParsedArgs, name: &OsStr) -> PathBuf {
+        match self {
+            Self::Installer(..) | Self::Manual(_) => {
+                args.to.theme_path(&name.to_string_lossy(), &args.out)
+            }
+            Self::Cursor(..) => args.out.join(name),
+        }
+    }
+
+    /// Reads the cursors of this, applying any hotspot overrides in `args`.
+    ///
+    /// ## Errors
+    ///
+    /// If any cursor can't be read, or an override can't be applied.
+    fn read(self, args: &ParsedArgs) -> Result<Output> {
+        match self {
+            Self::Installer(path, name, mappings) => {
+                let mut theme = logging::Context::default().with_file(&path).scope(|| {
+                    CursorTheme::from_mappings_by(name, mappings, args.registry, |theme, role| {
+                        args.decode_options_for(theme, role)
+                    })
+                    .with_context(|| format!("while reading dir={} as theme", path.display()))
+                })?;
+
+                args.apply_hotspots(&mut theme)?;
+                Ok(Output::Theme(theme))
+            }
+            Self::Manual(mut theme) => {
+                args.apply_hotspots(&mut theme)?;
+                Ok(Output::Theme(theme))
+            }
+            Self::Cursor(f, _) => logging::Context::default().with_file(&f).scope(|| {
+                let mut cursor = args
+                    .registry
+                    .decode(&f, &args.decode_options)
+                    .with_context(|| format!("while reading f={} as cursor", f.display()))?;
+
+                if let Some((x, y)) = args.get_hotspot(None, &f) {
+                    cursor.set_hotspot(x, y)?;
+                }
+
+                Ok(Output::Cursor(cursor))
+            }),
+        }
+    }
+}
+
+/// A theme or cursor read by [`Input::read`], before it's written.
+enum Output {
+    Theme(CursorTheme),
+    Cursor(GenericCursor),
+}
+
+/// Converts every theme and cursor file in `args`, or prints a plan if dry-running.
+///
+/// Everything is named first, without reading any cursors, so that where each output is
+/// written can be decided (see [`ParsedArgs::conflict`]) up front. Each output is then read,
+/// scaled and written in turn, so only a few are held in memory at once.
+///
+/// ## Errors
+///
+/// If reading, scaling or writing any theme or cursor fails, or if outputs conflict.
+/// If keeping going, this is instead [`PartialFailure`] if some (but not all) files fail.
+pub fn convert(args: &ParsedArgs) -> Result<()> {
+    let total = args.installer_files.len()
+        + if args.manual {
+            1
+        } else {
+            args.cursor_files.len()
+        };
+
+    let mut failures = Vec::new();
+    let inputs = keep_ok(args, resolve_inputs(args), &mut failures)?;
+    args.scaling
+        .warn_unmatched(inputs.iter().filter_map(|(_, input)| input.theme_name()));
+    let inputs = resolve_conflicts(args, inputs)?;
+
+    let results: Vec<_> = if args.dry_run {
+        let mut stdout = io::stdout().lock();
+        inputs
+            .into_iter()
+            .map(|(source, input, path)| {
+                let planned = input
+                    .read(args)
+                    .and_then(|output| plan(args, &source, output, &path, &mut stdout));
+                (source, planned)
+            })
+            .collect()
+    } else {
+        inputs
+            .into_par_iter()
+            .map(|(source, input, path)| {
+                let written = input
+                    .read(args)
+                    .and_then(|output| write_output(args, output, &path));
+                (source, written)
+            })
+            .collect()
+    };
+
+    keep_ok(args, results, &mut failures)?;
+    report_failures(&failures, total)
+}
+
+/// Helper function for [`convert`], naming (or prompting for) each theme and cursor.
+///
+/// Installers are only parsed here, and cursor files aren't read at all.
+fn resolve_inputs(args: &ParsedArgs) -> Vec<(String, Result<Input>)> {
+    let mut inputs: Vec<_> = args
+        .installer_files
+        .par_iter()
+        .map(|d| {
+            let input = logging::Context::default().with_file(d).scope(|| {
+                let (name, mappings) = parse_installer(d)
+                    .with_context(|| format!("while reading dir={} as theme", d.display()))?;
+                Ok(Input::Installer(d.clone(), name, mappings))
+            });
+
+            (d.display().to_string(), input)
+        })
+        .collect();
+
+    if args.manual {
+        let theme = prompt_for_theme(&args.cursor_files, args.registry, |theme, role| {
+            args.decode_options_for(theme, role)
+        });
+        inputs.push((String::from("manual"), theme.map(Input::Manual)));
+    } else {
+        inputs.extend(args.cursor_files.iter().map(|f| {
+            let input = file_stem(f).map(|name| Input::Cursor(f.clone(), name.to_owned()));
+            (f.display().to_string(), input)
+        }));
+    }
+
+    inputs
+}
+
+/// Helper function for [`convert`], deciding where each input is written
+/// following [`ParsedArgs::conflict`]. Skipped inputs are removed.
+///
+/// Suffixes (e.g., "theme-2") only change the path, not the name of the theme.
+///
+/// ## Errors
+///
+/// If inputs would be written to the same path, or an output already
+/// exists, unless this is allowed by [`ParsedArgs::conflict`].
+fn resolve_conflicts(
+    args: &ParsedArgs,
+    inputs: Vec<(String, Input)>,
+) -> Result<Vec<(String, Input, PathBuf)>> {
+    let mut claimed: HashMap<PathBuf, String> = HashMap::new();
+    let mut resolved = Vec::with_capacity(inputs.len());
+
+    'inputs: for (source, input) in inputs {
+        let base = input.name();
+        let mut path = input.path(args, &base);
+        let mut n = 1;
+
+        loop {
+            // doesn't follow symlinks, so broken ones are found too
+            let exists = path.symlink_metadata().is_ok();
+
+            match (claimed.get(&path), args.conflict) {
+                (None, _) if !exists => break,
+                (_, Conflict::Suffix) => {
+                    n += 1;
+                    let mut name = base.clone();
+                    name.push(format!("-{n}"));
+                    path = input.path(args, &name);
+                }
+                (Some(other), _) => bail!(
+                    "{other} and {source} would both be written to path={}; \
+                    pass '--suffix' to number them instead",
+                    path.display()
+                ),
+                (None, Conflict::Overwrite) => break,
+                (None, Conflict::Skip) => {
+                    info!("skipping {source}, as path={} exists", path.display());
+                    continue 'inputs;
+                }
+                (None, Conflict::Error) => bail!(
+                    "path={} already exists (from {source}); pass '--force' \
+                    to overwrite it, or '--skip-existing' or '--suffix'",
+                    path.display()
+                ),
+            }
+        }
+
+        claimed.insert(path.clone(), source.clone());
+        resolved.push((source, input, path));
+    }
+
+    Ok(resolved)
+}
+
+/// Helper function for [`convert`], scaling and writing `output` at `path`.
+fn write_output(args: &ParsedArgs, output: Output, path: &Path) -> Result<()> {
+    let limits = &args.decode_options.limits;
+
+    match output {
+        Output::Theme(mut theme) => {
+            args.scaling.scale_theme(&mut theme, limits)?;
+            args.to.encode_theme(&theme, path)
+        }
+        Output::Cursor(mut cursor) => logging::Context::default().with_file(path).scope(|| {
+            args.scaling.scale_cursor(&mut cursor, limits)?;
+            args.to.encode_cursor(&cursor, path)
+        }),
+    }
+}
+
+/// Helper function for [`convert`], returning the successful `results`.
+///
+/// If keeping going, failures are added to `failures`.
+///
+/// ## Errors
+///
+/// The first failure, if not keeping going.
+fn keep_ok<T>(
+    args: &ParsedArgs,
+    results: Vec<(String, Result<T>)>,
+    failures: &mut Vec<(String, anyhow::Error)>,
+) -> Result<Vec<(String, T)>> {
+    let mut ok = Vec::with_capacity(results.len());
+
+    for (source, result) in results {
+        match result {
+            Ok(value) => ok.push((source, value)),
+            Err(err) if args.keep_going => failures.push((source, err)),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(ok)
+}
+
+/// Helper function for [`convert`], printing a table of `failures` out of `total` files.
+///
+/// ## Errors
+///
+/// If there are any failures, or the table can't be written.
+fn report_failures(failures: &[(String, anyhow::Error)], total: usize) -> Result<()> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    let width = failures
+        .iter()
+        .map(|(source, _)| source.len())
+        .max()
+        .unwrap_or(0);
+    let mut stderr = io::stderr().lock();
+    writeln!(stderr, "{:width$}  ERROR", "FILE")?;
+
+    for (source, err) in failures {
+        writeln!(stderr, "{source:width$}  {err:#}")?;
+    }
+
+    let failed = failures.len();
+    if failed == total {
+        bail!("all {total} file(s) failed to convert");
+    }
+
+    Err(PartialFailure { failed, total }.into())
+}
+
+/// Prints to `w` what [`convert`] would write for `output` (from `source`) at `path`,
+/// without writing anything.
+///
+/// ## Errors
+///
+/// If writing to `w` fails.
+fn plan(
+    args: &ParsedArgs,
+    source: &str,
+    output: Output,
+    path: &Path,
+    w: &mut impl Write,
+) -> Result<()> {
+    let mut theme = match output {
+        Output::Theme(theme) => theme,
+        Output::Cursor(cursor) => {
+            writeln!(w, "cursor from {source}")?;
+            writeln!(w, "  sizes: {}", planned_sizes(&cursor, &args.scaling))?;
+            writeln!(w, "  writes: {}", path.display())?;
+            return Ok(());
+        }
+    };
+
+    let files = args.to.theme_files(&theme, path);
+
+    writeln!(w, "theme from {source}")?;
+    writeln!(w, "  writes: {}", path.display())?;
+
+    for file in files.iter().filter(|f| f.cursor_type.is_none()) {
+        if file.path != path {
+            writeln!(w, "  writes: {}", file.path.display())?;
+        }
+    }
+
+    let name = theme.name().to_owned();
+    for (r#type, cursor_path, cursor) in theme.cursors_mut() {
+        let scaling = args.scaling.resolve(Some(&name), Some(r#type));
+
+        writeln!(w, "  {type:?}")?;
+        writeln!(w, "    source: {}", cursor_path.display())?;
+        writeln!(w, "    sizes: {}", planned_sizes(cursor, &scaling))?;
+
+        for file in files
+            .iter()
+            .filter(|f| f.cursor_type.as_ref() == Some(r#type))
+        {
+            writeln!(w, "    writes: {}", file.path.display())?;
+
+            if !file.aliases.is_empty() {
+                let aliases: Vec<_> = file
+                    .aliases
+                    .iter()
+                    .filter_map(|a| a.file_name())
+                    .map(OsStr::to_string_lossy)
+                    .collect();
+
+                writeln!(w, "    aliases: {}", aliases.join(", "))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Helper function for [`plan`], listing the sizes `cursor` would have after `scaling`.
+fn planned_sizes(cursor: &GenericCursor, scaling: &Scaling) -> String {
+    let base = cursor.base_images().first().dimensions();
+    let stored = cursor.scaled_images().map(|i| i.first().dimensions());
+    let added = scaling
+        .scale_to
+        .iter()
+        .map(|&sf| CursorImage::scale_point(base, sf));
+
+    let mut sizes: Vec<_> = std::iter::once(base).chain(stored).chain(added).collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    let sizes: Vec<_> = sizes.iter().map(|(w, h)| format!("{w}x{h}")).collect();
+    sizes.join(", ")
+}
+
+/// Writes every image of each cursor in `paths` (read with `registry`) as
+/// PNG in `out`, with a ".hotspot" sidecar file next to each.
+///
+/// ## Errors
+///
+/// If reading, scaling or writing any cursor fails.
+pub fn extract(
+    paths: Vec<PathBuf>,
+    out: &Path,
+    scaling: &Scaling,
+    registry: &Registry,
+    options: &DecodeOptions,
+) -> Result<()> {
+    fs::create_dir_all(out)?;
+
+    for_each_cursor(
+        paths,
+        registry,
+        |theme, role| scaling.resolve(theme, role).decode_options(options),
+        |path, theme, r#type, cursor| {
+            scaling
+                .resolve(theme, r#type)
+                .scale_cursor(cursor, &options.limits)?;
+            let stem = file_stem(path)?.display().to_string();
+
+            for images in std::iter::once(cursor.base_images()).chain(cursor.scaled_images()) {
+                for (i, image) in images.inner().iter().enumerate() {
+                    let (width, height) = image.dimensions();
+                    let image_path = out.join(format!("{stem}-{width}x{height}-{i}.png"));
+                    write_png(image, &image_path)?;
+                    println!("{}", image_path.display());
+                }
+            }
+
+            Ok(())
+        },
+    )
+}
+
+/// Prints each cursor in `paths` (read with `registry`) to the terminal, using colored half blocks.
+///
+/// ## Errors
+///
+/// If any cursor can't be read.
+pub fn preview(
+    paths: Vec<PathBuf>,
+    all_frames: bool,
+    registry: &Registry,
+    options: &DecodeOptions,
+) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    for_each_cursor(
+        paths,
+        registry,
+        |_, _| options.clone(),
+        |path, _, r#type, cursor| {
+            let frames = cursor.base_images().inner();
+            let first = cursor.base_images().first();
+            let ((width, height), (x, y)) = (first.dimensions(), first.hotspot());
+
+            match r#type {
+                Some(r#type) => write!(stdout, "{} ({type:?})", path.display())?,
+                None => write!(stdout, "{}", path.display())?,
+            }
+
+            writeln!(
+                stdout,
+                ": {width}x{height}, hotspot=({x}, {y}), frames={}",
+                frames.len()
+            )?;
+
+            let shown = if all_frames { frames.len() } else { 1 };
+            for image in &frames[..shown] {
+                write_blocks(&mut stdout, image)?;
+            }
+
+            Ok(())
+        },
+    )
+}
+
+/// Reads (with `registry`), scales and encodes each theme and cursor in `paths`,
+/// without writing anything.
+///
+/// Each path is reported as valid or invalid as it's checked.
+///
+/// ## Errors
+///
+/// If any path is invalid.
+pub fn validate(
+    paths: Vec<PathBuf>,
+    scaling: &Scaling,
+    registry: &Registry,
+    options: &DecodeOptions,
+) -> Result<()> {
+    let (installer_files, cursor_files) = split_paths(paths, registry)?;
+    let total = installer_files.len() + cursor_files.len();
+    let mut failed = 0;
+    let mut names = Vec::new();
+
+    let check_cursor = |cursor: &mut GenericCursor, scaling: &Scaling| -> Result<()> {
+        scaling.scale_cursor(cursor, &options.limits)?;
+        cursor.write_xcursor(io::sink())
+    };
+
+    for installer_file in &installer_files {
+        let context = logging::Context::default().with_file(installer_file);
+        let result = context.scope(|| {
+            let (name, mappings) = parse_installer(installer_file)?;
+            let mut theme =
+                CursorTheme::from_mappings_by(name, mappings, registry, |theme, role| {
+                    scaling
+                        .resolve(Some(theme), Some(role))
+                        .decode_options(options)
+                })?;
+            let name = theme.name().to_owned();
+            names.push(name.clone());
+
+            theme.cursors_mut().try_for_each(|(r#type, path, cursor)| {
+                check_cursor(cursor, &scaling.resolve(Some(&name), Some(r#type)))
+                    .with_context(|| format!("for type={type:?}, path={}", path.display()))
+            })
+        });
+
+        failed += usize::from(report(installer_file, &result));
+    }
+
+    for cursor_file in &cursor_files {
+        let context = logging::Context::default().with_file(cursor_file);
+        let result = context.scope(|| {
+            registry
+                .decode(cursor_file, options)
+                .and_then(|mut c| check_cursor(&mut c, scaling))
+        });
+
+        failed += usize::from(report(cursor_file, &result));
+    }
+
+    scaling.warn_unmatched(names.iter().map(String::as_str));
+
+    if failed > 0 {
+        bail!("{failed} of {total} path(s) are invalid");
+    }
+
+    Ok(())
+}
+
+/// Helper function for [`validate`], printing `result` for `path`.
+///
+/// Returns true if `result` is an error.
+fn report(path: &Path, result: &Result<()>) -> bool {
+    match result {
+        Ok(()) => {
+            println!("ok: {}", path.display());
+            false
+        }
+        Err(err) => {
+            println!("invalid: {}: {err:#}", path.display());
+            true
+        }
+    }
+}
+
+/// Reads each theme and cursor in `paths` with `registry`, calling `f` with the path, theme
+/// name and type (if from a theme) and each cursor. Themes are expanded to their cursors.
+///
+/// Each cursor is decoded with the [`DecodeOptions`] that `options` returns for its theme
+/// name and type.
+///
+/// ## Errors
+///
+/// If any path can't be read, or from `f`.
+fn for_each_cursor(
+    paths: Vec<PathBuf>,
+    registry: &Registry,
+    options: impl Fn(Option<&str>, Option<&CursorType>) -> DecodeOptions,
+    mut f: impl FnMut(&Path, Option<&str>, Option<&CursorType>, &mut GenericCursor) -> Result<()>,
+) -> Result<()> {
+    let (installer_files, cursor_files) = split_paths(paths, registry)?;
+
+    for installer_file in &installer_files {
+        let context = logging::Context::default().with_file(installer_file);
+        let mut theme = context
+            .scope(|| {
+                let (name, mappings) = parse_installer(installer_file)?;
+                CursorTheme::from_mappings_by(name, mappings, registry, |theme, role| {
+                    options(Some(theme), Some(role))
+                })
+            })
+            .with_context(|| format!("while reading dir={} as theme", installer_file.display()))?;
+
+        let name = theme.name().to_owned();
+        for (r#type, path, cursor) in theme.cursors_mut() {
+            logging::Context::default()
+                .with_file(path)
+                .scope(|| f(path, Some(&name), Some(r#type), cursor))?;
+        }
+    }
+
+    for cursor_file in &cursor_files {
+        logging::Context::default()
+            .with_file(cursor_file)
+            .scope(|| {
+                let mut cursor = registry
+                    .decode(cursor_file, &options(None, None))
+                    .with_context(|| {
+                        format!("while reading f={} as cursor", cursor_file.display())
+                    })?;
+
+                f(cursor_file, None, None, &mut cursor)
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Returns the file stem of `path`.
+///
+/// ## Errors
+///
+/// If `path` has no file name.
+fn file_stem(path: &Path) -> Result<&OsStr> {
+    path.file_stem()
+        .ok_or_else(|| anyhow!("no file stem for cursor_file={}", path.display()))
+}
+
+/// Writes `image` as PNG to `path`, and its hotspot to a sidecar file.
+///
+/// ## Errors
+///
+/// If encoding or writing fails.
+fn write_png(image: &CursorImage, path: &Path) -> Result<()> {
+    let (width, height) = image.dimensions();
+    let (x, y) = image.hotspot();
+
+    image::save_buffer_with_format(
+        path,
+        image.rgba(),
+        width,
+        height,
+        ExtendedColorType::Rgba8,
+        ImageFormat::Png,
+    )
+    .with_context(|| format!("failed to write image={}", path.display()))?;
+
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".hotspot");
+    fs::write(&sidecar, format!("{x},{y}\n"))
+        .with_context(|| format!("failed to write hotspot for image={}", path.display()))
+}
+
+/// Writes `image` to `writer` with ANSI truecolor escapes, two pixels per character.
+///
+/// Pixels that are mostly transparent are left blank.
+fn write_blocks(writer: &mut impl Write, image: &CursorImage) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    let rgba = image.rgba();
+
+    let pixel = |x: usize, y: usize| {
+        (y < height)
+            .then(|| &rgba[(y * width + x) * 4..][..4])
+            .filter(|p| p[3] >= 128)
+    };
+
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            match (pixel(x, y), pixel(x, y + 1)) {
+                (None, None) => write!(writer, " ")?,
+                (Some(t), None) => write!(writer, "\x1b[38;2;{};{};{}m▀\x1b[0m", t[0], t[1], t[2])?,
+                (None, Some(b)) => write!(writer, "\x1b[38;2;{};{};{}m▄\x1b[0m", b[0], b[1], b[2])?,
+                (Some(t), Some(b)) => write!(
+                    writer,
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m▀\x1b[0m",
+                    t[0], t[1], t[2], b[0], b[1], b[2]
+                )?,
+            }
+        }
+
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::{Args, Command},
+        config::Config,
+    };
+
+    use clap::Parser;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/testing/fixtures/neuro/Neuro help.ani"
+    );
+
+    const INSTALLER: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/testing/fixtures/neuro/Install.inf"
+    );
+
+    /// Parses `args` as the arguments of a command.
+    fn parse(args: &[&str]) -> Command {
+        Args::try_parse_from(std::iter::once("currust").chain(args.iter().copied()))
+            .unwrap()
+            .command
+    }
+
+    /// Extracts a cursor, checking the name and contents of each image and sidecar.
+    #[test]
+    fn extract_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().to_str().unwrap();
+        let registry = Registry::with_builtins();
+
+        let Command::Extract(args) = parse(&["extract", FIXTURE, "--out", out, "--scale-to", "2"])
+        else {
+            unreachable!()
+        };
+
+        let scaling = Scaling::from_args(args.scaling, &Config::default()).unwrap();
+        let options = args.decoding.options(Some(&scaling));
+        extract(args.paths, &args.out, &scaling, &registry, &options).unwrap();
+
+        let mut cursor = registry.decode(Path::new(FIXTURE), &options).unwrap();
+        scaling.scale_cursor(&mut cursor, &options.limits).unwrap();
+
+        let mut expected = Vec::new();
+        for images in std::iter::once(cursor.base_images()).chain(cursor.scaled_images()) {
+            for (i, image) in images.inner().iter().enumerate() {
+                let (width, height) = image.dimensions();
+                let path = dir
+                    .path()
+                    .join(format!("Neuro help-{width}x{height}-{i}.png"));
+
+                let png = image::open(&path).unwrap().into_rgba8();
+                assert_eq!(png.dimensions(), (width, height));
+                assert_eq!(png.as_raw(), image.rgba());
+
+                let (x, y) = image.hotspot();
+                let mut sidecar = path.clone().into_os_string();
+                sidecar.push(".hotspot");
+                assert_eq!(fs::read_to_string(&sidecar).unwrap(), format!("{x},{y}\n"));
+
+                expected.extend([path, PathBuf::from(sidecar)]);
+            }
+        }
+
+        let mut written: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        written.sort();
+        expected.sort();
+
+        assert!(cursor.scaled_images().count() > 0);
+        assert_eq!(written, expected);
+    }
+
+    /// Extracts a theme, applying the scaling overrides for its name.
+    #[test]
+    fn extract_theme_overrides() {
+        let registry = Registry::with_builtins();
+        let count_written = |config: &str| {
+            let dir = tempfile::tempdir().unwrap();
+            let out = dir.path().to_str().unwrap();
+            let Command::Extract(args) = parse(&["extract", INSTALLER, "--out", out]) else {
+                unreachable!()
+            };
+
+            let config: Config = toml::from_str(config).unwrap();
+            let scaling = Scaling::from_args(args.scaling, &config).unwrap();
+            let options = args.decoding.options(Some(&scaling));
+            extract(args.paths, &args.out, &scaling, &registry, &options).unwrap();
+            fs::read_dir(dir.path()).unwrap().count()
+        };
+
+        let base = count_written("");
+        assert!(base > 0);
+        assert_eq!(count_written("[themes.\"Other\"]\nscale_to = [2]\n"), base);
+        assert_eq!(
+            count_written("[themes.\"Neuro-sama Cursor\"]\nscale_to = [2]\n"),
+            base * 2
+        );
+    }
+
+    /// Validates a good and a bad cursor, where only the bad one should be invalid.
+    #[test]
+    fn validate_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Registry::with_builtins();
+        let Command::Validate(args) = parse(&["validate", FIXTURE]) else {
+            unreachable!()
+        };
+
+        let scaling = Scaling::from_args(args.scaling, &Config::default()).unwrap();
+        let options = DecodeOptions::default();
+
+        // a CUR signature, but nothing after it
+        let bad = dir.path().join("bad.cur");
+        fs::write(&bad, [0, 0, 2, 0, 1, 0]).unwrap();
+
+        validate(args.paths, &scaling, &registry, &options).unwrap();
+
+        let err = validate(
+            vec![PathBuf::from(FIXTURE), bad],
+            &scaling,
+            &registry,
+            &options,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 path(s) are invalid");
+    }
+
+    /// Plans converting a theme to each format, checking the paths it would write.
+    #[test]
+    fn plan_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().to_str().unwrap();
+        let registry = Registry::with_builtins();
+
+        for to in ["xcursor", "cape"] {
+            let Command::Convert(args) =
+                parse(&["convert", INSTALLER, "--out", out, "--to", to, "--dry-run"])
+            else {
+                unreachable!()
+            };
+
+            let args = ParsedArgs::from_args(args, &Config::default(), &registry).unwrap();
+            let inputs = keep_ok(&args, resolve_inputs(&args), &mut Vec::new()).unwrap();
+            let inputs = resolve_conflicts(&args, inputs).unwrap();
+
+            let mut planned = Vec::new();
+            for (source, input, path) in inputs {
+                let output = input.read(&args).unwrap();
+                plan(&args, &source, output, &path, &mut planned).unwrap();
+            }
+            let planned = String::from_utf8(planned).unwrap();
+
+            let name = "Neuro-sama Cursor";
+            let writes = |path: PathBuf| format!("writes: {}\n", path.display());
+
+            if to == "xcursor" {
+                let path = dir.path().join(name);
+                assert!(planned.contains(&writes(path.clone())));
+                assert!(planned.contains(&writes(path.join("index.theme"))));
+                assert!(planned.contains(&writes(path.join("cursors").join("arrow"))));
+                assert!(planned.contains("aliases: default, left_ptr, top_left_arrow\n"));
+            } else {
+                let path = dir.path().join(format!("{name}.cape"));
+                assert_eq!(planned.matches("writes: ").count(), 1);
+                assert!(planned.contains(&writes(path)));
+                assert!(!planned.contains("aliases: "));
+            }
+
+            // nothing is written on a dry run
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+        }
+    }
+
+    /// Converts a good and a broken cursor while keeping going, where only the broken one fails.
+    #[test]
+    fn keep_going() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().to_str().unwrap();
+        let registry = Registry::with_builtins();
+
+        // a CUR signature, but nothing after it
+        let bad = dir.path().join("bad.cur");
+        fs::write(&bad, [0, 0, 2, 0, 1, 0]).unwrap();
+        let bad = bad.to_str().unwrap();
+
+        let Command::Convert(args) = parse(&["convert", FIXTURE, bad, "--out", out, "-k"]) else {
+            unreachable!()
+        };
+
+        let args = ParsedArgs::from_args(args, &Config::default(), &registry).unwrap();
+        let mut failures = Vec::new();
+        let inputs = keep_ok(&args, resolve_inputs(&args), &mut failures).unwrap();
+        let read = |args: &ParsedArgs| -> Vec<_> {
+            let inputs = keep_ok(args, resolve_inputs(args), &mut Vec::new()).unwrap();
+            inputs
+                .into_iter()
+                .map(|(source, input)| (source, input.read(args)))
+                .collect()
+        };
+
+        // naming doesn't read the cursors, so both are fine until then
+        assert_eq!(inputs.len(), 2);
+        assert!(failures.is_empty());
+
+        let outputs = keep_ok(&args, read(&args), &mut failures).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].0, FIXTURE);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, bad);
+
+        let err = report_failures(&failures, 2).unwrap_err();
+        let PartialFailure { failed, total } = err.downcast().unwrap();
+        assert_eq!((failed, total), (1, 2));
+
+        // without keeping going, the first failure is returned
+        let args = ParsedArgs {
+            keep_going: false,
+            ..args
+        };
+        assert!(keep_ok(&args, read(&args), &mut Vec::new()).is_err());
+    }
+
+    /// Parses conversion arguments writing to `out`, following `conflict`.
+    fn conflict_args<'a>(out: &Path, conflict: Conflict, registry: &'a Registry) -> ParsedArgs<'a> {
+        let Command::Convert(args) = parse(&["convert", FIXTURE, "--out", out.to_str().unwrap()])
+        else {
+            unreachable!()
+        };
+
+        ParsedArgs {
+            conflict,
+            ..ParsedArgs::from_args(args, &Config::default(), registry).unwrap()
+        }
+    }
+
+    /// Returns cursor inputs named each of `names`, without reading anything.
+    fn named_inputs(names: &[&str]) -> Vec<(String, Input)> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let input = Input::Cursor(PathBuf::from(FIXTURE), OsString::from(name));
+                (format!("{name}#{i}"), input)
+            })
+            .collect()
+    }
+
+    /// Resolves outputs that would be written to the same path.
+    #[test]
+    fn conflict_in_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Registry::with_builtins();
+
+        for conflict in [Conflict::Error, Conflict::Overwrite, Conflict::Skip] {
+            let args = conflict_args(dir.path(), conflict, &registry);
+            let err = resolve_conflicts(&args, named_inputs(&["a", "b", "a"])).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "a#0 and a#2 would both be written to path={}; \
+                    pass '--suffix' to number them instead",
+                    dir.path().join("a").display()
+                )
+            );
+        }
+    }
+
+    /// Resolves themes with blank names, which are named the same before being written.
+    #[test]
+    fn conflict_blank_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Registry::with_builtins();
+        let blank = |i| {
+            let theme = Input::Installer(PathBuf::from(INSTALLER), String::new(), Vec::new());
+            (format!("blank#{i}"), theme)
+        };
+
+        let args = conflict_args(dir.path(), Conflict::Overwrite, &registry);
+        let err = resolve_conflicts(&args, vec![blank(0), blank(1)]).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("blank#0 and blank#1 would both be written")
+        );
+
+        let args = conflict_args(dir.path(), Conflict::Suffix, &registry);
+        let resolved = resolve_conflicts(&args, vec![blank(0), blank(1)]).unwrap();
+        let paths: Vec<_> = resolved.into_iter().map(|(_, _, path)| path).collect();
+        assert_eq!(
+            paths,
+            ["placeholder_theme_name", "placeholder_theme_name-2"].map(|p| dir.path().join(p))
+        );
+    }
+
+    /// Resolves outputs that already exist, with each policy.
+    #[test]
+    fn conflict_existing() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Registry::with_builtins();
+        fs::write(dir.path().join("a"), []).unwrap();
+
+        let resolve = |conflict| {
+            let args = conflict_args(dir.path(), conflict, &registry);
+            resolve_conflicts(&args, named_inputs(&["a", "b"])).map(|resolved| {
+                let paths: Vec<_> = resolved.into_iter().map(|(_, _, path)| path).collect();
+                paths
+            })
+        };
+
+        let err = resolve(Conflict::Error).unwrap_err();
+        assert!(err.to_string().starts_with(&format!(
+            "path={} already exists (from a#0)",
+            dir.path().join("a").display()
+        )));
+
+        let paths = resolve(Conflict::Overwrite).unwrap();
+        assert_eq!(paths, [dir.path().join("a"), dir.path().join("b")]);
+
+        let paths = resolve(Conflict::Skip).unwrap();
+        assert_eq!(paths, [dir.path().join("b")]);
+    }
+
+    /// Resolves conflicts by numbering paths, keeping the names of themes.
+    #[test]
+    fn conflict_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Registry::with_builtins();
+        let args = conflict_args(dir.path(), Conflict::Suffix, &registry);
+        fs::write(dir.path().join("a"), []).unwrap();
+        fs::create_dir(dir.path().join("Theme")).unwrap();
+
+        let mut inputs = named_inputs(&["a", "b", "a"]);
+        let theme = Input::Installer(PathBuf::from(INSTALLER), String::from("Theme"), Vec::new());
+        inputs.push((String::from("theme"), theme));
+
+        let resolved = resolve_conflicts(&args, inputs).unwrap();
+        let paths: Vec<_> = resolved.iter().map(|(_, _, path)| path.clone()).collect();
+        assert_eq!(
+            paths,
+            ["a-2", "b", "a-3", "Theme-2"].map(|p| dir.path().join(p))
+        );
+
+        let Input::Installer(_, name, _) = &resolved[3].1 else {
+            unreachable!()
+        };
+        assert_eq!(name, "Theme");
+    }
+}
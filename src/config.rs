@@ -0,0 +1,309 @@
+//! Module for "currust.toml" config files, see [`Config`].
+//!
+//! A config sets defaults for conversions, which flags passed on the command line take
+//! priority over. For example:
+//!
+//! ```toml
+//! out = "converted"
+//! scale_to = [1.5, 2]
+//! scale_with = "box"
+//!
+//! # for every theme
+//! [roles.text]
+//! scale_with = "mitchell"
+//!
+//! # for the theme named "My Theme"
+//! [themes."My Theme".roles.arrow]
+//! scale_to = [3]
+//! ```
+
+use crate::{cli::ScalingAlgorithm, themes::theme::CursorType};
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+use log::debug;
+use serde::Deserialize;
+
+/// Name of config files.
+pub const CONFIG_FILE_NAME: &str = "currust.toml";
+
+/// Options for scaling, either as defaults or as overrides.
+///
+/// For overrides, scale factors are added to the ones inherited,
+/// while algorithms replace the ones inherited.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScalingConfig {
+    /// Scale factors, see "--scale-to".
+    pub scale_to: Option<Vec<f64>>,
+    /// Scaling algorithm, see "--scale-with".
+    pub scale_with: Option<ScalingAlgorithm>,
+    /// Scaling algorithm for upscaling, see "--upscale-with".
+    pub upscale_with: Option<ScalingAlgorithm>,
+    /// Scaling algorithm for downscaling, see "--downscale-with".
+    pub downscale_with: Option<ScalingAlgorithm>,
+}
+
+impl ScalingConfig {
+    /// Returns `self`, with unset options taken from `lower`.
+    fn or(self, lower: Self) -> Self {
+        Self {
+            scale_to: self.scale_to.or(lower.scale_to),
+            scale_with: self.scale_with.or(lower.scale_with),
+            upscale_with: self.upscale_with.or(lower.upscale_with),
+            downscale_with: self.downscale_with.or(lower.downscale_with),
+        }
+    }
+}
+
+/// Overrides for a theme, keyed by its name in [`Config::themes`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Overrides for every cursor in the theme.
+    #[serde(flatten)]
+    pub scaling: ScalingConfig,
+    /// Overrides for cursors with a role in the theme, which apply after [`Config::roles`].
+    #[serde(default)]
+    pub roles: BTreeMap<String, ScalingConfig>,
+}
+
+impl ThemeConfig {
+    /// Returns `self`, with unset options taken from `lower`.
+    fn or(self, lower: Self) -> Self {
+        Self {
+            scaling: self.scaling.or(lower.scaling),
+            roles: merge_maps(self.roles, lower.roles, ScalingConfig::or),
+        }
+    }
+}
+
+/// Contents of a config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The directory to place converted themes/files, see "--out".
+    ///
+    /// Relative paths are relative to the directory of the config file.
+    pub out: Option<PathBuf>,
+    /// Defaults for scaling.
+    #[serde(flatten)]
+    pub scaling: ScalingConfig,
+    /// Overrides for cursors with a role (e.g., "text", "nwse-resize"), in every theme.
+    #[serde(default)]
+    pub roles: BTreeMap<String, ScalingConfig>,
+    /// Overrides for themes, keyed by the name of the theme in its installer
+    /// (unsanitized, see [`CursorTheme::name`](crate::themes::theme::CursorTheme::name)).
+    #[serde(default)]
+    pub themes: BTreeMap<String, ThemeConfig>,
+}
+
+impl Config {
+    /// Reads the config at `path` if provided, and otherwise the user and project configs.
+    ///
+    /// The user config is "currust.toml" in `$XDG_CONFIG_HOME` (or "~/.config"), and the
+    /// project config is the nearest "currust.toml" in the current directory or its parents.
+    /// Options in the project config take priority over the user config.
+    ///
+    /// ## Errors
+    ///
+    /// If any config can't be read or parsed, or has an unknown role.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            return Self::read(path);
+        }
+
+        Self::load_nearest(user_config_path(), env::current_dir()?.ancestors())
+    }
+
+    /// Helper function for [`Self::load`], reading the config at `user` and
+    /// the nearest config in `dirs` (nearest first), which takes priority.
+    ///
+    /// ## Errors
+    ///
+    /// If any config can't be read or parsed, or has an unknown role.
+    fn load_nearest<'a>(
+        user: Option<PathBuf>,
+        dirs: impl Iterator<Item = &'a Path>,
+    ) -> Result<Self> {
+        let user = user.filter(|p| p.is_file());
+        let project = dirs
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|p| p.is_file())
+            .filter(|p| Some(p) != user.as_ref());
+
+        let mut config = Self::default();
+
+        for path in [project, user].into_iter().flatten() {
+            config = config.or(Self::read(&path)?);
+        }
+
+        Ok(config)
+    }
+
+    /// Reads and parses the config at `path`.
+    ///
+    /// ## Errors
+    ///
+    /// If `path` can't be read or parsed, or has an unknown role.
+    fn read(path: &Path) -> Result<Self> {
+        debug!("reading config={}", path.display());
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config={}", path.display()))?;
+        let mut config: Self = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse config={}", path.display()))?;
+
+        // relative to the config, since it may be in a parent directory
+        if let Some(out) = &mut config.out
+            && let Some(dir) = path.parent()
+        {
+            *out = dir.join(&*out);
+        }
+
+        let role_names = config
+            .roles
+            .keys()
+            .chain(config.themes.values().flat_map(|t| t.roles.keys()));
+
+        for name in role_names {
+            if CursorType::from_name(name).is_none() {
+                return Err(anyhow!("unknown role={name}"))
+                    .with_context(|| format!("in config={}", path.display()));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Returns `self`, with unset options taken from `lower`.
+    fn or(self, lower: Self) -> Self {
+        Self {
+            out: self.out.or(lower.out),
+            scaling: self.scaling.or(lower.scaling),
+            roles: merge_maps(self.roles, lower.roles, ScalingConfig::or),
+            themes: merge_maps(self.themes, lower.themes, ThemeConfig::or),
+        }
+    }
+}
+
+/// Returns the path of the user config, if the config directory is known.
+fn user_config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|home| home.join(".config")))
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+}
+
+/// Merges `lower` into `upper`, using `or` for keys in both.
+fn merge_maps<T>(
+    mut upper: BTreeMap<String, T>,
+    lower: BTreeMap<String, T>,
+    or: fn(T, T) -> T,
+) -> BTreeMap<String, T> {
+    for (key, value) in lower {
+        let merged = match upper.remove(&key) {
+            Some(upper_value) => or(upper_value, value),
+            None => value,
+        };
+
+        upper.insert(key, merged);
+    }
+
+    upper
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a config with `contents` at `path`, creating its parents.
+    fn write_config(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Loads the user and project configs, where the project config takes priority.
+    #[test]
+    fn load_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join("user").join(CONFIG_FILE_NAME);
+        let project = dir.path().join("project");
+        let cwd = project.join("sub");
+        fs::create_dir_all(&cwd).unwrap();
+
+        write_config(
+            &user,
+            "out = \"user-out\"\nscale_to = [2]\nscale_with = \"lanczos3\"\n\
+            [roles.text]\nscale_to = [3]\nscale_with = \"mitchell\"\n",
+        );
+        write_config(
+            &project.join(CONFIG_FILE_NAME),
+            "scale_to = [1.5]\n[roles.text]\nscale_with = \"bilinear\"\n",
+        );
+
+        // only searches inside the temporary dir, so stray configs above it aren't found
+        let root = dir.path();
+        let dirs = |cwd| Path::ancestors(cwd).take_while(|d| d.starts_with(root));
+
+        let config = Config::load_nearest(Some(user.clone()), dirs(&cwd)).unwrap();
+        assert_eq!(config.scaling.scale_to, Some(vec![1.5]));
+        assert_eq!(config.scaling.scale_with, Some(ScalingAlgorithm::Lanczos3));
+
+        // options are merged for each role, rather than replacing it
+        let text = &config.roles["text"];
+        assert_eq!(text.scale_to, Some(vec![3.0]));
+        assert_eq!(text.scale_with, Some(ScalingAlgorithm::Bilinear));
+
+        // relative to the config it's set in, not the current directory
+        assert_eq!(config.out, Some(dir.path().join("user").join("user-out")));
+
+        // without a project config, the user config is used as is
+        let config = Config::load_nearest(Some(user), dirs(dir.path())).unwrap();
+        assert_eq!(config.scaling.scale_to, Some(vec![2.0]));
+        assert_eq!(
+            config.roles["text"].scale_with,
+            Some(ScalingAlgorithm::Mitchell)
+        );
+    }
+
+    /// Merges theme overrides, keeping keys from both sides.
+    #[test]
+    fn merge_themes() {
+        let upper: Config = toml::from_str(
+            "[themes.a]\nscale_to = [2]\n[themes.a.roles.arrow]\nscale_with = \"box\"\n",
+        )
+        .unwrap();
+        let lower: Config = toml::from_str(
+            "[themes.a]\nscale_to = [3]\nscale_with = \"bilinear\"\n\
+            [themes.a.roles.text]\nscale_to = [4]\n[themes.b]\nscale_to = [5]\n",
+        )
+        .unwrap();
+
+        let merged = upper.or(lower);
+        assert_eq!(merged.themes.keys().collect::<Vec<_>>(), ["a", "b"]);
+
+        let a = &merged.themes["a"];
+        assert_eq!(a.scaling.scale_to, Some(vec![2.0]));
+        assert_eq!(a.scaling.scale_with, Some(ScalingAlgorithm::Bilinear));
+        assert_eq!(a.roles.keys().collect::<Vec<_>>(), ["arrow", "text"]);
+        assert_eq!(merged.themes["b"].scaling.scale_to, Some(vec![5.0]));
+    }
+
+    /// Reads a config with an unknown role.
+    #[test]
+    fn unknown_role() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        write_config(&path, "[themes.a.roles.nope]\nscale_to = [2]\n");
+
+        let err = Config::read(&path).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "unknown role=nope");
+    }
+}
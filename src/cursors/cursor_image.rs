@@ -2,6 +2,11 @@
 //!
 //! These represent the frames of static/animated cursors.
 
+use crate::{
+    cursors::decode::{DecodeOptions, Limits},
+    formats::dib::DibHeader,
+};
+
 use std::fmt;
 
 use anyhow::{Context, Result, bail};
@@ -10,6 +15,7 @@ use fast_image_resize::{
     images::{Image, ImageRef},
 };
 use ico::{IconDirEntry, ResourceType};
+use log::warn;
 
 /// Represents a generic cursor image.
 #[derive(Clone)]
@@ -54,10 +60,12 @@ impl CursorImage {
             bail!("hotspot_y={hotspot_y} cannot be greater than height={height}");
         }
 
-        if (width * height * 4) != rgba.len().try_into()? {
+        // u64 to avoid overflow on large dimensions
+        let expected_len = u64::from(width) * u64::from(height) * 4;
+
+        if expected_len != u64::try_from(rgba.len())? {
             bail!(
-                "Expected rgba.len()={}, instead got rgba.len()={}",
-                width * height * 4,
+                "Expected rgba.len()={expected_len}, instead got rgba.len()={}",
                 rgba.len()
             );
         }
@@ -79,7 +87,7 @@ impl CursorImage {
     /// ## Errors
     ///
     /// If the entry isn't a cursor (no hotspot), or RGBA fails to decode.
-    pub fn from_entry(entry: &IconDirEntry, delay: u32) -> Result<Self> {
+    pub fn from_entry(entry: &IconDirEntry, delay: u32, options: &DecodeOptions) -> Result<Self> {
         if entry.resource_type() == ResourceType::Icon {
             bail!(
                 "can't create CursorImage with resource_type={:?}",
@@ -91,10 +99,7 @@ impl CursorImage {
             .cursor_hotspot()
             .context("failed to extract hotspot to construct CursorImage")?;
 
-        let rgba = entry
-            .decode()
-            .context("failed to decode RGBA to construct CursorImage")?
-            .into_rgba_data();
+        let rgba = Self::decode_entry(entry, options)?;
 
         Self::new(
             entry.width(),
@@ -106,6 +111,194 @@ impl CursorImage {
         )
     }
 
+    /// Helper function for constructing from an `entry` that may not store a hotspot.
+    ///
+    /// Unlike [`Self::from_entry`], this accepts icons. The `hotspot` is used if
+    /// provided, otherwise the hotspot stored in `entry` is used if it's a cursor.
+    /// If neither exist, this falls back to the tip found by [`Self::find_tip`].
+    ///
+    /// ## Errors
+    ///
+    /// If RGBA fails to decode, or `hotspot` is out of bounds.
+    pub fn from_any_entry(
+        entry: &IconDirEntry,
+        hotspot: Option<(u32, u32)>,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let rgba = Self::decode_entry(entry, options)?;
+
+        let mut image = Self::new(entry.width(), entry.height(), 0, 0, rgba, 0)?;
+
+        let (hotspot_x, hotspot_y) = hotspot
+            .or_else(|| {
+                entry
+                    .cursor_hotspot()
+                    .map(|(x, y)| (u32::from(x), u32::from(y)))
+            })
+            .unwrap_or_else(|| image.find_tip());
+
+        image.set_hotspot(hotspot_x, hotspot_y)?;
+
+        Ok(image)
+    }
+
+    /// Helper function for decoding the RGBA of `entry`.
+    ///
+    /// This handles quirks of older cursors that [`ico`] doesn't, see [`DecodeOptions`].
+    fn decode_entry(entry: &IconDirEntry, options: &DecodeOptions) -> Result<Vec<u8>> {
+        // dimensions are read from the encoded data, so this is before allocating
+        options.limits.check_image(entry.width(), entry.height())?;
+
+        let mut rgba = entry
+            .decode()
+            .context("failed to decode RGBA to construct CursorImage")?
+            .into_rgba_data();
+
+        if entry.is_png() {
+            return Ok(rgba);
+        }
+
+        // cursor entries don't store bit depth, so read it from the DIB instead
+        let header = DibHeader::read(entry.data())?;
+
+        // only bitmaps without an alpha channel can have inverted pixels
+        if header.bit_count < 32 {
+            let num_inverted = options.inverted_pixels.render(&mut rgba, entry.width());
+
+            if num_inverted > 0 {
+                warn!(
+                    "{}-bpp entry ({}x{}) has {num_inverted} inverted pixels, approximating them with {:?}",
+                    header.bit_count,
+                    entry.width(),
+                    entry.height(),
+                    options.inverted_pixels
+                );
+            }
+        }
+
+        // some 32-bpp bitmaps leave alpha empty and use the AND mask instead,
+        // which would otherwise be fully transparent since ico ignores the mask
+        if header.bit_count == 32 && rgba.as_chunks::<4>().0.iter().all(|p| p[3] == 0) {
+            let mask = match header.read_and_mask(entry.data()) {
+                Ok(mask) => mask,
+                Err(err) => {
+                    warn!(
+                        "32-bpp entry ({}x{}) has an empty alpha channel and no usable AND mask, \
+                        leaving it transparent: {err}",
+                        entry.width(),
+                        entry.height()
+                    );
+
+                    return Ok(rgba);
+                }
+            };
+
+            // fully transparent either way (e.g., a blank frame)
+            if mask.iter().all(|&transparent| transparent) {
+                return Ok(rgba);
+            }
+
+            warn!(
+                "32-bpp entry ({}x{}) has an empty alpha channel, using its AND mask instead",
+                entry.width(),
+                entry.height()
+            );
+
+            for (pixel, transparent) in rgba.as_chunks_mut::<4>().0.iter_mut().zip(mask) {
+                pixel[3] = if transparent { 0 } else { u8::MAX };
+            }
+        }
+
+        Ok(rgba)
+    }
+
+    /// Sets the hotspot to (`hotspot_x`, `hotspot_y`).
+    ///
+    /// ## Errors
+    ///
+    /// If the hotspot is out of bounds, see [`Self::new`].
+    pub fn set_hotspot(&mut self, hotspot_x: u32, hotspot_y: u32) -> Result<()> {
+        let (width, height) = self.dimensions();
+
+        if hotspot_x > width {
+            bail!("hotspot_x={hotspot_x} cannot be greater than width={width}");
+        }
+
+        if hotspot_y > height {
+            bail!("hotspot_y={hotspot_y} cannot be greater than height={height}");
+        }
+
+        self.hotspot_x = hotspot_x;
+        self.hotspot_y = hotspot_y;
+
+        Ok(())
+    }
+
+    /// Sets the delay to `delay` milliseconds.
+    pub const fn set_delay(&mut self, delay: u32) {
+        self.delay = delay;
+    }
+
+    /// Returns the topmost-leftmost (mostly) opaque pixel as (x, y).
+    ///
+    /// This is the tip of an arrow pointing to the top-left, which is a sensible
+    /// hotspot for images that don't store one. Returns (0, 0) if nothing's opaque.
+    #[must_use]
+    pub fn find_tip(&self) -> (u32, u32) {
+        // ignores faint shadows and anti-aliasing
+        const MIN_ALPHA: u8 = 128;
+
+        self.rgba
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .position(|pixel| pixel[3] >= MIN_ALPHA)
+            .and_then(|i| u32::try_from(i).ok())
+            .map_or((0, 0), |i| (i % self.width, i / self.width))
+    }
+
+    /// Returns a copy of `self` on a transparent canvas of `width` and `height`,
+    /// where the top-left corner of `self` is placed at (`offset_x`, `offset_y`).
+    ///
+    /// The hotspot is moved along with the image.
+    ///
+    /// ## Errors
+    ///
+    /// If `self` doesn't fit on the canvas, or [`TryInto`] conversions fail.
+    pub fn on_canvas(&self, width: u32, height: u32, offset_x: u32, offset_y: u32) -> Result<Self> {
+        let fits = |offset: u32, len: u32, canvas_len: u32| {
+            offset.checked_add(len).is_some_and(|end| end <= canvas_len)
+        };
+
+        if !fits(offset_x, self.width, width) || !fits(offset_y, self.height, height) {
+            bail!(
+                "image ({}x{}) at offset=({offset_x}, {offset_y}) doesn't fit on canvas ({width}x{height})",
+                self.width,
+                self.height
+            );
+        }
+
+        // u64 to avoid overflow on large dimensions
+        let mut rgba = vec![0_u8; usize::try_from(u64::from(width) * u64::from(height) * 4)?];
+        let row_len = usize::try_from(self.width)? * 4;
+        let canvas_row_len = usize::try_from(width)? * 4;
+        let (start_x, start_y) = (usize::try_from(offset_x)? * 4, usize::try_from(offset_y)?);
+
+        for (y, row) in self.rgba.chunks_exact(row_len).enumerate() {
+            let start = (start_y + y) * canvas_row_len + start_x;
+            rgba[start..start + row_len].copy_from_slice(row);
+        }
+
+        Self::new(
+            width,
+            height,
+            self.hotspot_x + offset_x,
+            self.hotspot_y + offset_y,
+            rgba,
+            self.delay,
+        )
+    }
+
     /// Returns a new [`CursorImage`] scaled to `scale_factor`.
     ///
     /// ## Errors
@@ -158,6 +351,12 @@ impl CursorImage {
         &self.rgba
     }
 
+    /// Returns the number of pixels, i.e, width * height.
+    #[must_use]
+    pub fn num_pixels(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+
     /// Returns the max of width and height.
     #[must_use]
     pub fn nominal_size(&self) -> u32 {
@@ -213,6 +412,65 @@ impl CursorImages {
     pub fn inner(&self) -> &[CursorImage] {
         &self.inner
     }
+
+    /// Mutable accessor for `inner`.
+    ///
+    /// This is a slice, so invariants can't be broken by adding/removing frames.
+    /// Take care to not break them otherwise (e.g., by changing dimensions).
+    pub fn inner_mut(&mut self) -> &mut [CursorImage] {
+        &mut self.inner
+    }
+
+    /// Like [`Self::try_from`], but frames with differing dimensions are placed on
+    /// a common canvas first. Frames are aligned so that their hotspots overlap.
+    ///
+    /// The canvas is checked against `limits` before any frame is placed on it.
+    ///
+    /// ## Errors
+    ///
+    /// - If the canvas exceeds `limits`, see [`Limits::check_image`] and [`Limits::check_total`].
+    /// - If other invariants aren't met, see [`Self::try_from`].
+    pub fn aligned(vec: Vec<CursorImage>, limits: &Limits) -> Result<Self> {
+        let expected_dims = vec.first().map(CursorImage::dimensions);
+
+        if vec
+            .iter()
+            .all(|img| Some(img.dimensions()) == expected_dims)
+        {
+            return Self::try_from(vec);
+        }
+
+        // furthest extents from the hotspot in each direction
+        let left = vec.iter().map(|img| img.hotspot_x).max().unwrap_or(0);
+        let top = vec.iter().map(|img| img.hotspot_y).max().unwrap_or(0);
+        let right = vec
+            .iter()
+            .map(|img| img.width - img.hotspot_x)
+            .max()
+            .unwrap_or(0);
+        let bottom = vec
+            .iter()
+            .map(|img| img.height - img.hotspot_y)
+            .max()
+            .unwrap_or(0);
+
+        let (Some(width), Some(height)) = (left.checked_add(right), top.checked_add(bottom)) else {
+            bail!("frames are too far apart to be aligned on a common canvas");
+        };
+
+        // the canvas can be much larger than any of the frames
+        limits.check_image(width, height)?;
+        limits.check_total(u64::from(width) * u64::from(height) * u64::try_from(vec.len())?)?;
+
+        warn!("frames have differing dimensions, aligning them on a {width}x{height} canvas");
+
+        let aligned: Vec<_> = vec
+            .iter()
+            .map(|img| img.on_canvas(width, height, left - img.hotspot_x, top - img.hotspot_y))
+            .collect::<Result<_>>()?;
+
+        Self::try_from(aligned)
+    }
 }
 
 impl TryFrom<Vec<CursorImage>> for CursorImages {
@@ -256,8 +514,15 @@ impl From<CursorImages> for Vec<CursorImage> {
 
 #[cfg(test)]
 pub mod tests {
-    use super::CursorImage;
-    use std::sync::LazyLock;
+    use super::{CursorImage, CursorImages};
+    use crate::{
+        cursors::decode::{DecodeOptions, Limits},
+        formats::dib::tests::{cur, dib_32bpp},
+    };
+
+    use std::{io::Cursor, sync::LazyLock};
+
+    use ico::IconDir;
 
     pub static BLACK: LazyLock<CursorImage> = LazyLock::new(|| CursorImage {
         width: 32,
@@ -276,4 +541,77 @@ pub mod tests {
         delay: 100,
         rgba: vec![255u8; 4096],
     });
+
+    /// Aligns a 2x2 and 3x1 frame by their hotspots.
+    #[test]
+    fn align_frames() {
+        let a = CursorImage::new(2, 2, 1, 1, vec![255; 16], 100).unwrap();
+        let b = CursorImage::new(3, 1, 0, 0, vec![127; 12], 100).unwrap();
+        assert!(CursorImages::try_from(vec![a.clone(), b.clone()]).is_err());
+
+        // the 4x2 canvas is checked, rather than the frames
+        let limits = Limits {
+            max_image_pixels: 7,
+            ..Limits::default()
+        };
+        assert!(CursorImages::aligned(vec![a.clone(), b.clone()], &limits).is_err());
+
+        let limits = Limits {
+            max_total_pixels: 15,
+            ..Limits::default()
+        };
+        assert!(CursorImages::aligned(vec![a.clone(), b.clone()], &limits).is_err());
+
+        let images = CursorImages::aligned(vec![a, b], &Limits::default()).unwrap();
+        let (a, b) = (&images.inner()[0], &images.inner()[1]);
+        assert_eq!(a.dimensions(), (4, 2));
+        assert_eq!((a.hotspot(), b.hotspot()), ((1, 1), (1, 1)));
+
+        // b is placed one row down, starting from the hotspot
+        let alphas: Vec<u8> = b.rgba().as_chunks::<4>().0.iter().map(|p| p[3]).collect();
+        assert_eq!(alphas, [0, 0, 0, 0, 0, 127, 127, 127]);
+    }
+
+    /// Rebuilds alpha from the AND mask for a 32-bpp entry with an empty alpha channel.
+    #[test]
+    fn alpha_from_and_mask() {
+        let mask = [true, false, false, true, true, false];
+        let dib = dib_32bpp(2, &[[255, 255, 255, 0]; 6], Some(&mask));
+        let dir = IconDir::read(Cursor::new(cur(&[dib]))).unwrap();
+
+        let image =
+            CursorImage::from_entry(&dir.entries()[0], 0, &DecodeOptions::default()).unwrap();
+        let alphas: Vec<u8> = image
+            .rgba()
+            .as_chunks::<4>()
+            .0
+            .iter()
+            .map(|p| p[3])
+            .collect();
+        assert_eq!(alphas, [0, 255, 255, 0, 0, 255]);
+
+        // fully transparent masks are left as is
+        let dib = dib_32bpp(2, &[[255, 255, 255, 0]; 6], Some(&[true; 6]));
+        let dir = IconDir::read(Cursor::new(cur(&[dib]))).unwrap();
+
+        let image =
+            CursorImage::from_entry(&dir.entries()[0], 0, &DecodeOptions::default()).unwrap();
+        assert!(image.rgba().as_chunks::<4>().0.iter().all(|p| p[3] == 0));
+    }
+
+    /// Leaves a 32-bpp entry with an empty alpha channel transparent if its AND mask is truncated.
+    #[test]
+    fn alpha_without_and_mask() {
+        let dib = dib_32bpp(2, &[[255, 255, 255, 0]; 6], Some(&[false; 6]));
+
+        // 6 pixels of 4 bytes, and 3 rows of 4 bytes
+        for len in [dib.len() - 4, dib.len() - 12] {
+            let dir = IconDir::read(Cursor::new(cur(&[dib[..len].to_vec()]))).unwrap();
+
+            let image =
+                CursorImage::from_entry(&dir.entries()[0], 0, &DecodeOptions::default()).unwrap();
+            assert_eq!(image.dimensions(), (2, 3));
+            assert!(image.rgba().as_chunks::<4>().0.iter().all(|p| p[3] == 0));
+        }
+    }
 }
@@ -0,0 +1,239 @@
+//! Contains [`DecodeOptions`], for handling quirks of old or malformed cursors.
+
+use anyhow::{Result, bail};
+use fast_image_resize::ResizeAlg;
+
+/// How to render inverted pixels, which are used in legacy monochrome cursors.
+///
+/// These cursors use an AND and XOR mask, where each pixel is either black, white,
+/// transparent, or _inverted_ (inverts whatever's underneath on screen). There's
+/// no equivalent for inverted pixels in modern formats, so they must be approximated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvertedPixels {
+    /// Renders as black, with a white outline so it's visible on dark backgrounds.
+    #[default]
+    Outline,
+    /// Renders as solid black.
+    Black,
+    /// Renders as solid white.
+    White,
+    /// Renders as a checker pattern of black and white.
+    Checker,
+}
+
+/// What to do with sizes that only some frames of an animated cursor have.
+///
+/// ANI frames can each store multiple sizes, but nothing ensures that every frame
+/// stores the same sizes. Since every size must have all frames, these are incomplete.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingSizes {
+    /// Drops the incomplete size.
+    #[default]
+    Drop,
+    /// Fills in missing frames by scaling from the frame's base image.
+    Scale {
+        /// Algorithm for upscaling.
+        upscale_with: ResizeAlg,
+        /// Algorithm for downscaling.
+        downscale_with: ResizeAlg,
+    },
+}
+
+/// Limits on the input when decoding cursors, which guard against malformed files.
+///
+/// These are checked before anything large is allocated where possible, so that
+/// untrusted input fails with an error rather than running out of memory.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Max size of a single chunk (e.g., an ANI frame) in bytes.
+    pub max_chunk_size: u32,
+    /// Max number of frames stored in an animated cursor.
+    pub max_frames: u32,
+    /// Max number of steps in an animation, i.e, the length of the sequence.
+    pub max_steps: u32,
+    /// Max number of pixels in a single image, including scaled images.
+    pub max_image_pixels: u64,
+    /// Max number of pixels across all images of a cursor, including scaled images.
+    ///
+    /// Pixels take 4 bytes each, so this also limits the size of the output.
+    pub max_total_pixels: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            // 256x256 frames are 256 KiB each, and frames can store multiple sizes
+            max_chunk_size: 16 * 1024 * 1024,
+            max_frames: 1024,
+            max_steps: 4096,
+            max_image_pixels: 2048 * 2048,
+            // 512 MiB of RGBA
+            max_total_pixels: 128 * 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// Checks that an image of `width` and `height` is within [`Self::max_image_pixels`].
+    ///
+    /// ## Errors
+    ///
+    /// If the image has too many pixels.
+    pub fn check_image(&self, width: u32, height: u32) -> Result<()> {
+        let pixels = u64::from(width) * u64::from(height);
+
+        if pixels > self.max_image_pixels {
+            bail!(
+                "image ({width}x{height}) exceeds max_image_pixels={}",
+                self.max_image_pixels
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `pixels` (across all images of a cursor) is within [`Self::max_total_pixels`].
+    ///
+    /// ## Errors
+    ///
+    /// If there are too many pixels.
+    pub fn check_total(&self, pixels: u64) -> Result<()> {
+        if pixels > self.max_total_pixels {
+            bail!(
+                "cursor has {pixels} pixels in total, which exceeds max_total_pixels={}",
+                self.max_total_pixels
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Options used when decoding cursors.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// How to render inverted pixels.
+    pub inverted_pixels: InvertedPixels,
+    /// Skip unknown chunks in ANI files instead of failing, like Windows does.
+    pub lenient: bool,
+    /// Place animation frames with differing sizes on a common canvas instead of
+    /// failing. Frames are aligned by their hotspot, so they stay in place.
+    pub align_frames: bool,
+    /// What to do with sizes that only some frames have.
+    pub missing_sizes: MissingSizes,
+    /// Limits on the input.
+    pub limits: Limits,
+}
+
+impl InvertedPixels {
+    /// Renders inverted pixels in `rgba` (with `width`) in-place.
+    ///
+    /// Inverted pixels are expected to be transparent with a non-black color,
+    /// which is how [`ico`] decodes them. Returns the number of inverted pixels,
+    /// which is zero if `rgba` doesn't have a full row of `width`.
+    pub(crate) fn render(self, rgba: &mut [u8], width: u32) -> usize {
+        const BLACK: [u8; 4] = [0, 0, 0, 255];
+        const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+        let width = width as usize;
+        let pixels = rgba.as_chunks_mut::<4>().0;
+
+        // nothing to render without at least one full row
+        if width == 0 || pixels.len() < width {
+            return 0;
+        }
+
+        let inverted: Vec<usize> = pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p[3] == 0 && p[..3] != [0, 0, 0])
+            .map(|(i, _)| i)
+            .collect();
+
+        for &i in &inverted {
+            let (x, y) = (i % width, i / width);
+
+            let is_black = match self {
+                Self::Outline | Self::Black => true,
+                Self::White => false,
+                Self::Checker => (x + y) % 2 == 0,
+            };
+
+            pixels[i] = if is_black { BLACK } else { WHITE };
+        }
+
+        if self == Self::Outline {
+            let height = pixels.len() / width;
+
+            for &i in &inverted {
+                let (x, y) = (i % width, i / width);
+
+                // transparent neighbors become the outline
+                for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                        let neighbor = &mut pixels[ny * width + nx];
+
+                        if neighbor[3] == 0 {
+                            *neighbor = WHITE;
+                        }
+                    }
+                }
+            }
+        }
+
+        inverted.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders a 3x3 image with an inverted pixel in the centre.
+    #[test]
+    fn render_inverted() {
+        let mut rgba = [[0_u8, 0, 0, 0]; 9];
+        rgba[4] = [255, 255, 255, 0];
+        let mut rgba = rgba.as_flattened().to_vec();
+        let mut outline = rgba.clone();
+
+        assert_eq!(InvertedPixels::Black.render(&mut rgba, 3), 1);
+        let pixels = rgba.as_chunks::<4>().0;
+        assert_eq!(pixels[4], [0, 0, 0, 255]);
+        assert!(pixels.iter().enumerate().all(|(i, p)| i == 4 || p[3] == 0));
+
+        assert_eq!(InvertedPixels::Outline.render(&mut outline, 3), 1);
+        let pixels = outline.as_chunks::<4>().0;
+        assert_eq!(pixels[4], [0, 0, 0, 255]);
+        assert!(
+            pixels
+                .iter()
+                .enumerate()
+                .all(|(i, p)| i == 4 || *p == [255; 4])
+        );
+
+        // degenerate dimensions are ignored, rather than dividing by zero
+        let mut rgba = [255, 255, 255, 0];
+        assert_eq!(InvertedPixels::Outline.render(&mut rgba, 0), 0);
+        assert_eq!(InvertedPixels::Outline.render(&mut rgba, 2), 0);
+        assert_eq!(rgba, [255, 255, 255, 0]);
+    }
+
+    /// Checks images and totals at their limits, and one over.
+    #[test]
+    fn limits_boundaries() {
+        let limits = Limits {
+            max_image_pixels: 64 * 64,
+            max_total_pixels: 1000,
+            ..Limits::default()
+        };
+
+        assert!(limits.check_image(64, 64).is_ok());
+        assert!(limits.check_image(4096, 1).is_ok());
+        assert!(limits.check_image(64, 65).is_err());
+        assert!(limits.check_image(4097, 1).is_err());
+
+        assert!(limits.check_total(1000).is_ok());
+        assert!(limits.check_total(1001).is_err());
+    }
+}
@@ -3,21 +3,36 @@
 //! This represents a full static/animated cursor.
 
 use crate::{
-    cursors::cursor_image::{CursorImage, CursorImages},
-    formats::{ani::AniFile, xcursor::Xcursor},
+    cursors::{
+        cursor_image::{CursorImage, CursorImages},
+        decode::{DecodeOptions, Limits, MissingSizes},
+        hotspot,
+    },
+    formats::{
+        ani::{AniError, AniErrorKind, AniFile},
+        detect::Format,
+        dib,
+        registry::Registry,
+        xcursor::Xcursor,
+    },
+    fs_utils::write_atomic,
+    themes::theme::CursorType,
 };
 
 use std::{
+    cmp::Reverse,
     fs::{self, File},
-    io::{BufWriter, Cursor},
-    mem,
+    io::{BufReader, Cursor, Read, Seek, Write},
+    iter,
     path::Path,
 };
 
 use anyhow::{Context, Result, bail};
 use binrw::BinWrite;
 use fast_image_resize::ResizeAlg;
-use ico::IconDir;
+use ico::{IconDir, IconDirEntry, ResourceType};
+use image::ImageReader;
+use log::{debug, warn};
 
 /// Represents a generic cursor.
 #[derive(Debug)]
@@ -35,6 +50,8 @@ pub struct GenericCursor {
     /// Both ANI and Xcursor have the capability of storing metadata similar
     /// to this. ANI uses it's "INFO" chunk, while Xcursor uses comment chunks.
     info: Option<String>,
+    /// Whether the hotspot was stored in the source, rather than guessed.
+    stored_hotspot: bool,
 }
 
 impl GenericCursor {
@@ -44,7 +61,7 @@ impl GenericCursor {
     ///
     /// - If `base_images` or `scaled_images` is empty.
     /// - If propagated from [`CursorImages`] construction.
-    fn new(
+    pub fn new(
         base_images: CursorImages,
         scaled_images: Vec<CursorImages>,
         info: Option<String>,
@@ -86,17 +103,19 @@ impl GenericCursor {
             scaled: scaled_images,
             scale_factors,
             info,
+            stored_hotspot: true,
         })
     }
 
     /// Constructor without `scaled`.
     #[must_use]
-    fn new_unscaled(base_images: CursorImages, info: Option<String>) -> Self {
+    pub fn new_unscaled(base_images: CursorImages, info: Option<String>) -> Self {
         Self {
             base: base_images,
             scaled: Vec::new(),
             scale_factors: vec![1.0],
             info,
+            stored_hotspot: true,
         }
     }
 
@@ -106,15 +125,27 @@ impl GenericCursor {
     ///
     /// ## Errors
     ///
-    /// If the newly made [`CursorImage`] doesn't
-    /// have a unique (canon) scale factor.
-    pub fn add_scale(&mut self, scale_factor: f64, algorithm: ResizeAlg) -> Result<()> {
+    /// - If the newly made [`CursorImage`] doesn't have a unique (canon) scale factor.
+    /// - If the scaled images would exceed `limits`, which is checked before scaling.
+    pub fn add_scale(
+        &mut self,
+        scale_factor: f64,
+        algorithm: ResizeAlg,
+        limits: &Limits,
+    ) -> Result<()> {
         // some cursors already store scaled versions
         if self.scale_factors.contains(&scale_factor) {
-            eprintln!("scale_factor={scale_factor} already added, skipping");
+            debug!("scale_factor={scale_factor} already added, skipping");
             return Ok(());
         }
 
+        let (width, height) =
+            CursorImage::scale_point(self.base.first().dimensions(), scale_factor);
+        limits.check_image(width, height)?;
+
+        let added_pixels = u64::from(width) * u64::from(height) * u64::try_from(self.base.len())?;
+        limits.check_total(self.num_pixels() + added_pixels)?;
+
         self.scale_factors.push(scale_factor);
 
         let scaled_images: Vec<CursorImage> = self
@@ -129,34 +160,180 @@ impl GenericCursor {
         Ok(())
     }
 
-    /// Reads the file and parses based on extension.
+    /// Reads the file and parses based on its format, using the built-in decoders.
+    ///
+    /// See [`Registry::decode`], which also reads formats registered outside of the crate.
     ///
     /// ## Errors
     ///
-    /// If `path` has no extension or an extension that isn't "ani" or "cur".
-    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref();
+    /// If the format of `path` isn't recognised or can't be read.
+    pub fn from_path<P: AsRef<Path>>(path: P, options: &DecodeOptions) -> Result<Self> {
+        Registry::builtin().decode(path.as_ref(), options)
+    }
 
-        let Some(ext) = path.extension() else {
-            bail!("path has no extension; expected 'cur' or 'ani'")
-        };
+    /// Reads and parses a cursor from `reader`, using the built-in decoders.
+    ///
+    /// Unlike [`Self::from_path`], the format is only detected from the contents.
+    /// See [`Registry::decode_reader`].
+    ///
+    /// ## Errors
+    ///
+    /// If the format isn't recognised or can't be read.
+    pub fn from_reader<R: Read + Seek>(mut reader: R, options: &DecodeOptions) -> Result<Self> {
+        Registry::builtin().decode_reader(&mut reader, options)
+    }
+
+    /// Reads and parses a static cursor from `image_path`,
+    /// which must be a path to an ICO, PNG or BMP image.
+    ///
+    /// Since these don't store hotspots, a sidecar file is checked for one, which
+    /// is the image path with ".hotspot" appended (e.g., "arrow.png.hotspot").
+    /// This should contain the coordinates as "x,y". If there's no sidecar, the
+    /// tip is used instead, see [`CursorImage::find_tip`].
+    ///
+    /// For ICO files with multiple sizes, the sidecar hotspot is for the
+    /// base size and is scaled for other sizes.
+    ///
+    /// ## Errors
+    ///
+    /// If the file can't be read or decoded, or the sidecar is malformed.
+    pub fn from_image_path<P: AsRef<Path>>(image_path: P, options: &DecodeOptions) -> Result<Self> {
+        let image_path = image_path.as_ref();
+        let hotspot = Self::read_hotspot_sidecar(image_path)?;
+        let handle = File::open(image_path).context("(filesystem) failed to read")?;
 
-        let ext = ext.to_ascii_lowercase();
+        Self::from_image_reader(BufReader::new(handle), hotspot, options)
+    }
 
-        let cursor = if ext == "cur" {
-            Self::from_cur_path(path)
-        } else if ext == "ani" {
-            Self::from_ani_path(path)
+    /// Reads and parses a static cursor from `reader`, which must contain
+    /// an ICO, PNG or BMP image. See [`Self::from_image_path`].
+    ///
+    /// If `hotspot` is [`None`], the tip is used instead.
+    ///
+    /// ## Errors
+    ///
+    /// If the image can't be read or decoded.
+    pub fn from_image_reader<R: Read>(
+        mut reader: R,
+        hotspot: Option<(u32, u32)>,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let mut handle = Vec::new();
+        reader
+            .read_to_end(&mut handle)
+            .context("failed to read image")?;
+
+        // some "icons" are actually cursors with the wrong extension
+        let is_ico = matches!(Format::sniff(&handle), Some(Format::Ico | Format::Cur));
+
+        if !is_ico {
+            // only reads the header, so this is before allocating
+            let reader = ImageReader::new(Cursor::new(&handle)).with_guessed_format()?;
+            let (width, height) = reader.into_dimensions().context("failed to decode image")?;
+            options.limits.check_image(width, height)?;
+
+            let rgba = image::load_from_memory(&handle)
+                .context("failed to decode image")?
+                .into_rgba8();
+
+            let (width, height) = rgba.dimensions();
+            let mut image = CursorImage::new(width, height, 0, 0, rgba.into_raw(), 0)?;
+            let (hotspot_x, hotspot_y) = hotspot.unwrap_or_else(|| image.find_tip());
+            image.set_hotspot(hotspot_x, hotspot_y)?;
+
+            let mut cursor = Self::new_unscaled(vec![image].try_into()?, None);
+            cursor.stored_hotspot = hotspot.is_some();
+
+            return Ok(cursor);
+        }
+
+        let icon_dir = IconDir::read(Cursor::new(handle)).context("failed to read `IconDir`")?;
+
+        if icon_dir.entries().is_empty() {
+            bail!("no stored images found");
+        }
+
+        let base_dims = Self::get_base_dimensions(&[&icon_dir]);
+        let base_nominal = f64::from(base_dims.0.max(base_dims.1));
+
+        // icons usually store each size at multiple bit depths, so use the best one
+        let mut entries: Vec<_> = icon_dir.entries().iter().collect();
+        entries.sort_by_key(|e| (e.width(), e.height(), Reverse(dib::bit_depth(e))));
+        entries.dedup_by_key(|e| (e.width(), e.height()));
+
+        let mut base = None;
+        let mut scaled = Vec::new();
+
+        for entry in entries {
+            let nominal = f64::from(entry.width().max(entry.height()));
+            let entry_hotspot =
+                hotspot.map(|h| CursorImage::scale_point(h, nominal / base_nominal));
+            let image = CursorImage::from_any_entry(entry, entry_hotspot, options)?;
+
+            if image.dimensions() == base_dims {
+                base = Some(image);
+            } else {
+                scaled.push(CursorImages::try_from(vec![image])?);
+            }
+        }
+
+        let base = CursorImages::try_from(vec![base.context("no base image found")?])?;
+
+        let mut cursor = if scaled.is_empty() {
+            Self::new_unscaled(base, None)
         } else {
-            bail!(
-                "expected extension 'cur' or 'ani' for path, got ext={}",
-                ext.display()
-            )
-        }?;
+            Self::new(base, scaled, None)?
+        };
+
+        // some "icons" are actually cursors with the wrong extension
+        cursor.stored_hotspot =
+            hotspot.is_some() || icon_dir.resource_type() == ResourceType::Cursor;
 
         Ok(cursor)
     }
 
+    /// Helper function for [`Self::from_image_path`].
+    ///
+    /// Returns [`None`] if there's no sidecar.
+    fn read_hotspot_sidecar(image_path: &Path) -> Result<Option<(u32, u32)>> {
+        let mut sidecar = image_path.as_os_str().to_owned();
+        sidecar.push(".hotspot");
+        let sidecar = Path::new(&sidecar);
+
+        if !sidecar.try_exists()? {
+            return Ok(None);
+        }
+
+        let contents = fs::read_to_string(sidecar).context("(filesystem) failed to read")?;
+        let hotspot = parse_hotspot(contents.trim())
+            .with_context(|| format!("malformed sidecar={}", sidecar.display()))?;
+
+        Ok(Some(hotspot))
+    }
+
+    /// Sets the hotspot of all images to (`hotspot_x`, `hotspot_y`).
+    ///
+    /// The hotspot is relative to the base images and is scaled
+    /// accordingly for any scaled images that already exist.
+    ///
+    /// ## Errors
+    ///
+    /// If the hotspot is out of bounds for any image.
+    pub fn set_hotspot(&mut self, hotspot_x: u32, hotspot_y: u32) -> Result<()> {
+        let base_nominal = f64::from(self.base.first().nominal_size());
+
+        for images in iter::once(&mut self.base).chain(&mut self.scaled) {
+            let scale_factor = f64::from(images.first().nominal_size()) / base_nominal;
+            let (x, y) = CursorImage::scale_point((hotspot_x, hotspot_y), scale_factor);
+
+            for image in images.inner_mut() {
+                image.set_hotspot(x, y)?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Reads and parses a cursor from `cur_path`, which
     /// must be a path to a Windows cursor file (i.e, CUR).
     ///
@@ -164,10 +341,19 @@ impl GenericCursor {
     ///
     /// If a file handle to `cur_path` can't be opened,
     /// or the file stored is not a CUR file.
-    pub fn from_cur_path<P: AsRef<Path>>(cur_path: P) -> Result<Self> {
-        let cur_path = cur_path.as_ref();
-        let handle = fs::read(cur_path).context("(filesystem) failed to read")?;
-        let icon_dir = IconDir::read(Cursor::new(handle)).context("failed to read `IconDir`")?;
+    pub fn from_cur_path<P: AsRef<Path>>(cur_path: P, options: &DecodeOptions) -> Result<Self> {
+        let handle = File::open(cur_path).context("(filesystem) failed to read")?;
+
+        Self::from_cur_reader(BufReader::new(handle), options)
+    }
+
+    /// Reads and parses a cursor from `reader`, which must contain a CUR file.
+    ///
+    /// ## Errors
+    ///
+    /// If the data isn't a CUR file.
+    pub fn from_cur_reader<R: Read + Seek>(reader: R, options: &DecodeOptions) -> Result<Self> {
+        let icon_dir = IconDir::read(reader).context("failed to read `IconDir`")?;
         let entries = icon_dir.entries();
 
         if entries.is_empty() {
@@ -179,7 +365,7 @@ impl GenericCursor {
         let base_dims = Self::get_base_dimensions(&[&icon_dir]);
 
         for entry in entries {
-            let image = CursorImage::from_entry(entry, 0)?;
+            let image = CursorImage::from_entry(entry, 0, options)?;
 
             if image.dimensions() == base_dims {
                 base.push(image);
@@ -199,15 +385,41 @@ impl GenericCursor {
 
     /// Parses `ani_path`.
     ///
+    /// Frames stored as raw bitmaps (`AF_ICON` unset) use the hotspot of the first ICO
+    /// frame if there is one, otherwise (0, 0). Use an override or detection to fix this.
+    ///
+    /// Frames are read one at a time and dropped before the next is read, so only one
+    /// frame's data is held in memory at once. Each frame is read twice: first for its
+    /// sizes, then to decode it (only if it's displayed), see [`Self::group_ani_frames`].
+    ///
     /// ## Errors
     ///
-    /// - `ani_path` fails to be parsed as an [`IconDir`]
+    /// - `ani_path` fails to be parsed as an [`AniFile`], see [`AniError`].
+    /// - A frame fails to be parsed as an [`IconDir`].
     /// - Stored RGBA in ICO frames fail to be decoded.
     /// - Frames are inconsistent, see [`CursorImages`].
     /// - [`TryInto`] conversions fail (between primitive types).
-    pub fn from_ani_path<P: AsRef<Path>>(ani_path: P) -> Result<Self> {
-        let ani_blob = fs::read(&ani_path)?;
-        let ani_file = AniFile::from_blob(&ani_blob)?;
+    pub fn from_ani_path<P: AsRef<Path>>(ani_path: P, options: &DecodeOptions) -> Result<Self> {
+        let ani_path = ani_path.as_ref();
+        let handle = File::open(ani_path).context("(filesystem) failed to read")?;
+
+        // parsing errors don't know which file they're from
+        Self::from_ani_reader(BufReader::new(handle), options).map_err(|e| {
+            match e.downcast::<AniError>() {
+                Ok(e) => e.with_path(ani_path).into(),
+                Err(e) => e,
+            }
+        })
+    }
+
+    /// Reads and parses a cursor from `reader`, which must contain an ANI file.
+    /// See [`Self::from_ani_path`].
+    ///
+    /// ## Errors
+    ///
+    /// See [`Self::from_ani_path`].
+    pub fn from_ani_reader<R: Read + Seek>(mut reader: R, options: &DecodeOptions) -> Result<Self> {
+        let ani_file = AniFile::from_reader(&mut reader, options)?;
         let header = &ani_file.header;
 
         // optional info stuff
@@ -218,24 +430,57 @@ impl GenericCursor {
             (None, None) => None,
         };
 
-        // read each ico frame
-        let icos: Vec<IconDir> = ani_file
-            .ico_frames
-            .into_iter()
-            .map(|chunk| IconDir::read(&mut Cursor::new(&chunk.data)))
-            .collect::<Result<_, _>>()?;
+        let chunks = ani_file.ico_frames;
+        let mut frame_sizes = Vec::with_capacity(chunks.len());
+        let mut has_raw_frames = false;
+        let mut raw_hotspot = None;
+        let mut has_32x32 = false;
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            let data = chunk.read(&mut reader)?;
+            let is_raw = !dib::is_icon_dir(&data);
+
+            // raw frames (AF_ICON unset) are only wrapped for their sizes here
+            let ico = if is_raw {
+                has_raw_frames = true;
+                Self::read_raw_frame(&data, (0, 0))?
+            } else {
+                IconDir::read(Cursor::new(data))?
+            };
+
+            let Some(first) = ico.entries().first() else {
+                bail!(AniError::new(
+                    chunk.chunk_offset(),
+                    AniErrorKind::EmptyFrame { index }
+                ));
+            };
+
+            // raw frames don't store a hotspot, so use the first one stored
+            if !is_raw && raw_hotspot.is_none() {
+                raw_hotspot = first.cursor_hotspot();
+            }
+
+            has_32x32 |= ico
+                .entries()
+                .iter()
+                .any(|e| (e.width(), e.height()) == (32, 32));
+            frame_sizes.push(AniFrameSizes::new(&ico));
+        }
+
+        let stored_hotspot = !has_raw_frames || raw_hotspot.is_some();
+
+        if has_raw_frames && header.has_icon_frames() {
+            warn!("found raw bitmap frames, even though AF_ICON is set");
+        }
 
-        // get display order as indices into icos
+        // get display order as indices into frames
         let sequence: Option<Vec<usize>> = ani_file
             .sequence
             .map(|chunk| chunk.data.into_iter().map(usize::try_from).collect())
             .transpose()?;
 
         // indices validated in-bounds in AniFile
-        let sequenced_icos: Vec<&IconDir> = sequence.map_or_else(
-            || icos.iter().collect(),
-            |v| v.into_iter().map(|idx| &icos[idx]).collect(),
-        );
+        let sequence = sequence.unwrap_or_else(|| (0..frame_sizes.len()).collect());
 
         // use default timings in header, or custom one if defined
         let num_steps = usize::try_from(header.num_steps)?;
@@ -243,86 +488,279 @@ impl GenericCursor {
             .rate
             .map_or_else(|| vec![header.jiffy_rate; num_steps], |chunk| chunk.data);
 
-        // jiffies are 1/60th of a second
+        // jiffies are 1/60th of a second. rates come from the file,
+        // so this is widened and clamped instead of overflowing
         //
         // NOTE: this might cause slight diffs compared
         //       to other converters because of rounding
         let delays_ms: Vec<u32> = delays_jiffies
             .into_iter()
-            .map(|j| (j * 1000 + 30) / 60) // round by adding 30
+            .map(|j| (u64::from(j) * 1000 + 30) / 60) // round by adding 30
+            .map(|ms| u32::try_from(ms).unwrap_or(u32::MAX))
             .collect();
 
-        let base_dims = Self::get_base_dimensions(&sequenced_icos);
-        let mut base = Vec::new();
-        let mut scaled_ungrouped = Vec::new();
-
-        for (ico, delay) in sequenced_icos.iter().zip(delays_ms) {
-            let entries = ico.entries();
+        // frames are repeated in the sequence, so check before decoding them
+        options
+            .limits
+            .check_total(sequence.iter().map(|&i| frame_sizes[i].num_pixels()).sum())?;
 
-            for entry in entries {
-                let image = CursorImage::from_entry(entry, delay)?;
+        let Some(first) = frame_sizes.first() else {
+            bail!(AniError::new(0, AniErrorKind::NoFrames));
+        };
 
-                if image.dimensions() == base_dims {
-                    base.push(image);
-                } else {
-                    scaled_ungrouped.push(image);
-                }
-            }
-        }
+        // tries to use 32x32 as base, like `Self::get_base_dimensions`, but falls back on
+        // the smallest size rather than the first, since entries may be stored in any order
+        let base_nominal = if has_32x32 { 32 } else { first.smallest_size() };
 
-        let base = CursorImages::try_from(base)?;
+        let steps: Vec<(usize, u32)> = sequence.into_iter().zip(delays_ms).collect();
 
-        if scaled_ungrouped.is_empty() {
-            return Ok(Self::new_unscaled(base, info));
-        }
+        // frames are read again for decoding, now that the hotspot for raw frames is known
+        let mut read_frame = |index: usize| -> Result<IconDir> {
+            let data = chunks[index].read(&mut reader)?;
 
-        // could use hashmap here but ehh
-        scaled_ungrouped.sort_unstable_by_key(CursorImage::dimensions);
-        let scaled_ungrouped = scaled_ungrouped;
-        let mut scaled = Vec::new();
-        let mut buffer = Vec::new();
-        let mut current_dims = scaled_ungrouped[0].dimensions();
-
-        // group by dimensions
-        for image in scaled_ungrouped {
-            if image.dimensions() != current_dims {
-                scaled.push((mem::take(&mut buffer)).try_into()?);
-                current_dims = image.dimensions();
+            if dib::is_icon_dir(&data) {
+                Ok(IconDir::read(Cursor::new(data))?)
+            } else {
+                Self::read_raw_frame(&data, raw_hotspot.unwrap_or_default())
             }
+        };
 
-            buffer.push(image);
-        }
+        // group by nominal size, since frames of the same size may
+        // differ slightly in dimensions (e.g., 32x32 and 32x31)
+        let (base, scaled) =
+            Self::group_ani_frames(&frame_sizes, &steps, base_nominal, &mut read_frame, options)?;
+
+        if scaled.is_empty() {
+            let mut cursor = Self::new_unscaled(base, info);
+            cursor.stored_hotspot = stored_hotspot;
 
-        // push anything left
-        if !buffer.is_empty() {
-            scaled.push(buffer.try_into()?);
+            return Ok(cursor);
         }
 
-        Self::new(base, scaled, info)
+        let mut cursor = Self::new(base, scaled, info)?;
+        cursor.stored_hotspot = stored_hotspot;
+
+        Ok(cursor)
     }
 
     /// Saves `self` to `path` as Xcursor.
     ///
+    /// This is written atomically, so `path` is never left partially written.
+    ///
     /// ## Errors
     ///
     /// If filesystem operations fail, or if propagated from [`Xcursor`].
     pub fn save_as_xcursor<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let path = path.as_ref();
-        let file = File::create(path)?;
-
-        // this line is pretty important. reduces syscalls by like 500x
-        let mut writer = BufWriter::new(file);
+        write_atomic(path.as_ref(), |writer| self.write_xcursor(writer))
+    }
 
+    /// Writes `self` to `writer` as Xcursor.
+    ///
+    /// ## Errors
+    ///
+    /// If writing fails, or if propagated from [`Xcursor`].
+    pub fn write_xcursor<W: Write>(&self, mut writer: W) -> Result<()> {
         let xcursor = Xcursor::new(self)?;
 
-        // this can create partial writes. consider fixing
-        xcursor.write(&mut writer)?;
+        // binrw needs to seek, which not all writers can
+        let mut buf = Cursor::new(Vec::new());
+        xcursor.write(&mut buf)?;
+        writer.write_all(buf.get_ref())?;
 
         Ok(())
     }
 
+    /// Detects the hotspot for `r#type` and sets it, if the hotspot
+    /// wasn't stored in the source or is suspicious (see [`hotspot`]).
+    ///
+    /// Returns the detected hotspot if it was set, as (x, y).
+    ///
+    /// ## Errors
+    ///
+    /// If propagated from [`Self::set_hotspot`].
+    pub fn detect_hotspot(&mut self, r#type: &CursorType) -> Result<Option<(u32, u32)>> {
+        let first = self.base.first();
+
+        if self.stored_hotspot && !hotspot::is_suspicious(first) {
+            return Ok(None);
+        }
+
+        let (x, y) = hotspot::detect(first, r#type);
+        self.set_hotspot(x, y)?;
+        self.stored_hotspot = true;
+
+        Ok(Some((x, y)))
+    }
+
     /// Helper function for [`Self::from_ani_path`].
     ///
+    /// Wraps the raw bitmap `data` (an ANI frame when `AF_ICON` is unset) with `hotspot`.
+    fn read_raw_frame(data: &[u8], hotspot: (u16, u16)) -> Result<IconDir> {
+        let cur = dib::wrap_as_cur(data, hotspot).context("failed to read raw bitmap frame")?;
+
+        Ok(IconDir::read(Cursor::new(cur))?)
+    }
+
+    /// Helper function for [`Self::from_ani_path`].
+    ///
+    /// Returns the entry of `ico` with the nominal `size`, if any. Frames may store a size
+    /// at multiple bit depths (like icons, see [`Self::from_image_path`]), so the best is used.
+    fn best_ani_entry(ico: &IconDir, size: u32) -> Option<&IconDirEntry> {
+        ico.entries()
+            .iter()
+            .filter(|e| e.width().max(e.height()) == size)
+            .max_by_key(|e| dib::bit_depth(e))
+    }
+
+    /// Helper function for [`Self::from_ani_path`].
+    ///
+    /// Groups the images of each frame by nominal size into the base images (of
+    /// `base_nominal`) and scaled images. Sizes that only some frames have are
+    /// handled with [`DecodeOptions::missing_sizes`].
+    ///
+    /// `frame_sizes` holds the sizes of each frame, and `steps` is the display order, as
+    /// indices into `frame_sizes` along with their delays. Which sizes are kept is decided
+    /// from `frame_sizes` alone, then each displayed frame is read with `read_frame` and
+    /// decoded once (even if it's repeated in `steps`), one frame at a time. Frames that
+    /// aren't displayed and sizes that are dropped aren't decoded. Repeated frames are
+    /// still copied, since each step has its own delay.
+    fn group_ani_frames(
+        frame_sizes: &[AniFrameSizes],
+        steps: &[(usize, u32)],
+        base_nominal: u32,
+        read_frame: &mut dyn FnMut(usize) -> Result<IconDir>,
+        options: &DecodeOptions,
+    ) -> Result<(CursorImages, Vec<CursorImages>)> {
+        let to_images = |images: Vec<CursorImage>| {
+            if options.align_frames {
+                CursorImages::aligned(images, &options.limits)
+            } else {
+                CursorImages::try_from(images)
+            }
+        };
+
+        if let Some(&(i, _)) = steps
+            .iter()
+            .find(|&&(i, _)| !frame_sizes[i].contains(base_nominal))
+        {
+            bail!("frame {i} has no {base_nominal}px image");
+        }
+
+        let mut sizes: Vec<u32> = frame_sizes
+            .iter()
+            .flat_map(|frame| frame.sizes.iter().map(|&(size, _)| size))
+            .filter(|&size| size != base_nominal)
+            .collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+
+        // base first, then the kept sizes, along with the algorithm
+        // for filling in missing images if the size is incomplete
+        let mut kept = vec![(base_nominal, None)];
+
+        for size in sizes {
+            let num_present = steps
+                .iter()
+                .filter(|&&(i, _)| frame_sizes[i].contains(size))
+                .count();
+
+            if num_present == steps.len() {
+                kept.push((size, None));
+                continue;
+            }
+
+            let MissingSizes::Scale {
+                upscale_with,
+                downscale_with,
+            } = options.missing_sizes
+            else {
+                warn!(
+                    "only {num_present}/{} frames have a {size}px image, dropping this size",
+                    steps.len()
+                );
+                continue;
+            };
+
+            warn!(
+                "only {num_present}/{} frames have a {size}px image, scaling the rest from {base_nominal}px",
+                steps.len()
+            );
+
+            let algorithm = if size > base_nominal {
+                upscale_with
+            } else {
+                downscale_with
+            };
+
+            kept.push((size, Some(algorithm)));
+        }
+
+        // the image of each kept size, for each displayed frame
+        let mut decoded: Vec<Option<Vec<Option<CursorImage>>>> = vec![None; frame_sizes.len()];
+
+        for &(i, _) in steps {
+            if decoded[i].is_some() {
+                continue;
+            }
+
+            // dropped at the end of each iteration, before the next frame is read
+            let ico = read_frame(i).with_context(|| format!("failed to read frame {i}"))?;
+            let images = kept
+                .iter()
+                .map(|&(size, _)| {
+                    Self::best_ani_entry(&ico, size)
+                        .map(|entry| CursorImage::from_entry(entry, 0, options))
+                        .transpose()
+                })
+                .collect::<Result<_>>()
+                .with_context(|| format!("failed to decode frame {i}"))?;
+
+            decoded[i] = Some(images);
+        }
+
+        // the image of the `k`th kept size in each step, if any
+        let take_size = |k: usize| -> Vec<Option<CursorImage>> {
+            steps
+                .iter()
+                .map(|&(i, delay)| {
+                    let mut image = decoded[i].as_ref().and_then(|images| images[k].clone());
+                    if let Some(image) = &mut image {
+                        image.set_delay(delay);
+                    }
+
+                    image
+                })
+                .collect()
+        };
+
+        // checked to be present above
+        let base_frames: Vec<CursorImage> = take_size(0).into_iter().flatten().collect();
+        let base = to_images(base_frames.clone())?;
+        let mut scaled = Vec::with_capacity(kept.len() - 1);
+
+        for (k, &(size, algorithm)) in kept.iter().enumerate().skip(1) {
+            let Some(algorithm) = algorithm else {
+                scaled.push(to_images(take_size(k).into_iter().flatten().collect())?);
+                continue;
+            };
+
+            let scale_factor = f64::from(size) / f64::from(base_nominal);
+            let filled: Vec<CursorImage> = take_size(k)
+                .into_iter()
+                .zip(&base_frames)
+                .map(|(image, base_image)| {
+                    image.map_or_else(|| base_image.scaled_to(scale_factor, algorithm), Ok)
+                })
+                .collect::<Result<_>>()?;
+
+            scaled.push(to_images(filled)?);
+        }
+
+        Ok((base, scaled))
+    }
+
+    /// Helper function for [`Self::from_cur_path`] and [`Self::from_image_path`].
+    ///
     /// Tries to use 32x32 as base and checks `icons`. If there are
     /// no 32x32 entries, defaults to dimensions of first entry.
     fn get_base_dimensions(icons: &[&IconDir]) -> (u32, u32) {
@@ -364,6 +802,12 @@ impl GenericCursor {
         (self.scaled.len() + 1) * self.base.len()
     }
 
+    /// Returns the number of pixels across all `base` and `scaled` images.
+    #[must_use]
+    pub fn num_pixels(&self) -> u64 {
+        self.joined_images().map(CursorImage::num_pixels).sum()
+    }
+
     /// Returns an iterator joining `base` and `scaled` flattened
     /// over [`CursorImage`] (rather than [`CursorImages`]).
     pub fn joined_images(&self) -> impl Iterator<Item = &CursorImage> {
@@ -374,10 +818,94 @@ impl GenericCursor {
     }
 }
 
+/// Sizes stored in an ANI frame, read before any frame is decoded.
+///
+/// See [`GenericCursor::from_ani_reader`].
+#[derive(Debug)]
+struct AniFrameSizes {
+    /// Each nominal size (sorted) with the number of pixels
+    /// of its best entry, see [`GenericCursor::best_ani_entry`].
+    sizes: Vec<(u32, u64)>,
+}
+
+impl AniFrameSizes {
+    /// Reads the sizes stored in `ico`.
+    fn new(ico: &IconDir) -> Self {
+        let nominal = |e: &IconDirEntry| e.width().max(e.height());
+        let mut sizes: Vec<u32> = ico.entries().iter().map(nominal).collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+
+        Self {
+            sizes: sizes
+                .into_iter()
+                .filter_map(|size| {
+                    GenericCursor::best_ani_entry(ico, size)
+                        .map(|e| (size, u64::from(e.width()) * u64::from(e.height())))
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the smallest nominal size, used as the base if there's no 32x32 entry.
+    fn smallest_size(&self) -> u32 {
+        self.sizes.first().map_or(0, |&(size, _)| size)
+    }
+
+    /// Returns true if the frame has an image of the nominal `size`.
+    fn contains(&self, size: u32) -> bool {
+        self.sizes.iter().any(|&(s, _)| s == size)
+    }
+
+    /// Returns the number of pixels across the best entry of each size.
+    fn num_pixels(&self) -> u64 {
+        self.sizes.iter().map(|&(_, pixels)| pixels).sum()
+    }
+}
+
+/// Parses `hotspot` formatted as "x,y" (e.g., "9,2").
+///
+/// ## Errors
+///
+/// If `hotspot` isn't two comma-separated integers.
+pub fn parse_hotspot(hotspot: &str) -> Result<(u32, u32)> {
+    let (x, y) = hotspot
+        .split_once(',')
+        .with_context(|| format!("expected hotspot as 'x,y', got hotspot={hotspot}"))?;
+
+    let x = x.trim().parse().with_context(|| format!("invalid x={x}"))?;
+    let y = y.trim().parse().with_context(|| format!("invalid y={y}"))?;
+
+    Ok((x, y))
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::cursors::cursor_image::tests::{BLACK, WHITE};
+    use crate::{
+        AniErrorKind,
+        cursors::cursor_image::tests::{BLACK, WHITE},
+        formats::{
+            ani::tests::{anih, chunk, list, riff},
+            dib::tests::{cur, dib_4bpp, dib_32bpp},
+        },
+        from_root,
+    };
+
+    use fast_image_resize::FilterType;
+
+    const RED: [u8; 4] = [0, 0, 255, 255];
+    const BLUE: [u8; 4] = [255, 0, 0, 255];
+
+    /// Returns a 32-bpp DIB of `size` where every pixel is `color` (BGRA).
+    fn square_dib(size: u32, color: [u8; 4]) -> Vec<u8> {
+        let num_pixels = usize::try_from(size * size).unwrap();
+        dib_32bpp(
+            size,
+            &vec![color; num_pixels],
+            Some(&vec![false; num_pixels]),
+        )
+    }
 
     /// Generates an animated cursor with ten alternating black and white frames.
     #[expect(clippy::missing_panics_doc)]
@@ -398,4 +926,218 @@ pub mod tests {
         let frames = CursorImages::try_from(frames.to_vec()).unwrap();
         GenericCursor::new_unscaled(frames, None)
     }
+
+    /// Writes a PNG of `width` x `height` to `path`, which is
+    /// transparent apart from the pixels from `opaque` onwards.
+    fn write_png(path: &Path, width: u32, height: u32, opaque: (u32, u32)) {
+        let image = image::RgbaImage::from_fn(width, height, |x, y| {
+            let alpha = if x >= opaque.0 && y >= opaque.1 {
+                255
+            } else {
+                0
+            };
+            image::Rgba([0, 0, 0, alpha])
+        });
+
+        image.save(path).unwrap();
+    }
+
+    /// Reads PNGs as static cursors, using the sidecar hotspot or the tip.
+    #[test]
+    fn png_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let tip = dir.path().join("tip.png");
+        let sidecar = dir.path().join("sidecar.png");
+        write_png(&tip, 24, 16, (3, 5));
+        write_png(&sidecar, 24, 16, (3, 5));
+        fs::write(dir.path().join("sidecar.png.hotspot"), "9,2\n").unwrap();
+        let options = DecodeOptions::default();
+
+        for (path, hotspot) in [(&tip, (3, 5)), (&sidecar, (9, 2))] {
+            let cursor = GenericCursor::from_path(path, &options).unwrap();
+            let image = cursor.base_images().first();
+
+            assert_eq!(cursor.num_images(), 1);
+            assert_eq!(image.dimensions(), (24, 16));
+            assert_eq!(image.hotspot(), hotspot);
+        }
+
+        fs::write(dir.path().join("sidecar.png.hotspot"), "9;2").unwrap();
+        assert!(GenericCursor::from_path(&sidecar, &options).is_err());
+    }
+
+    /// Checks that reading from memory matches reading from a path.
+    #[test]
+    fn read_from_memory() {
+        const PATH: &str = from_root!("/testing/fixtures/neuro/Neuro help.ani");
+        const BLOB: &[u8] = include_bytes!(from_root!("/testing/fixtures/neuro/Neuro help.ani"));
+        let options = DecodeOptions::default();
+
+        let from_path = GenericCursor::from_path(PATH, &options).unwrap();
+        let from_reader = GenericCursor::from_reader(Cursor::new(BLOB), &options).unwrap();
+
+        let (mut expected, mut actual) = (Vec::new(), Vec::new());
+        from_path.write_xcursor(&mut expected).unwrap();
+        from_reader.write_xcursor(&mut actual).unwrap();
+
+        assert_eq!(from_path.num_images(), from_reader.num_images());
+        assert!(actual.starts_with(b"Xcur"));
+        assert_eq!(expected, actual);
+    }
+
+    /// Reads an ANI where only the first frame has a 48px image, and the
+    /// 32px image of the first frame is stored at both 4-bpp and 32-bpp.
+    #[test]
+    fn missing_sizes() {
+        let first = cur(&[
+            dib_4bpp(32, 32, RED),
+            square_dib(32, BLUE),
+            square_dib(48, BLUE),
+        ]);
+        let second = cur(&[square_dib(32, BLUE)]);
+
+        let blob = riff(&[
+            anih(2, 2, 10, 1),
+            list(b"fram", &[chunk(b"icon", &first), chunk(b"icon", &second)]),
+        ]);
+
+        let dropped =
+            GenericCursor::from_reader(Cursor::new(&blob), &DecodeOptions::default()).unwrap();
+        assert_eq!(dropped.num_images(), 2);
+
+        // the 32-bpp image is used over the 4-bpp one
+        for image in dropped.base_images().inner() {
+            assert_eq!(image.rgba()[..4], [0, 0, 255, 255]);
+        }
+
+        let algorithm = ResizeAlg::Convolution(FilterType::Box);
+        let options = DecodeOptions {
+            missing_sizes: MissingSizes::Scale {
+                upscale_with: algorithm,
+                downscale_with: algorithm,
+            },
+            ..DecodeOptions::default()
+        };
+
+        let scaled = GenericCursor::from_reader(Cursor::new(&blob), &options).unwrap();
+        let scaled_images: Vec<_> = scaled.scaled_images().collect();
+        assert_eq!(scaled.num_images(), 4);
+        assert_eq!(scaled_images.len(), 1);
+
+        for image in scaled_images[0].inner() {
+            assert_eq!(image.dimensions(), (48, 48));
+            assert_eq!(image.rgba()[..4], [0, 0, 255, 255]);
+        }
+    }
+
+    /// Uses the smallest size as the base of an ANI without a 32x32 image, in any storage order.
+    #[test]
+    fn ani_base_size() {
+        for sizes in [[48, 64], [64, 48]] {
+            let frame = cur(&sizes.map(|size| square_dib(size, BLUE)));
+            let blob = riff(&[anih(1, 1, 10, 1), list(b"fram", &[chunk(b"icon", &frame)])]);
+
+            let cursor =
+                GenericCursor::from_reader(Cursor::new(&blob), &DecodeOptions::default()).unwrap();
+            let scaled: Vec<_> = cursor.scaled_images().collect();
+
+            assert_eq!(cursor.base_images().first().dimensions(), (48, 48));
+            assert_eq!(scaled.len(), 1);
+            assert_eq!(scaled[0].first().dimensions(), (64, 64));
+        }
+    }
+
+    /// Reads ANIs without any frames, or with a frame without any images.
+    #[test]
+    fn empty_ani_frames() {
+        let kind = |blob: Vec<u8>| {
+            GenericCursor::from_reader(Cursor::new(blob), &DecodeOptions::default())
+                .unwrap_err()
+                .downcast::<AniError>()
+                .unwrap()
+                .kind
+        };
+
+        let no_frames = riff(&[anih(0, 0, 0, 1)]);
+        assert_eq!(no_frames.len(), 56);
+        assert!(matches!(kind(no_frames), AniErrorKind::NoFrames));
+
+        let empty_frame = riff(&[
+            anih(1, 1, 10, 1),
+            list(b"fram", &[chunk(b"icon", &cur(&[]))]),
+        ]);
+        assert!(matches!(
+            kind(empty_frame),
+            AniErrorKind::EmptyFrame { index: 0 }
+        ));
+    }
+
+    /// Reads a high-DPI ANI with 256px frames, which is larger than a few MiB.
+    #[test]
+    fn large_ani() {
+        let frames: Vec<Vec<u8>> = (0..8)
+            .map(|i| {
+                chunk(
+                    b"icon",
+                    &cur(&[square_dib(256, if i % 2 == 0 { RED } else { BLUE })]),
+                )
+            })
+            .collect();
+        let blob = riff(&[anih(8, 8, 10, 1), list(b"fram", &frames)]);
+        assert!(blob.len() > 2 * 1024 * 1024);
+
+        let cursor =
+            GenericCursor::from_reader(Cursor::new(&blob), &DecodeOptions::default()).unwrap();
+        assert_eq!(cursor.num_images(), 8);
+        assert_eq!(cursor.base_images().first().dimensions(), (256, 256));
+        assert_eq!(
+            cursor.base_images().inner()[1].rgba()[..4],
+            [0, 0, 255, 255]
+        );
+
+        // each frame is a little over 256 KiB
+        let mut options = DecodeOptions::default();
+        options.limits.max_chunk_size = 256 * 1024;
+
+        let err = GenericCursor::from_reader(Cursor::new(&blob), &options)
+            .unwrap_err()
+            .downcast::<AniError>()
+            .unwrap();
+        assert!(matches!(err.kind, AniErrorKind::ChunkTooLarge { .. }));
+    }
+
+    /// Checks that scaling is limited before scaled images are allocated.
+    #[test]
+    fn scale_limits() {
+        let algorithm = ResizeAlg::Convolution(FilterType::Box);
+        let limits = Limits {
+            max_image_pixels: 64 * 64,
+            // 10 frames of 32x32 and 64x64
+            max_total_pixels: 10 * (32 * 32 + 64 * 64),
+            ..Limits::default()
+        };
+
+        let mut cursor = black_and_white();
+        assert!(cursor.add_scale(2.5, algorithm, &limits).is_err());
+        cursor.add_scale(2.0, algorithm, &limits).unwrap();
+        assert!(cursor.add_scale(0.5, algorithm, &limits).is_err());
+        assert_eq!(cursor.num_images(), 20);
+    }
+
+    /// Reads an ANI with a rate too large to be represented in milliseconds.
+    #[test]
+    fn huge_rate() {
+        let frame = chunk(b"icon", &cur(&[square_dib(32, RED)]));
+        let blob = riff(&[
+            anih(2, 2, u32::MAX, 1),
+            list(b"fram", &[frame.clone(), frame]),
+        ]);
+
+        let cursor =
+            GenericCursor::from_reader(Cursor::new(&blob), &DecodeOptions::default()).unwrap();
+
+        for image in cursor.base_images().inner() {
+            assert_eq!(image.delay(), u32::MAX);
+        }
+    }
 }
@@ -0,0 +1,189 @@
+//! Heuristics for detecting hotspots from the shape of a [`CursorImage`].
+//!
+//! These are used for sources that don't store a hotspot (e.g., PNG), or store one
+//! that's obviously wrong. Each [`CursorType`] has a shape it's usually drawn as,
+//! so the heuristic used depends on it:
+//!
+//! - arrows pointing to the top-left use their tip
+//! - symmetric shapes (e.g., crosshairs and resize arrows) use their centroid
+//! - I-beams use the centre of their stem
+//! - hands use their fingertip
+//!
+//! These are guesses, so any detected hotspots should be reported for review.
+
+use crate::{cursors::cursor_image::CursorImage, themes::theme::CursorType};
+
+/// Alpha at which pixels are considered part of the shape.
+///
+/// This ignores faint shadows and anti-aliasing.
+const MIN_ALPHA: u8 = 128;
+
+/// Detects the hotspot of `image` for `r#type`, returned as (x, y).
+///
+/// If `image` has no opaque pixels, (0, 0) is returned.
+#[must_use]
+pub fn detect(image: &CursorImage, r#type: &CursorType) -> (u32, u32) {
+    use CursorType::*;
+
+    let Some(points) = OpaquePoints::new(image) else {
+        return (0, 0);
+    };
+
+    match r#type {
+        Arrow | Help | LeftPtrWatch => image.find_tip(),
+        Hand | CenterPtr => points.top_centre(),
+        Text => points.stem_centre(),
+        Pencil => points.bottom_left(),
+        Crosshair | Watch | Forbidden | NsResize | EwResize | NwseResize | NeswResize | Move => {
+            points.centroid()
+        }
+    }
+}
+
+/// Returns true if the hotspot of `image` is (0, 0), but there's nothing opaque there.
+///
+/// Hotspots of (0, 0) are usually left as the default, even if the shape
+/// doesn't have a tip at the top-left corner (e.g., for a crosshair).
+#[must_use]
+pub fn is_suspicious(image: &CursorImage) -> bool {
+    // leeway for anti-aliasing and outlines
+    const RADIUS: u32 = 2;
+
+    if image.hotspot() != (0, 0) {
+        return false;
+    }
+
+    let (width, height) = image.dimensions();
+
+    !(0..=RADIUS.min(height - 1))
+        .flat_map(|y| (0..=RADIUS.min(width - 1)).map(move |x| (x, y)))
+        .any(|(x, y)| alpha_at(image, x, y) >= MIN_ALPHA)
+}
+
+/// Returns the alpha of the pixel at (`x`, `y`).
+fn alpha_at(image: &CursorImage, x: u32, y: u32) -> u8 {
+    let (width, _) = image.dimensions();
+    let index = (y * width + x) as usize;
+
+    image.rgba()[index * 4 + 3]
+}
+
+/// Coordinates of every opaque pixel in an image, in row-major order.
+struct OpaquePoints(Vec<(u32, u32)>);
+
+impl OpaquePoints {
+    /// Returns [`None`] if `image` has no opaque pixels.
+    fn new(image: &CursorImage) -> Option<Self> {
+        let (width, height) = image.dimensions();
+
+        let points: Vec<_> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| alpha_at(image, x, y) >= MIN_ALPHA)
+            .collect();
+
+        (!points.is_empty()).then_some(Self(points))
+    }
+
+    /// Mean of all points, rounded to the nearest pixel.
+    #[expect(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn centroid(&self) -> (u32, u32) {
+        let n = self.0.len() as f64;
+        let (sum_x, sum_y) = self.0.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| {
+            (sx + f64::from(x), sy + f64::from(y))
+        });
+
+        ((sum_x / n).round() as u32, (sum_y / n).round() as u32)
+    }
+
+    /// Middle of the topmost row, e.g., a fingertip or the tip of an upwards arrow.
+    fn top_centre(&self) -> (u32, u32) {
+        let top = self.0[0].1;
+        let row: Vec<_> = self.0.iter().filter(|p| p.1 == top).collect();
+
+        (row[row.len() / 2].0, top)
+    }
+
+    /// Centre of the column with the most points, vertically centred on its bounds.
+    ///
+    /// For I-beams, this is the stem, which avoids skew from (possibly uneven) serifs.
+    fn stem_centre(&self) -> (u32, u32) {
+        let max_x = self.0.iter().map(|p| p.0).max().unwrap_or(0);
+        let mut counts = vec![0_u32; max_x as usize + 1];
+
+        for &(x, _) in &self.0 {
+            counts[x as usize] += 1;
+        }
+
+        // first of the widest columns, then its middle (if the stem is thick)
+        let max_count = counts.iter().copied().max().unwrap_or(0);
+        let first = counts.iter().position(|&c| c == max_count).unwrap_or(0);
+        let run = counts[first..]
+            .iter()
+            .take_while(|&&c| c == max_count)
+            .count();
+        let stem_x = u32::try_from(first + run / 2).unwrap_or(0);
+
+        let column: Vec<_> = self.0.iter().filter(|p| p.0 == stem_x).collect();
+        let (top, bottom) = (column[0].1, column[column.len() - 1].1);
+
+        (stem_x, top + (bottom - top) / 2)
+    }
+
+    /// Leftmost point of the bottommost row, e.g., the tip of a pencil.
+    fn bottom_left(&self) -> (u32, u32) {
+        let bottom = self.0[self.0.len() - 1].1;
+
+        self.0
+            .iter()
+            .copied()
+            .find(|p| p.1 == bottom)
+            .unwrap_or((0, bottom))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes a 32x32 image where pixels are opaque if `is_opaque(x, y)`.
+    fn make_image(is_opaque: impl Fn(u32, u32) -> bool) -> CursorImage {
+        let rgba = (0..32)
+            .flat_map(|y| (0..32).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let alpha = if is_opaque(x, y) { 255 } else { 0 };
+                [0, 0, 0, alpha]
+            })
+            .collect();
+
+        CursorImage::new(32, 32, 0, 0, rgba, 0).unwrap()
+    }
+
+    /// Checks each heuristic against simple shapes.
+    #[test]
+    fn detect_shapes() {
+        use CursorType::*;
+
+        // plus sign centred on (15, 15)
+        let cross = make_image(|x, y| {
+            (x == 15 && (5..=25).contains(&y)) || (y == 15 && (5..=25).contains(&x))
+        });
+        assert!(is_suspicious(&cross));
+        assert_eq!(detect(&cross, &Crosshair), (15, 15));
+        assert_eq!(detect(&cross, &Hand), (15, 5));
+
+        // I-beam with a 2px stem and serifs on the top-left
+        let ibeam = make_image(|x, y| {
+            ((12..=13).contains(&x) && (4..=28).contains(&y)) || (y == 4 && (8..=13).contains(&x))
+        });
+        assert_eq!(detect(&ibeam, &Text), (13, 16));
+
+        // triangle with its tip at (3, 2)
+        let arrow = make_image(|x, y| y >= 2 && x >= 3 && x - 3 <= y - 2);
+        assert_eq!(detect(&arrow, &Arrow), (3, 2));
+        assert_eq!(detect(&arrow, &Pencil), (3, 31));
+    }
+}
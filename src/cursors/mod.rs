@@ -1,4 +1,6 @@
 //! Contains generic representations of cursors for easier conversions.
 
 pub mod cursor_image;
+pub mod decode;
 pub mod generic_cursor;
+pub mod hotspot;
@@ -0,0 +1,497 @@
+//! C ABI for embedding currust, see `include/currust.h`.
+//!
+//! Cursors are opaque handles ([`CurrustCursor`]) which must be freed with
+//! [`currust_cursor_free`]. Functions return a [`CurrustStatus`], and on failure,
+//! the error message can be retrieved with [`currust_last_error`].
+//!
+//! Panics are caught at the boundary and reported as [`CurrustStatus::Panic`].
+//!
+//! Enums are passed as integers rather than as [`CurrustScaling`] itself, since
+//! C allows any value for an enum, and an unknown one would be undefined behaviour.
+//!
+//! The header is generated with [cbindgen](https://github.com/mozilla/cbindgen):
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --output include/currust.h
+//! ```
+
+use crate::{
+    cursors::{decode::DecodeOptions, generic_cursor::GenericCursor},
+    formats::registry::Registry,
+    fs_utils::find_files,
+    themes::theme::{CursorTheme, is_installer},
+};
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char},
+    io::Cursor,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr, slice,
+};
+
+use anyhow::{Context, Result, bail};
+use fast_image_resize::{FilterType, ResizeAlg};
+
+thread_local! {
+    /// Message of the last error on this thread, see [`currust_last_error`].
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque handle to a cursor.
+pub struct CurrustCursor(GenericCursor);
+
+/// Status returned by fallible functions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurrustStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A pointer was null, a string wasn't valid UTF-8, or an enum value is unknown.
+    InvalidArgument = 1,
+    /// The call failed, see [`currust_last_error`].
+    Error = 2,
+    /// currust panicked, which is a bug.
+    Panic = 3,
+}
+
+/// Algorithms for scaling cursors, passed as a `uint32_t`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurrustScaling {
+    Nearest = 0,
+    Box = 1,
+    Bilinear = 2,
+    Mitchell = 3,
+    Lanczos3 = 4,
+}
+
+impl TryFrom<u32> for CurrustScaling {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        let alg = match value {
+            0 => Self::Nearest,
+            1 => Self::Box,
+            2 => Self::Bilinear,
+            3 => Self::Mitchell,
+            4 => Self::Lanczos3,
+            _ => bail!("unknown scaling algorithm={value}"),
+        };
+
+        Ok(alg)
+    }
+}
+
+impl From<CurrustScaling> for ResizeAlg {
+    fn from(alg: CurrustScaling) -> Self {
+        match alg {
+            CurrustScaling::Nearest => Self::Nearest,
+            CurrustScaling::Box => Self::Convolution(FilterType::Box),
+            CurrustScaling::Bilinear => Self::Convolution(FilterType::Bilinear),
+            CurrustScaling::Mitchell => Self::Convolution(FilterType::Mitchell),
+            CurrustScaling::Lanczos3 => Self::Convolution(FilterType::Lanczos3),
+        }
+    }
+}
+
+/// A buffer allocated by currust, which must be freed with [`currust_buffer_free`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct CurrustBuffer {
+    /// Pointer to the data, or null if empty.
+    pub data: *mut u8,
+    /// Length of the data in bytes.
+    pub len: usize,
+}
+
+impl CurrustBuffer {
+    /// Leaks `vec` as a buffer. Its capacity is shrunk to fit, so that it can be rebuilt.
+    fn from_vec(vec: Vec<u8>) -> Self {
+        let data = Box::into_raw(vec.into_boxed_slice());
+
+        Self {
+            len: data.len(),
+            data: data.cast(),
+        }
+    }
+}
+
+/// Sets [`LAST_ERROR`] to `message`.
+fn set_last_error(message: &str) {
+    // interior nulls would truncate the message anyway
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Sets [`LAST_ERROR`] to `message`, returning [`CurrustStatus::InvalidArgument`].
+fn invalid_argument(message: &str) -> CurrustStatus {
+    set_last_error(message);
+    CurrustStatus::InvalidArgument
+}
+
+/// Runs `f`, catching errors and panics, which are stored in [`LAST_ERROR`].
+fn guard(f: impl FnOnce() -> Result<CurrustStatus>) -> CurrustStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(status)) => status,
+        Ok(Err(e)) => {
+            set_last_error(&format!("{e:#}"));
+            CurrustStatus::Error
+        }
+        Err(_) => {
+            set_last_error("currust panicked; please report this");
+            CurrustStatus::Panic
+        }
+    }
+}
+
+/// Helper function for converting `s` to a [`Path`].
+///
+/// ## Safety
+///
+/// `s` must be null or a valid null-terminated string.
+unsafe fn to_path<'a>(s: *const c_char) -> Result<&'a Path> {
+    if s.is_null() {
+        bail!("path is null");
+    }
+
+    // SAFETY: `s` is non-null and valid, as required by the caller
+    let s = unsafe { CStr::from_ptr(s) };
+
+    Ok(Path::new(s.to_str().context("path isn't valid UTF-8")?))
+}
+
+/// Returns the message of the last error on this thread, or null if there's none.
+///
+/// The string is owned by currust, and is valid until the next call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn currust_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Reads a cursor (e.g., ANI or CUR) from `data` with length `len`, and stores it in `out`.
+///
+/// The format is detected from the contents. The cursor must be freed with [`currust_cursor_free`].
+///
+/// ## Safety
+///
+/// `data` must be valid for reads of `len` bytes, and `out` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn currust_cursor_from_buffer(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut CurrustCursor,
+) -> CurrustStatus {
+    if data.is_null() || out.is_null() {
+        return invalid_argument("data or out is null");
+    }
+
+    guard(|| {
+        // SAFETY: `data` is non-null and valid for `len` bytes, as required by the caller
+        let data = unsafe { slice::from_raw_parts(data, len) };
+        let cursor = GenericCursor::from_reader(Cursor::new(data), &DecodeOptions::default())?;
+
+        // SAFETY: `out` is non-null and valid for writes, as required by the caller
+        unsafe {
+            out.write(Box::into_raw(Box::new(CurrustCursor(cursor))));
+        }
+
+        Ok(CurrustStatus::Ok)
+    })
+}
+
+/// Adds a scaled size to `cursor` by `scale_factor`, using `algorithm` (a [`CurrustScaling`]).
+///
+/// ## Safety
+///
+/// `cursor` must be a cursor from [`currust_cursor_from_buffer`] that hasn't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn currust_cursor_add_scale(
+    cursor: *mut CurrustCursor,
+    scale_factor: f64,
+    algorithm: u32,
+) -> CurrustStatus {
+    // SAFETY: `cursor` is valid, as required by the caller
+    let Some(cursor) = (unsafe { cursor.as_mut() }) else {
+        return invalid_argument("cursor is null");
+    };
+
+    let algorithm = match CurrustScaling::try_from(algorithm) {
+        Ok(algorithm) => algorithm,
+        Err(e) => return invalid_argument(&e.to_string()),
+    };
+
+    guard(|| {
+        let options = DecodeOptions::default();
+        cursor
+            .0
+            .add_scale(scale_factor, algorithm.into(), &options.limits)?;
+
+        Ok(CurrustStatus::Ok)
+    })
+}
+
+/// Writes `cursor` as Xcursor to a new buffer, stored in `out`.
+///
+/// The buffer must be freed with [`currust_buffer_free`].
+///
+/// ## Safety
+///
+/// `cursor` must be a cursor from [`currust_cursor_from_buffer`] that hasn't
+/// been freed, and `out` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn currust_cursor_write_xcursor(
+    cursor: *const CurrustCursor,
+    out: *mut CurrustBuffer,
+) -> CurrustStatus {
+    // SAFETY: `cursor` is valid, as required by the caller
+    let Some(cursor) = (unsafe { cursor.as_ref() }) else {
+        return invalid_argument("cursor is null");
+    };
+
+    if out.is_null() {
+        return invalid_argument("out is null");
+    }
+
+    guard(|| {
+        let mut data = Vec::new();
+        cursor.0.write_xcursor(&mut data)?;
+
+        // SAFETY: `out` is non-null and valid for writes, as required by the caller
+        unsafe {
+            out.write(CurrustBuffer::from_vec(data));
+        }
+
+        Ok(CurrustStatus::Ok)
+    })
+}
+
+/// Frees `cursor`. Does nothing if `cursor` is null.
+///
+/// ## Safety
+///
+/// `cursor` must be null or a cursor from [`currust_cursor_from_buffer`] that hasn't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn currust_cursor_free(cursor: *mut CurrustCursor) {
+    if !cursor.is_null() {
+        // SAFETY: `cursor` came from `Box::into_raw`, as required by the caller
+        drop(unsafe { Box::from_raw(cursor) });
+    }
+}
+
+/// Frees `buffer`. Does nothing if its data is null.
+///
+/// ## Safety
+///
+/// `buffer` must be from currust, and must not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn currust_buffer_free(buffer: CurrustBuffer) {
+    if !buffer.data.is_null() {
+        let data = ptr::slice_from_raw_parts_mut(buffer.data, buffer.len);
+
+        // SAFETY: `data` came from a leaked boxed slice, as required by the caller
+        drop(unsafe { Box::from_raw(data) });
+    }
+}
+
+/// Converts the theme in the directory `theme_dir`, which must contain one installer
+/// file (INF or CRS), to an X11 cursor theme, which is written inside of the directory `out_dir`.
+///
+/// Each of the `num_scales` scale factors in `scales` are added using
+/// `algorithm` (a [`CurrustScaling`]).
+/// `scales` may be null if `num_scales` is zero.
+///
+/// ## Safety
+///
+/// `theme_dir` and `out_dir` must be valid null-terminated strings,
+/// and `scales` must be valid for reads of `num_scales` elements.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn currust_convert_theme(
+    theme_dir: *const c_char,
+    out_dir: *const c_char,
+    scales: *const f64,
+    num_scales: usize,
+    algorithm: u32,
+) -> CurrustStatus {
+    if scales.is_null() && num_scales != 0 {
+        return invalid_argument("scales is null, but num_scales isn't zero");
+    }
+
+    let algorithm = match CurrustScaling::try_from(algorithm) {
+        Ok(algorithm) => algorithm,
+        Err(e) => return invalid_argument(&e.to_string()),
+    };
+
+    // SAFETY: strings are null or valid, as required by the caller
+    let paths = unsafe {
+        (
+            to_path(theme_dir).context("for theme_dir"),
+            to_path(out_dir).context("for out_dir"),
+        )
+    };
+
+    let (theme_dir, out_dir) = match paths {
+        (Ok(theme_dir), Ok(out_dir)) => (theme_dir, out_dir),
+        (Err(e), _) | (_, Err(e)) => return invalid_argument(&format!("{e:#}")),
+    };
+
+    let scales = if num_scales == 0 {
+        &[]
+    } else {
+        // SAFETY: `scales` is non-null and valid for `num_scales` elements
+        unsafe { slice::from_raw_parts(scales, num_scales) }
+    };
+
+    guard(|| {
+        let installers: Vec<_> = find_files(theme_dir, is_installer)
+            .with_context(|| format!("failed to read theme_dir={}", theme_dir.display()))?
+            .collect();
+
+        let [installer_path] = installers.as_slice() else {
+            bail!(
+                "expected one installer file (INF or CRS) in theme_dir={}, found {}",
+                theme_dir.display(),
+                installers.len()
+            );
+        };
+
+        let options = DecodeOptions::default();
+        let mut theme =
+            CursorTheme::from_installer_file(installer_path, Registry::builtin(), &options)?;
+
+        for &sf in scales {
+            theme.add_scale(sf, algorithm.into(), &options.limits)?;
+        }
+
+        std::fs::create_dir_all(out_dir)?;
+        theme.save_as_x11_theme(out_dir)?;
+
+        Ok(CurrustStatus::Ok)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_root;
+
+    /// Converts a theme directory through the C API, checking the written theme.
+    #[test]
+    fn convert_theme() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme_dir = CString::new(from_root!("/testing/fixtures/neuro")).unwrap();
+        let out_dir = CString::new(dir.path().to_str().unwrap()).unwrap();
+        let scales = [1.5, 2.0];
+
+        let status = unsafe {
+            currust_convert_theme(
+                theme_dir.as_ptr(),
+                out_dir.as_ptr(),
+                scales.as_ptr(),
+                scales.len(),
+                CurrustScaling::Box as u32,
+            )
+        };
+        assert_eq!(status, CurrustStatus::Ok);
+
+        let themes: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(themes.len(), 1);
+        assert!(themes[0].join("index.theme").is_file());
+        assert!(
+            themes[0]
+                .join("cursors")
+                .read_dir()
+                .unwrap()
+                .next()
+                .is_some()
+        );
+
+        // the output directory has no installer
+        let status =
+            unsafe { currust_convert_theme(out_dir.as_ptr(), out_dir.as_ptr(), ptr::null(), 0, 0) };
+        assert_eq!(status, CurrustStatus::Error);
+        let message = unsafe { CStr::from_ptr(currust_last_error()) };
+        assert!(message.to_str().unwrap().contains("found 0"), "{message:?}");
+
+        let status =
+            unsafe { currust_convert_theme(theme_dir.as_ptr(), ptr::null(), ptr::null(), 0, 0) };
+        assert_eq!(status, CurrustStatus::InvalidArgument);
+    }
+
+    /// Checks that `include/currust.h` is what cbindgen generates from this module.
+    #[test]
+    fn header_up_to_date() {
+        let config = cbindgen::Config::from_file(from_root!("/cbindgen.toml")).unwrap();
+        let mut header = Vec::new();
+
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(from_root!("/src/ffi.rs"))
+            .generate()
+            .unwrap()
+            .write(&mut header);
+
+        assert_eq!(
+            String::from_utf8(header).unwrap(),
+            include_str!(from_root!("/include/currust.h")),
+            "regenerate the header, see the README"
+        );
+    }
+
+    /// Converts a cursor through the C API, checking the output against the Rust API.
+    #[test]
+    fn convert_buffer() {
+        const BLOB: &[u8] = include_bytes!(from_root!("/testing/fixtures/neuro/Neuro help.ani"));
+
+        let mut cursor = ptr::null_mut();
+        let mut buffer = CurrustBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+
+        unsafe {
+            let status = currust_cursor_from_buffer(BLOB.as_ptr(), BLOB.len(), &raw mut cursor);
+            assert_eq!(status, CurrustStatus::Ok);
+
+            let status = currust_cursor_add_scale(cursor, 2.0, 5);
+            assert_eq!(status, CurrustStatus::InvalidArgument);
+            assert!(!currust_last_error().is_null());
+
+            let status = currust_cursor_add_scale(cursor, 2.0, CurrustScaling::Box as u32);
+            assert_eq!(status, CurrustStatus::Ok);
+
+            let status = currust_cursor_write_xcursor(cursor, &raw mut buffer);
+            assert_eq!(status, CurrustStatus::Ok);
+
+            let mut expected =
+                GenericCursor::from_reader(Cursor::new(BLOB), &DecodeOptions::default()).unwrap();
+            expected
+                .add_scale(
+                    2.0,
+                    CurrustScaling::Box.into(),
+                    &DecodeOptions::default().limits,
+                )
+                .unwrap();
+            let mut data = Vec::new();
+            expected.write_xcursor(&mut data).unwrap();
+
+            assert_eq!(slice::from_raw_parts(buffer.data, buffer.len), data);
+
+            currust_buffer_free(buffer);
+            currust_cursor_free(cursor);
+        }
+
+        let status = unsafe { currust_cursor_from_buffer(b"nope".as_ptr(), 4, &raw mut cursor) };
+        assert_eq!(status, CurrustStatus::Error);
+        assert!(!currust_last_error().is_null());
+
+        let status = unsafe { currust_cursor_write_xcursor(ptr::null(), ptr::null_mut()) };
+        assert_eq!(status, CurrustStatus::InvalidArgument);
+        let message = unsafe { CStr::from_ptr(currust_last_error()) };
+        assert_eq!(message, c"cursor is null");
+    }
+}
@@ -6,14 +6,140 @@
 //! You may find it helpful to also read about [RIFF](https://en.wikipedia.org/wiki/Resource_Interchange_File_Format).
 
 use std::{
+    error::Error,
     fmt,
-    io::{Cursor, Read},
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::PathBuf,
 };
 
 use anyhow::{Context, Result, bail};
 use binrw::{BinRead, NullString, binread};
+use log::warn;
+use thiserror::Error;
 
-use crate::warn;
+use crate::cursors::decode::DecodeOptions;
+
+/// An error from parsing an ANI file, see [`AniFile::from_reader`].
+///
+/// This points to the offending chunk by its offset, so that the file
+/// can be checked in a hex editor, along with the path if it's known.
+#[derive(Debug)]
+pub struct AniError {
+    /// The file being parsed, if it was read from a path.
+    pub path: Option<PathBuf>,
+    /// Offset from the start of the file of the offending chunk.
+    pub offset: u64,
+    /// What went wrong.
+    pub kind: AniErrorKind,
+}
+
+/// Kinds of [`AniError`].
+///
+/// Chunks are named by their fourcc, prefixed with their "LIST" subtype if
+/// they're a subchunk (e.g., "INFO/IENG"), the same as [`AniFile::skipped`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum AniErrorKind {
+    /// A fourcc other than the one required was found.
+    #[error("expected {expected:?}, instead got {found:?}")]
+    UnexpectedFourcc {
+        expected: &'static str,
+        found: String,
+    },
+    /// A chunk isn't known, and parsing isn't lenient.
+    #[error("unknown chunk={name:?}")]
+    UnknownChunk { name: String },
+    /// A chunk that can only appear once was repeated.
+    #[error("duplicate chunk={name:?}")]
+    DuplicateChunk { name: String },
+    /// The size of a chunk goes past the end of the file.
+    #[error("size={size} of chunk={name:?} extends beyond file")]
+    BeyondFile { name: String, size: u64 },
+    /// The size of a subchunk goes past the end of its "LIST".
+    #[error("size={size} of chunk={name:?} extends beyond its 'LIST'")]
+    BeyondList { name: String, size: u64 },
+    /// The size of a chunk is too small for its contents.
+    #[error("size={size} of chunk={name:?} is too small")]
+    TooSmall { name: String, size: u32 },
+    /// The size of a chunk exceeds [`Limits::max_chunk_size`](crate::cursors::decode::Limits::max_chunk_size).
+    #[error("size={size} of chunk={name:?} exceeds max_chunk_size={max}")]
+    ChunkTooLarge { name: String, size: u32, max: u32 },
+    /// There are more frames than [`Limits::max_frames`](crate::cursors::decode::Limits::max_frames).
+    #[error("num_frames={num_frames} exceeds max_frames={max}")]
+    TooManyFrames { num_frames: u64, max: u32 },
+    /// There are more steps than [`Limits::max_steps`](crate::cursors::decode::Limits::max_steps).
+    #[error("num_steps={num_steps} exceeds max_steps={max}")]
+    TooManySteps { num_steps: u32, max: u32 },
+    /// A chunk couldn't be parsed.
+    #[error("malformed chunk={name:?}")]
+    Malformed {
+        name: String,
+        #[source]
+        source: binrw::Error,
+    },
+    /// There are no frames, either from an empty or missing "fram" chunk.
+    #[error("no frames, 'fram' chunk is empty or missing")]
+    NoFrames,
+    /// A frame has no images, i.e, an ICO frame with no entries.
+    #[error("frame index={index} has no images")]
+    EmptyFrame { index: usize },
+    /// The number of frames doesn't match the header.
+    #[error("expected num_frames={expected}, instead got {found} frames")]
+    FrameCount { expected: u32, found: usize },
+    /// The number of rates doesn't match the header.
+    #[error("expected num_steps={expected}, instead got {found} rates")]
+    RateCount { expected: u32, found: usize },
+    /// There are multiple frames, but nothing says how long to show them.
+    #[error("no frame timings (>1 frames): jiffy_rate=0, and no 'rate' chunk")]
+    NoTimings,
+    /// An index in the "seq " chunk doesn't refer to a frame.
+    #[error("frame index={index} of 'seq ' chunk is out of bounds for num_frames={num_frames}")]
+    SequenceOutOfBounds { index: u32, num_frames: u32 },
+}
+
+impl AniError {
+    /// Creates an error at `offset` without a path.
+    #[must_use]
+    pub const fn new(offset: u64, kind: AniErrorKind) -> Self {
+        Self {
+            path: None,
+            offset,
+            kind,
+        }
+    }
+
+    /// Sets [`Self::path`] to `path`.
+    #[must_use]
+    pub fn with_path(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for AniError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset={:#x}", self.kind, self.offset)?;
+
+        if let Some(path) = &self.path {
+            write!(f, " of path={}", path.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for AniError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.kind.source()
+    }
+}
+
+/// Returns `fourcc` as a string, replacing invalid characters.
+fn fourcc_name(prefix: &str, fourcc: [u8; 4]) -> String {
+    format!("{prefix}{}", String::from_utf8_lossy(&fourcc))
+}
 
 /// RIFF chunk with [`Self::data`] as `Vec<u32>`.
 #[binread]
@@ -33,19 +159,53 @@ pub struct RiffChunkU32 {
     // no padding needed, data is inherently even (u32)
 }
 
-/// RIFF chunk with [`Self::data`] as [`Vec<u8>`].
-#[binread]
-#[derive(Debug)]
-#[br(little)]
-pub struct RiffChunkU8 {
-    // size == length here since `data` is Vec<u8>
-    #[br(temp)]
-    data_size: u32,
+/// Location of a RIFF chunk's data, used for frames (the "icon" subchunks).
+///
+/// Frames can be large, so they're only read when needed with [`Self::read`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameChunk {
+    /// Offset of the chunk data from the start of the file.
+    ///
+    /// Always past the chunk's fourcc and size, since it's only set when parsing.
+    offset: u64,
+    /// Size of the chunk data, excluding padding.
+    size: u32,
+}
 
-    /// The chunk data.
-    #[br(count = data_size, pad_after = data_size % 2)]
-    pub data: Vec<u8>,
-    // padding byte skipped with `pad_after`
+impl FrameChunk {
+    /// Returns the offset of the chunk data from the start of the file.
+    #[must_use]
+    pub const fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the size of the chunk data, excluding padding.
+    #[must_use]
+    pub const fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the offset of the chunk from the start of the file, rather than its data.
+    #[must_use]
+    pub const fn chunk_offset(&self) -> u64 {
+        // skips the fourcc and size
+        self.offset.saturating_sub(8)
+    }
+
+    /// Reads the chunk data from `reader`, which should be the file it was parsed from.
+    ///
+    /// ## Errors
+    ///
+    /// If seeking or reading from `reader` fails.
+    pub fn read<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<u8>> {
+        reader.seek(SeekFrom::Start(self.offset))?;
+        let mut data = vec![0; usize::try_from(self.size)?];
+        reader
+            .read_exact(&mut data)
+            .context("failed to read frame data")?;
+
+        Ok(data)
+    }
 }
 
 /// Contains possible flag combinations for [`AniHeader`].
@@ -58,28 +218,43 @@ pub struct RiffChunkU8 {
 /// #define AF_SEQUENCE 0x2     // Animation is sequenced.
 /// ```
 ///
-/// All frames must be in ICO format in order to store the required
-/// cursor metadata (e.g, hotspot), so these are invalid flags:
-///
-/// - `0`: no flags set
-/// - `2`: frames are not ICO
+/// Frames should be in ICO format in order to store the required cursor metadata
+/// (e.g, hotspot), but some older files store raw bitmaps (DIBs) instead, which
+/// is when `AF_ICON` is unset. These frames have no hotspot.
 #[derive(Debug, Default, PartialEq, BinRead)]
 #[br(little)]
 #[br(repr = u32)]
 enum AniFlags {
     // NOTE: this is storing the valid combinations of
     // bitflags and are not meant to be composable.
+    /// Contains raw bitmap frames that play in the
+    /// order they're defined (no "seq " chunk).
+    RawUnsequenced = 0,
     /// Contains ICO frames with a custom "seq " chunk,
     /// which defines the order frames should be played.
     ///
     /// This is mainly for optimizing repeated frames.
     #[default]
     Unsequenced = 1,
+    /// Contains raw bitmap frames with a custom "seq " chunk.
+    RawSequenced = 2,
     /// Contains ICO frames that play in the
     /// order they're defined (no "seq " chunk).
     Sequenced = 3,
 }
 
+impl AniFlags {
+    /// Returns true if `AF_SEQUENCE` is set.
+    const fn is_sequenced(&self) -> bool {
+        matches!(self, Self::Sequenced | Self::RawSequenced)
+    }
+
+    /// Returns true if `AF_ICON` is set.
+    const fn has_icon_frames(&self) -> bool {
+        matches!(self, Self::Unsequenced | Self::Sequenced)
+    }
+}
+
 /// Models an ANI file's header (or the "anih" chunk).
 ///
 /// ```text
@@ -133,6 +308,20 @@ pub struct AniHeader {
     flags: AniFlags,
 }
 
+impl AniHeader {
+    /// Returns true if frames are stored in ICO format, rather than as raw bitmaps.
+    #[must_use]
+    pub const fn has_icon_frames(&self) -> bool {
+        self.flags.has_icon_frames()
+    }
+
+    /// Returns true if frames are played in the order of a "seq " chunk.
+    #[must_use]
+    pub const fn is_sequenced(&self) -> bool {
+        self.flags.is_sequenced()
+    }
+}
+
 /// Models an ANI file.
 ///
 /// ```text
@@ -141,6 +330,9 @@ pub struct AniHeader {
 ///     [LIST('INFO'                   
 ///         [INAM(<ZSTR>)]  // Title.
 ///         [IART(<ZSTR>)]  // Author.
+///         [ICOP(<ZSTR>)]  // Copyright.
+///         [ICMT(<ZSTR>)]  // Comments.
+///         [ISFT(<ZSTR>)]  // Software used to create the file.
 ///     )]                             
 ///
 ///     'anih'(<ANIHEADER>)  // ANI file header.
@@ -176,6 +368,17 @@ pub struct AniFile {
     /// The author stored in the "INFO" ("LIST" subtype) chunk, with
     /// the identifier: "IART". Note that this is rarely present.
     pub author: Option<NullString>,
+    /// The copyright notice stored in the "INFO" chunk, with the identifier: "ICOP".
+    pub copyright: Option<NullString>,
+    /// Comments stored in the "INFO" chunk, with the identifier: "ICMT".
+    pub comments: Option<NullString>,
+    /// The software used to create the file, stored in the
+    /// "INFO" chunk with the identifier: "ISFT".
+    pub software: Option<NullString>,
+    /// Fourccs of unknown chunks that were skipped when parsing leniently.
+    ///
+    /// Subchunks of "LIST" chunks are prefixed with the list's subtype, e.g, "INFO/IENG".
+    pub skipped: Vec<String>,
     /// Per-frame timings. Usually [`None`].
     ///
     /// ## Explanation
@@ -204,7 +407,29 @@ pub struct AniFile {
     /// for supporting different sizes.
     ///
     /// _(although redundant, since Windows scales cursors already.)_
-    pub ico_frames: Vec<RiffChunkU8>,
+    ///
+    /// If `AF_ICON` is unset, these are raw bitmaps (DIBs) instead,
+    /// see [`AniHeader::has_icon_frames`] and [`crate::formats::dib`].
+    ///
+    /// Only the locations of frames are stored, see [`FrameChunk::read`].
+    pub ico_frames: Vec<FrameChunk>,
+    /// Where each chunk was found, so that errors can point to it.
+    pub offsets: ChunkOffsets,
+}
+
+/// Offsets of chunks from the start of an ANI file, see [`AniError::offset`].
+///
+/// Each is [`None`] if the chunk is missing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkOffsets {
+    /// Offset of the "anih" chunk.
+    pub header: Option<u64>,
+    /// Offset of the "rate" chunk.
+    pub rate: Option<u64>,
+    /// Offset of the "seq " chunk.
+    pub sequence: Option<u64>,
+    /// Offset of the "LIST" chunk with the "fram" subtype.
+    pub frames: Option<u64>,
 }
 
 // skip ico_frames
@@ -214,17 +439,28 @@ impl fmt::Debug for AniFile {
             .field("header", &self.header)
             .field("title", &self.title)
             .field("author", &self.author)
+            .field("copyright", &self.copyright)
+            .field("comments", &self.comments)
+            .field("software", &self.software)
+            .field("skipped", &self.skipped)
             .field("rate", &self.rate)
             .field("sequence", &self.sequence)
+            .field("offsets", &self.offsets)
             .finish_non_exhaustive()
     }
 }
 
 impl AniFile {
-    /// Max blob size for any (dynamic length) chunk.
-    const MAX_CHUNK_SIZE: usize = 2_097_152;
+    /// Parses `ani_blob`, see [`Self::from_reader`].
+    ///
+    /// ## Errors
+    ///
+    /// See [`Self::from_reader`].
+    pub fn from_blob(ani_blob: &[u8], options: &DecodeOptions) -> Result<Self> {
+        Self::from_reader(&mut Cursor::new(ani_blob), options)
+    }
 
-    /// Parses `ani_blob`.
+    /// Parses the ANI file in `reader`, which should be at the start of the file.
     ///
     /// This is pretty complicated to parse (matches on fourcc)
     /// because of the "constraint" (or more like freedom?)
@@ -233,90 +469,127 @@ impl AniFile {
     /// > [gdgsoft](https://www.gdgsoft.com/anituner/help/aniformat.htm):
     /// > "Any of the blocks ("ACON", "anih", "rate", or "seq ") can appear in any order."
     ///
+    /// Frames aren't read here, only their locations are, see [`FrameChunk::read`].
+    ///
+    /// If [`DecodeOptions::lenient`] is set, unknown chunks are skipped using their
+    /// size instead of failing, and are recorded in [`Self::skipped`].
+    ///
     /// ## Errors
     ///
     /// Parsing is quite tricky. There's a load of errors that can happen:
     ///
     /// - overflow on calculations
     /// - duplicate chunks
+    /// - unknown chunks (unless parsing leniently)
     /// - missing required chunks (e.g, no [`AniHeader`])
-    /// - blob lengths being unreasonably large (safety)
+    /// - chunk sizes, frame counts or step counts exceeding [`DecodeOptions::limits`] (safety)
     /// - more complex invariants not being met, see [`Self::check_invariants`]
-    pub fn from_blob(ani_blob: &[u8]) -> Result<Self> {
-        if ani_blob.len() > Self::MAX_CHUNK_SIZE {
-            bail!(
-                "ani_blob.len()={} unreasonably large (2MB+)",
-                ani_blob.len()
-            )
-        }
-
+    pub fn from_reader<R: Read + Seek>(reader: &mut R, options: &DecodeOptions) -> Result<Self> {
         // for sanity checks against read sizes
-        let ani_blob_len_u64 = u64::try_from(ani_blob.len())?;
+        let stream_len = reader.seek(SeekFrom::End(0))?;
+        reader.rewind()?;
+
         let mut ani = Self::default();
-        let mut cursor = Cursor::new(ani_blob);
         let mut buf = [0_u8; 4];
-        cursor.read_exact(&mut buf)?;
+        reader.read_exact(&mut buf)?;
 
         if buf != *b"RIFF" {
-            bail!("expected 'RIFF' chunk, instead got {buf:?}");
+            bail!(AniError::new(
+                0,
+                AniErrorKind::UnexpectedFourcc {
+                    expected: "RIFF",
+                    found: fourcc_name("", buf),
+                },
+            ));
         }
 
-        cursor.read_exact(&mut buf)?;
+        reader.read_exact(&mut buf)?;
         let riff_size = u32::from_le_bytes(buf);
 
         // NOTE: stricter checks like this fail on "valid" files
         // `riff_size == blob.len() - 8`
         // https://github.com/quantum5/win2xcur/commit/ac9552ce83d2955a96a4d7a5cfde7c113ec5a4c5
-        if u64::from(riff_size) > ani_blob_len_u64 {
-            bail!("riff_size={riff_size} extends beyond blob")
+        if u64::from(riff_size) > stream_len {
+            bail!(AniError::new(
+                0,
+                AniErrorKind::BeyondFile {
+                    name: String::from("RIFF"),
+                    size: u64::from(riff_size),
+                },
+            ));
         }
 
-        cursor.read_exact(&mut buf)?;
+        reader.read_exact(&mut buf)?;
 
         if buf != *b"ACON" {
-            bail!("expected 'ACON' as 'RIFF' subtype, instead got {buf:?}");
+            bail!(AniError::new(
+                8,
+                AniErrorKind::UnexpectedFourcc {
+                    expected: "ACON",
+                    found: fourcc_name("", buf),
+                },
+            ));
         }
 
         // read chunks and parse
-        while cursor.position() < ani_blob.len().try_into()? {
-            cursor.read_exact(&mut buf)?;
+        while reader.stream_position()? < stream_len {
+            let offset = reader.stream_position()?;
+            reader.read_exact(&mut buf)?;
 
             match &buf {
-                b"LIST" => Self::parse_list(&mut cursor, &mut ani)?,
+                b"LIST" => Self::parse_list(reader, stream_len, &mut ani, options)?,
                 b"anih" => {
-                    if ani.header != AniHeader::default() {
-                        bail!("duplicate 'anih' chunk");
+                    if ani.offsets.header.is_some() {
+                        bail!(Self::duplicate(offset, "anih"));
                     }
 
-                    ani.header =
-                        AniHeader::read_le(&mut cursor).context("failed to read 'anih' chunk")?;
+                    ani.offsets.header = Some(offset);
+                    ani.header = AniHeader::read_le(reader)
+                        .map_err(|e| Self::malformed(offset, "anih", e))?;
+
+                    Self::check_limits(&ani.header, offset, options)?;
                 }
 
                 b"rate" => {
                     if ani.rate.is_some() {
-                        bail!("duplicate 'rate' chunk");
+                        bail!(Self::duplicate(offset, "rate"));
                     }
 
+                    let size = Self::peek_size(reader, stream_len, "rate", options)?;
+                    Self::check_steps(size / 4, offset, options)?;
+                    ani.offsets.rate = Some(offset);
                     ani.rate = Some(
-                        RiffChunkU32::read_le(&mut cursor)
-                            .context("failed to read 'rate' chunk")?,
+                        RiffChunkU32::read_le(reader)
+                            .map_err(|e| Self::malformed(offset, "rate", e))?,
                     );
                 }
 
                 b"seq " => {
                     if ani.sequence.is_some() {
-                        bail!("duplicate 'seq ' chunk");
+                        bail!(Self::duplicate(offset, "seq "));
                     }
 
+                    let size = Self::peek_size(reader, stream_len, "seq ", options)?;
+                    Self::check_steps(size / 4, offset, options)?;
+                    ani.offsets.sequence = Some(offset);
                     ani.sequence = Some(
-                        RiffChunkU32::read_le(&mut cursor)
-                            .context("failed to read 'seq ' chunk")?,
+                        RiffChunkU32::read_le(reader)
+                            .map_err(|e| Self::malformed(offset, "seq ", e))?,
                     );
                 }
 
-                // consider attempting to read size and skipping
-                // for unknown chunks (but it's a bit unreliable)
-                _ => bail!("unexpected fourcc(?) buf={buf:?}"),
+                // this is a bit unreliable, since a bad size
+                // misaligns everything after it, hence opt-in
+                _ if options.lenient => {
+                    Self::skip_chunk(reader, None, stream_len, &mut ani, buf, "")?;
+                }
+
+                _ => bail!(AniError::new(
+                    offset,
+                    AniErrorKind::UnknownChunk {
+                        name: fourcc_name("", buf),
+                    },
+                )),
             }
         }
 
@@ -325,94 +598,137 @@ impl AniFile {
         Ok(ani)
     }
 
-    /// Helper for [`Self::from_blob`] for the "LIST" chunk.
+    /// Helper for [`AniErrorKind::DuplicateChunk`].
+    fn duplicate(offset: u64, name: &str) -> AniError {
+        AniError::new(
+            offset,
+            AniErrorKind::DuplicateChunk {
+                name: name.to_string(),
+            },
+        )
+    }
+
+    /// Helper for [`AniErrorKind::Malformed`].
+    fn malformed(offset: u64, name: &str, source: binrw::Error) -> AniError {
+        AniError::new(
+            offset,
+            AniErrorKind::Malformed {
+                name: name.to_string(),
+                source,
+            },
+        )
+    }
+
+    /// Helper for [`Self::from_reader`] for the "LIST" chunk.
     ///
     /// This can diverge depending on the subtype, which can
     /// either be "INFO" (title/author) or "fram" (frame data).
     ///
     /// The "INFO" chunk isn't required. The "fram" chunk is.
-    fn parse_list(cursor: &mut Cursor<&[u8]>, ani: &mut Self) -> Result<()> {
-        let ani_blob_size = cursor.get_ref().len();
+    fn parse_list<R: Read + Seek>(
+        reader: &mut R,
+        stream_len: u64,
+        ani: &mut Self,
+        options: &DecodeOptions,
+    ) -> Result<()> {
+        // the "LIST" fourcc was already read
+        let list_offset = reader.stream_position()? - 4;
         let mut buf = [0_u8; 4];
         let mut list_id = [0_u8; 4];
-        cursor.read_exact(&mut buf)?; // list size
-        cursor.read_exact(&mut list_id)?;
+        reader.read_exact(&mut buf)?; // list size
+        reader.read_exact(&mut list_id)?;
         let list_size = u32::from_le_bytes(buf);
+        let list_name = fourcc_name("LIST/", list_id);
 
         // excluding subtype fourcc (and padding)
-        let list_data_size = list_size
-            .checked_sub(4)
-            .with_context(|| format!("underflow on list_size={list_size} - 4"))?;
-
-        if usize::try_from(list_data_size)? > Self::MAX_CHUNK_SIZE {
-            bail!("list_data_size={list_data_size} unreasonably large (2MB+)");
-        }
-
-        let end = cursor
-            .position()
-            .checked_add(u64::from(list_data_size))
-            .with_context(|| {
-                format!(
-                    "overflow on cursor.position={} + list_data_size={list_data_size}",
-                    cursor.position()
-                )
-            })?;
-
-        if end > ani_blob_size.try_into()? {
-            bail!("list_data_size={list_data_size} extends beyond blob");
+        let Some(list_data_size) = list_size.checked_sub(4) else {
+            bail!(AniError::new(
+                list_offset,
+                AniErrorKind::TooSmall {
+                    name: list_name,
+                    size: list_size,
+                },
+            ));
+        };
+
+        let end = reader.stream_position()? + u64::from(list_data_size);
+
+        if end > stream_len {
+            bail!(AniError::new(
+                list_offset,
+                AniErrorKind::BeyondFile {
+                    name: list_name,
+                    size: u64::from(list_data_size),
+                },
+            ));
         }
 
         match &list_id {
             b"INFO" => {
-                while cursor.position() < end {
-                    cursor.read_exact(&mut buf)?;
-
-                    let field = if buf == *b"INAM" {
-                        &mut ani.title
-                    } else if buf == *b"IART" {
-                        &mut ani.author
-                    } else {
-                        bail!("expected 'INAM' or 'IART' subchunk in 'INFO', instead got {buf:?}");
+                while reader.stream_position()? < end {
+                    let offset = reader.stream_position()?;
+                    reader.read_exact(&mut buf)?;
+                    let name = fourcc_name("INFO/", buf);
+
+                    let field = match &buf {
+                        b"INAM" => &mut ani.title,
+                        b"IART" => &mut ani.author,
+                        b"ICOP" => &mut ani.copyright,
+                        b"ICMT" => &mut ani.comments,
+                        b"ISFT" => &mut ani.software,
+                        _ if options.lenient => {
+                            Self::skip_chunk(reader, Some(end), stream_len, ani, buf, "INFO/")?;
+                            continue;
+                        }
+                        _ => bail!(AniError::new(offset, AniErrorKind::UnknownChunk { name })),
                     };
 
                     if field.is_some() {
-                        bail!("duplicate 'INAM' or 'IART' subchunk in 'INFO'");
+                        bail!(Self::duplicate(offset, &name));
                     }
 
-                    // size of string
-                    cursor.read_exact(&mut buf)?;
-                    *field = Some(NullString::read_le(cursor)?);
-                }
-            }
-
-            b"fram" => {
-                if !ani.ico_frames.is_empty() {
-                    bail!("duplicate 'fram' chunk");
-                }
+                    // size of string, which is padded if odd
+                    let size = Self::read_size(reader, stream_len, &name, options)?;
 
-                let mut chunks = Vec::with_capacity(usize::try_from(ani.header.num_frames)?);
+                    // empty strings have nothing to read, not even a null terminator
+                    if size == 0 {
+                        continue;
+                    }
 
-                while cursor.position() < end {
-                    cursor.read_exact(&mut buf)?;
+                    let mut string = vec![0; usize::try_from(size)?];
+                    reader
+                        .read_exact(&mut string)
+                        .with_context(|| format!("failed to read chunk={name:?}"))?;
 
-                    if buf != *b"icon" {
-                        bail!("expected 'icon' subchunk, instead got {buf:?}");
+                    // strings aren't always null-terminated right at the end
+                    if let Some(null) = string.iter().position(|&b| b == 0) {
+                        string.truncate(null);
                     }
 
-                    let chunk = RiffChunkU8::read_le(cursor)
-                        .context("failed to read 'icon' subchunk of 'fram'")?;
-
-                    chunks.push(chunk);
+                    *field = Some(NullString(string));
+                    reader.seek_relative(i64::from(size % 2))?;
                 }
+            }
 
-                if chunks.is_empty() {
-                    bail!("failed to parse any frames from 'fram' chunk");
+            b"fram" => {
+                if ani.offsets.frames.is_some() {
+                    bail!(Self::duplicate(list_offset, &list_name));
                 }
 
-                ani.ico_frames = chunks;
+                ani.offsets.frames = Some(list_offset);
+                ani.ico_frames = Self::parse_frames(reader, stream_len, end, ani, options)?;
             }
 
-            _ => bail!("unexpected list_id={list_id:?}"),
+            _ if options.lenient => {
+                warn!("skipping unknown chunk={list_name:?}");
+                ani.skipped.push(list_name);
+                reader.seek(SeekFrom::Start(end))?;
+            }
+
+            _ => bail!(AniError::new(
+                list_offset,
+                AniErrorKind::UnknownChunk { name: list_name },
+            )),
         }
 
         // do NOT pad based on list_data_size here !!!
@@ -426,6 +742,202 @@ impl AniFile {
         Ok(())
     }
 
+    /// Helper for [`Self::parse_list`] for the "fram" chunk, which ends at `end`.
+    ///
+    /// Only the locations of frames are read, see [`FrameChunk`].
+    fn parse_frames<R: Read + Seek>(
+        reader: &mut R,
+        stream_len: u64,
+        end: u64,
+        ani: &mut Self,
+        options: &DecodeOptions,
+    ) -> Result<Vec<FrameChunk>> {
+        // the "LIST" chunk header and subtype were already read
+        let list_offset = reader.stream_position()? - 12;
+        let mut buf = [0_u8; 4];
+        let mut chunks = Vec::with_capacity(usize::try_from(ani.header.num_frames)?);
+
+        while reader.stream_position()? < end {
+            let offset = reader.stream_position()?;
+            reader.read_exact(&mut buf)?;
+
+            if buf != *b"icon" {
+                if options.lenient {
+                    Self::skip_chunk(reader, Some(end), stream_len, ani, buf, "fram/")?;
+                    continue;
+                }
+
+                bail!(AniError::new(
+                    offset,
+                    AniErrorKind::UnexpectedFourcc {
+                        expected: "icon",
+                        found: fourcc_name("fram/", buf),
+                    },
+                ));
+            }
+
+            let size = Self::read_size(reader, stream_len, "fram/icon", options)?;
+            let data_offset = reader.stream_position()?;
+
+            // padding byte is skipped too
+            reader.seek_relative(i64::from(size) + i64::from(size % 2))?;
+            chunks.push(FrameChunk {
+                offset: data_offset,
+                size,
+            });
+
+            if chunks.len() > usize::try_from(options.limits.max_frames)? {
+                bail!(AniError::new(
+                    offset,
+                    AniErrorKind::TooManyFrames {
+                        num_frames: chunks.len() as u64,
+                        max: options.limits.max_frames,
+                    },
+                ));
+            }
+        }
+
+        if chunks.is_empty() {
+            bail!(AniError::new(list_offset, AniErrorKind::NoFrames));
+        }
+
+        Ok(chunks)
+    }
+
+    /// Helper for [`Self::from_reader`] for reading the size of the chunk `name`.
+    ///
+    /// The chunk must fit in the file and within the limit in [`DecodeOptions::limits`],
+    /// so that nothing unreasonably large gets allocated for it.
+    fn read_size<R: Read + Seek>(
+        reader: &mut R,
+        stream_len: u64,
+        name: &str,
+        options: &DecodeOptions,
+    ) -> Result<u32> {
+        // the fourcc was already read
+        let offset = reader.stream_position()? - 4;
+        let mut buf = [0_u8; 4];
+        reader
+            .read_exact(&mut buf)
+            .with_context(|| format!("failed to read size of chunk={name:?}"))?;
+
+        let size = u32::from_le_bytes(buf);
+        let max = options.limits.max_chunk_size;
+        let name = name.to_string();
+
+        if size > max {
+            bail!(AniError::new(
+                offset,
+                AniErrorKind::ChunkTooLarge { name, size, max },
+            ));
+        }
+
+        // the padding byte of the last chunk is sometimes omitted
+        if reader.stream_position()? + u64::from(size) > stream_len {
+            bail!(AniError::new(
+                offset,
+                AniErrorKind::BeyondFile {
+                    name,
+                    size: u64::from(size),
+                },
+            ));
+        }
+
+        Ok(size)
+    }
+
+    /// Like [`Self::read_size`], but doesn't advance `reader`.
+    ///
+    /// This is for chunks that read their own size (e.g., [`RiffChunkU32`]).
+    fn peek_size<R: Read + Seek>(
+        reader: &mut R,
+        stream_len: u64,
+        name: &str,
+        options: &DecodeOptions,
+    ) -> Result<u32> {
+        let size = Self::read_size(reader, stream_len, name, options)?;
+        reader.seek_relative(-4)?;
+
+        Ok(size)
+    }
+
+    /// Helper for [`Self::from_reader`] for checking `header` against [`DecodeOptions::limits`].
+    ///
+    /// This is checked early, since frames and steps are allocated based on the header.
+    fn check_limits(header: &AniHeader, offset: u64, options: &DecodeOptions) -> Result<()> {
+        let max = options.limits.max_frames;
+
+        if header.num_frames > max {
+            bail!(AniError::new(
+                offset,
+                AniErrorKind::TooManyFrames {
+                    num_frames: u64::from(header.num_frames),
+                    max,
+                },
+            ));
+        }
+
+        Self::check_steps(header.num_steps, offset, options)
+    }
+
+    /// Helper for [`Self::from_reader`] for checking `num_steps` against [`DecodeOptions::limits`].
+    fn check_steps(num_steps: u32, offset: u64, options: &DecodeOptions) -> Result<()> {
+        let max = options.limits.max_steps;
+
+        if num_steps > max {
+            bail!(AniError::new(
+                offset,
+                AniErrorKind::TooManySteps { num_steps, max },
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Helper for [`Self::from_reader`] for skipping an unknown chunk, `fourcc`.
+    ///
+    /// This reads the chunk's size and seeks past it (and its padding). The skipped chunk
+    /// is recorded in [`Self::skipped`], with `prefix` to show which "LIST" it came from.
+    ///
+    /// Subchunks must fit within the end of their "LIST", `list_end`, so that a bad size
+    /// can't swallow the chunks after it. Otherwise, chunks must fit within the file.
+    fn skip_chunk<R: Read + Seek>(
+        reader: &mut R,
+        list_end: Option<u64>,
+        stream_len: u64,
+        ani: &mut Self,
+        fourcc: [u8; 4],
+        prefix: &str,
+    ) -> Result<()> {
+        let offset = reader.stream_position()? - 4;
+        let name = fourcc_name(prefix, fourcc);
+        let mut buf = [0_u8; 4];
+        reader
+            .read_exact(&mut buf)
+            .with_context(|| format!("failed to read size of unknown chunk={name:?}"))?;
+
+        let size = u32::from_le_bytes(buf);
+        let end = reader.stream_position()? + u64::from(size);
+
+        // the padding byte of the last chunk is sometimes omitted
+        if end > list_end.unwrap_or(stream_len) {
+            let size = u64::from(size);
+            let kind = if list_end.is_some() {
+                AniErrorKind::BeyondList { name, size }
+            } else {
+                AniErrorKind::BeyondFile { name, size }
+            };
+
+            bail!(AniError::new(offset, kind));
+        }
+
+        warn!("skipping unknown chunk={name:?} with size={size}");
+        reader.seek_relative(i64::from(size) + i64::from(size % 2))?;
+        ani.skipped.push(name);
+
+        Ok(())
+    }
+
     /// Helper function for checking invariants, since Clippy
     /// is complaining about my function body length :(
     ///
@@ -433,39 +945,58 @@ impl AniFile {
     /// This is a deliberate choice, as Windows still renders
     /// files that the spec technically considers invalid.
     fn check_invariants(ani: &Self) -> Result<()> {
-        use AniFlags::*;
-
         let hdr = &ani.header;
+        let offsets = &ani.offsets;
         let num_frames = usize::try_from(hdr.num_frames)?;
         let num_steps = usize::try_from(hdr.num_steps)?;
 
+        // only the start of the file is left if the header is missing too
+        let header_offset = offsets.header.unwrap_or_default();
+
+        if ani.ico_frames.is_empty() {
+            // the "fram" chunk is missing, since an empty one fails earlier
+            bail!(AniError::new(header_offset, AniErrorKind::NoFrames));
+        }
+
         if num_frames != ani.ico_frames.len() {
-            bail!(
-                "expected num_frames={num_frames}, instead got ico_frames.len()={}",
-                ani.ico_frames.len()
-            );
+            bail!(AniError::new(
+                offsets.frames.unwrap_or(header_offset),
+                AniErrorKind::FrameCount {
+                    expected: hdr.num_frames,
+                    found: ani.ico_frames.len(),
+                },
+            ));
         }
 
         if let Some(rate) = &ani.rate
             && rate.data.len() != num_steps
         {
-            bail!(
-                "expected num_steps={num_steps}, instead got rate.len()={}",
-                rate.data.len(),
-            )
+            bail!(AniError::new(
+                offsets.rate.unwrap_or(header_offset),
+                AniErrorKind::RateCount {
+                    expected: hdr.num_steps,
+                    found: rate.data.len(),
+                },
+            ));
         }
 
         if hdr.jiffy_rate == 0 && ani.rate.is_none() && ani.ico_frames.len() > 1 {
-            bail!("no frame timings (>1 frames): jiffy_rate=0, ani.rate=None");
+            bail!(AniError::new(header_offset, AniErrorKind::NoTimings));
         }
 
         if let Some(seq) = &ani.sequence
-            && seq.data.iter().max() >= Some(&hdr.num_frames)
+            && let Some(&index) = seq.data.iter().find(|&&i| i >= hdr.num_frames)
         {
-            bail!("frame indices of 'seq ' chunk go out of bounds");
+            bail!(AniError::new(
+                offsets.sequence.unwrap_or(header_offset),
+                AniErrorKind::SequenceOutOfBounds {
+                    index,
+                    num_frames: hdr.num_frames,
+                },
+            ));
         }
 
-        if hdr.flags == Sequenced && ani.sequence.is_none() {
+        if hdr.flags.is_sequenced() && ani.sequence.is_none() {
             warn!(
                 "expected 'seq ' chunk from flags={:?}, found None. the \
                 order in which frames were stored will be used instead",
@@ -474,7 +1005,7 @@ impl AniFile {
         }
 
         if let Some(seq) = &ani.sequence
-            && hdr.flags == Unsequenced
+            && !hdr.flags.is_sequenced()
             && seq.data != (0..hdr.num_steps).collect::<Vec<_>>()
         {
             warn!(
@@ -489,11 +1020,58 @@ impl AniFile {
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use std::fmt::Write;
 
     use super::*;
-    use crate::from_root;
+    use crate::{from_root, unwrap_err_as};
+
+    /// Returns a RIFF chunk with `fourcc` and `data`, padded if odd.
+    #[must_use]
+    #[expect(clippy::missing_panics_doc)]
+    pub fn chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+        chunk.extend_from_slice(data);
+
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+
+        chunk
+    }
+
+    /// Returns a "LIST" chunk with the subtype `list_id` containing `chunks`.
+    #[must_use]
+    pub fn list(list_id: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let data: Vec<u8> = list_id.iter().copied().chain(chunks.concat()).collect();
+        chunk(b"LIST", &data)
+    }
+
+    /// Returns an "anih" chunk with `num_frames`, `num_steps`, `jiffy_rate` and `flags`.
+    #[must_use]
+    pub fn anih(num_frames: u32, num_steps: u32, jiffy_rate: u32, flags: u32) -> Vec<u8> {
+        let mut data = 36_u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&num_frames.to_le_bytes());
+        data.extend_from_slice(&num_steps.to_le_bytes());
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&jiffy_rate.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+
+        chunk(b"anih", &data)
+    }
+
+    /// Returns an ANI file containing `chunks`.
+    #[must_use]
+    #[expect(clippy::missing_panics_doc)]
+    pub fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let data: Vec<u8> = b"ACON".iter().copied().chain(chunks.concat()).collect();
+        let mut riff = b"RIFF".to_vec();
+        riff.extend_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+        riff.extend(data);
+
+        riff
+    }
 
     /// Parses a file and checks everything matches expected results.
     // (sort of lazy but it's better than nothing)
@@ -504,14 +1082,21 @@ mod tests {
 
         const {
             assert!(
-                size_of::<AniFile>() == 136,
+                size_of::<AniFile>() == 296,
                 "AniFile fields have changed, update tests and this number accordingly"
             );
         }
 
-        let ani = AniFile::from_blob(ANI_BLOB).unwrap();
+        let ani = AniFile::from_blob(ANI_BLOB, &DecodeOptions::default()).unwrap();
         let hdr = &ani.header;
 
+        assert!(ani.title.is_none());
+        assert!(ani.author.is_none());
+        assert!(ani.copyright.is_none());
+        assert!(ani.comments.is_none());
+        assert!(ani.software.is_none());
+        assert!(ani.skipped.is_empty());
+
         assert_eq!(hdr.num_frames, 10);
         assert_eq!(hdr.num_steps, 21);
         assert_eq!(hdr.jiffy_rate, 6);
@@ -519,6 +1104,16 @@ mod tests {
 
         assert!(ani.rate.is_none());
 
+        assert_eq!(
+            ani.offsets,
+            ChunkOffsets {
+                header: Some(12),
+                rate: None,
+                sequence: Some(43008),
+                frames: Some(56),
+            }
+        );
+
         assert_eq!(
             ani.sequence.as_ref().unwrap().data,
             &[
@@ -539,9 +1134,199 @@ mod tests {
         let mut ani_frames = String::new();
 
         for frame in ani.ico_frames {
-            writeln!(&mut ani_frames, "{:?}", frame.data).unwrap();
+            let data = frame.read(&mut Cursor::new(ANI_BLOB)).unwrap();
+            writeln!(&mut ani_frames, "{data:?}").unwrap();
         }
 
         assert_eq!(ani_frames, ANI_FRAMES);
     }
+
+    /// Checks that errors have the right kind and offset.
+    #[test]
+    fn error_offsets() {
+        let parse = |blob: &[u8]| {
+            unwrap_err_as::<AniError>(AniFile::from_blob(blob, &DecodeOptions::default()))
+        };
+
+        let err = parse(b"RIFF\x04\0\0\0WAVE");
+        assert_eq!(err.offset, 8);
+        assert!(matches!(
+            err.kind,
+            AniErrorKind::UnexpectedFourcc { expected: "ACON", found } if found == "WAVE"
+        ));
+
+        let err = parse(b"RIFF\x0c\0\0\0ACONabcd\0\0\0\0");
+        assert_eq!(err.offset, 12);
+        assert!(matches!(err.kind, AniErrorKind::UnknownChunk { name } if name == "abcd"));
+
+        // invariants are checked after parsing, but still point to the offending chunk
+        let parse_chunks = |chunks: &[Vec<u8>], index: usize| {
+            let offset = 12 + chunks[..index].iter().map(Vec::len).sum::<usize>();
+            let err = parse(&riff(chunks));
+            assert_eq!(err.offset, offset as u64, "{}", err.kind);
+
+            err.kind
+        };
+
+        let rate = chunk(b"rate", &10_u32.to_le_bytes());
+        let frames = |n| list(b"fram", &vec![chunk(b"icon", b"frame"); n]);
+
+        let kind = parse_chunks(&[rate.clone(), anih(1, 1, 10, 1)], 1);
+        assert!(matches!(kind, AniErrorKind::NoFrames));
+
+        let kind = parse_chunks(&[anih(2, 2, 10, 1), frames(1)], 1);
+        assert!(matches!(
+            kind,
+            AniErrorKind::FrameCount {
+                expected: 2,
+                found: 1
+            }
+        ));
+
+        let kind = parse_chunks(&[anih(1, 2, 10, 1), frames(1), rate], 2);
+        assert!(matches!(
+            kind,
+            AniErrorKind::RateCount {
+                expected: 2,
+                found: 1
+            }
+        ));
+
+        let kind = parse_chunks(&[frames(2), anih(2, 2, 0, 1)], 1);
+        assert!(matches!(kind, AniErrorKind::NoTimings));
+
+        let sequence = chunk(b"seq ", &1_u32.to_le_bytes());
+        let kind = parse_chunks(&[anih(1, 1, 10, 3), sequence, frames(1)], 1);
+        assert!(matches!(
+            kind,
+            AniErrorKind::SequenceOutOfBounds {
+                index: 1,
+                num_frames: 1
+            }
+        ));
+    }
+
+    /// Parses "INFO" strings and unknown chunks, both strictly and leniently.
+    #[test]
+    fn info_and_unknown_chunks() {
+        let blob = riff(&[
+            list(
+                b"INFO",
+                &[
+                    chunk(b"INAM", b"Title\0"),
+                    chunk(b"ICOP", b"(c) someone\0"),
+                    // empty, so the next subchunk shouldn't be read as its string
+                    chunk(b"ICMT", b""),
+                    // not null-terminated at the end
+                    chunk(b"ISFT", b"editor\0\0\0"),
+                    chunk(b"IENG", b"engineer\0"),
+                ],
+            ),
+            chunk(b"abcd", b"odd"),
+            anih(1, 1, 10, 1),
+            list(b"fram", &[chunk(b"icon", b"frame")]),
+        ]);
+
+        let err = AniFile::from_blob(&blob, &DecodeOptions::default())
+            .unwrap_err()
+            .downcast::<AniError>()
+            .unwrap();
+
+        assert!(matches!(err.kind, AniErrorKind::UnknownChunk { name } if name == "INFO/IENG"));
+
+        let options = DecodeOptions {
+            lenient: true,
+            ..DecodeOptions::default()
+        };
+
+        let ani = AniFile::from_blob(&blob, &options).unwrap();
+        let string = |s: &Option<NullString>| s.as_ref().map(ToString::to_string);
+
+        assert_eq!(string(&ani.title).as_deref(), Some("Title"));
+        assert_eq!(string(&ani.author), None);
+        assert_eq!(string(&ani.copyright).as_deref(), Some("(c) someone"));
+        assert_eq!(string(&ani.comments), None);
+        assert_eq!(string(&ani.software).as_deref(), Some("editor"));
+        assert_eq!(ani.skipped, ["INFO/IENG", "abcd"]);
+
+        let frame = ani.ico_frames[0].read(&mut Cursor::new(&blob)).unwrap();
+        assert_eq!(frame, b"frame");
+
+        // the size of "IENG" would swallow "anih" and "fram" if only bounded by the file
+        let mut info = list(b"INFO", &[chunk(b"IENG", b"engineer\0")]);
+        let info_len = info.len();
+        info[info_len - 14..info_len - 10].copy_from_slice(&60_u32.to_le_bytes());
+
+        let blob = riff(&[
+            info,
+            anih(1, 1, 10, 1),
+            list(b"fram", &[chunk(b"icon", b"frame")]),
+        ]);
+        // the data of "IENG" starts at 32, so it still fits in the file
+        assert!(blob.len() >= 32 + 60);
+
+        let err = AniFile::from_blob(&blob, &options)
+            .unwrap_err()
+            .downcast::<AniError>()
+            .unwrap();
+        assert_eq!(err.offset, 24);
+        assert!(matches!(
+            err.kind,
+            AniErrorKind::BeyondList { name, size: 60 } if name == "INFO/IENG"
+        ));
+    }
+
+    /// Checks the frame and step limits at their limits, and one over.
+    #[test]
+    fn frame_and_step_limits() {
+        let mut options = DecodeOptions::default();
+        options.limits.max_frames = 2;
+        options.limits.max_steps = 3;
+
+        let parse = |num_frames: u32, sequence: &[u32]| {
+            let frames = vec![chunk(b"icon", b"frame"); usize::try_from(num_frames).unwrap()];
+            let sequence: Vec<u8> = sequence.iter().flat_map(|i| i.to_le_bytes()).collect();
+            let num_steps = u32::try_from(sequence.len() / 4).unwrap();
+
+            AniFile::from_blob(
+                &riff(&[
+                    anih(num_frames, num_steps, 10, 3),
+                    chunk(b"seq ", &sequence),
+                    list(b"fram", &frames),
+                ]),
+                &options,
+            )
+            .map_err(|e| e.downcast::<AniError>().unwrap().kind)
+        };
+
+        assert!(parse(2, &[0, 1, 0]).is_ok());
+        assert!(matches!(
+            parse(3, &[0, 1, 2]),
+            Err(AniErrorKind::TooManyFrames {
+                num_frames: 3,
+                max: 2
+            })
+        ));
+        assert!(matches!(
+            parse(2, &[0, 1, 0, 1]),
+            Err(AniErrorKind::TooManySteps {
+                num_steps: 4,
+                max: 3
+            })
+        ));
+
+        // frames are also counted in "fram", in case the header is wrong or comes after
+        let blob = riff(&[
+            list(b"fram", &vec![chunk(b"icon", b"frame"); 3]),
+            anih(3, 3, 10, 1),
+        ]);
+        let err = AniFile::from_blob(&blob, &options).unwrap_err();
+        assert!(matches!(
+            err.downcast::<AniError>().unwrap().kind,
+            AniErrorKind::TooManyFrames {
+                num_frames: 3,
+                max: 2
+            }
+        ));
+    }
 }
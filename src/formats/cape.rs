@@ -0,0 +1,319 @@
+//! Module used to write Mousecape (macOS) capes from [`CursorTheme`].
+//!
+//! A cape is a property list with some metadata and a "Cursors" dictionary,
+//! which maps Apple cursor identifiers to a cursor. Each cursor stores:
+//!
+//! 1) The hotspot and size, in _points_ rather than pixels.
+//! 2) The frame count and a single frame duration (in seconds).
+//! 3) The "Representations", which are PNG images for each scale (only 1x and 2x).
+//!    Animated cursors have their frames stacked vertically in one image.
+//!
+//! ## References
+//!
+//! - [Mousecape](https://github.com/alexzielenski/Mousecape)
+
+use crate::{
+    cursors::{cursor_image::CursorImages, generic_cursor::GenericCursor},
+    fs_utils::write_atomic,
+    themes::theme::{CursorTheme, CursorType},
+};
+
+use std::{io::Write, path::Path};
+
+use anyhow::{Context, Result};
+use ico::IconImage;
+use log::warn;
+use plist::{Dictionary, Value};
+
+/// Versions numbers of the cape format.
+mod versions {
+    pub const CAPE: f64 = 1.0;
+    pub const MINIMUM: f64 = 2.0;
+    pub const FORMAT: f64 = 2.0;
+}
+
+/// Returns the Apple cursor identifiers that `r#type` replaces.
+///
+/// Unlike [`crate::themes::symlinks`], there's no "concrete" cursor here; each
+/// identifier is a separate entry in the cape. Some types have no equivalent.
+const fn get_identifiers(r#type: &CursorType) -> &'static [&'static str] {
+    use CursorType::*;
+
+    match r#type {
+        Arrow => &[
+            "com.apple.coregraphics.Arrow",
+            "com.apple.coregraphics.Move",
+        ],
+        Hand => &["com.apple.cursor.2", "com.apple.cursor.13"],
+        Watch => &["com.apple.coregraphics.Wait"],
+        LeftPtrWatch => &["com.apple.cursor.4"],
+        Help => &["com.apple.cursor.40"],
+        Text => &[
+            "com.apple.coregraphics.IBeam",
+            "com.apple.coregraphics.IBeamXOR",
+        ],
+        Crosshair => &["com.apple.cursor.7", "com.apple.cursor.8"],
+        Forbidden => &["com.apple.cursor.3"],
+        NsResize => &[
+            "com.apple.cursor.21",
+            "com.apple.cursor.22",
+            "com.apple.cursor.23",
+            "com.apple.cursor.31",
+            "com.apple.cursor.32",
+            "com.apple.cursor.36",
+        ],
+        EwResize => &[
+            "com.apple.cursor.17",
+            "com.apple.cursor.18",
+            "com.apple.cursor.19",
+            "com.apple.cursor.27",
+            "com.apple.cursor.28",
+            "com.apple.cursor.38",
+        ],
+        NwseResize => &[
+            "com.apple.cursor.33",
+            "com.apple.cursor.34",
+            "com.apple.cursor.35",
+        ],
+        NeswResize => &[
+            "com.apple.cursor.29",
+            "com.apple.cursor.30",
+            "com.apple.cursor.37",
+        ],
+        Move => &[
+            "com.apple.cursor.11",
+            "com.apple.cursor.12",
+            "com.apple.cursor.39",
+        ],
+        // no macOS equivalents
+        Pencil | CenterPtr => &[],
+    }
+}
+
+/// Models a Mousecape cape.
+#[derive(Debug)]
+pub struct Cape {
+    /// The root dictionary of the property list.
+    inner: Value,
+}
+
+impl Cape {
+    /// Converts `theme` to a cape named `name`.
+    ///
+    /// ## Errors
+    ///
+    /// If PNG encoding fails for any of the cursors' representations.
+    pub fn new(theme: &CursorTheme, name: &str) -> Result<Self> {
+        let mut cursors = Dictionary::new();
+        let mut hidpi = false;
+
+        for (r#type, cursor) in theme.cursors() {
+            let identifiers = get_identifiers(r#type);
+
+            if identifiers.is_empty() {
+                warn!("no macOS equivalent for type={type:?}, skipping");
+                continue;
+            }
+
+            let retina = Self::retina_images(cursor);
+            hidpi |= retina.is_some();
+
+            let entry = Self::cursor_entry(cursor, retina)
+                .with_context(|| format!("while converting type={type:?} to cape"))?;
+
+            for id in identifiers {
+                cursors.insert((*id).to_string(), entry.clone());
+            }
+        }
+
+        let sanitized: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+
+        let mut inner = Dictionary::new();
+        inner.insert("Author".into(), Value::String("currust".into()));
+        inner.insert("CapeName".into(), Value::String(name.to_string()));
+        inner.insert("CapeVersion".into(), Value::Real(versions::CAPE));
+        inner.insert("Cloud".into(), Value::Boolean(false));
+        inner.insert("Cursors".into(), Value::Dictionary(cursors));
+        inner.insert("HiDPI".into(), Value::Boolean(hidpi));
+        inner.insert(
+            "Identifier".into(),
+            Value::String(format!("local.currust.{sanitized}")),
+        );
+        inner.insert("MinimumVersion".into(), Value::Real(versions::MINIMUM));
+        inner.insert("Version".into(), Value::Real(versions::FORMAT));
+
+        Ok(Self {
+            inner: Value::Dictionary(inner),
+        })
+    }
+
+    /// Helper function for [`Self::new`].
+    ///
+    /// Converts `cursor` to a single entry of the "Cursors" dictionary,
+    /// with `retina` as its 2x representation, see [`Self::retina_images`].
+    fn cursor_entry(cursor: &GenericCursor, retina: Option<&CursorImages>) -> Result<Value> {
+        let base = cursor.base_images();
+        let first = base.first();
+        let (points_wide, points_high) = first.dimensions();
+        let (hotspot_x, hotspot_y) = first.hotspot();
+
+        // capes only store one duration, unlike ANI/Xcursor
+        let delay = first.delay();
+        if base.inner().iter().any(|image| image.delay() != delay) {
+            warn!("cape can't store per-frame delays, using first delay={delay}ms for all");
+        }
+
+        let representations: Vec<Value> = std::iter::once(base)
+            .chain(retina)
+            .map(|images| Self::stacked_png(images).map(Value::Data))
+            .collect::<Result<_>>()?;
+
+        let mut entry = Dictionary::new();
+        entry.insert(
+            "FrameCount".into(),
+            Value::Integer(u64::try_from(base.len())?.into()),
+        );
+        entry.insert(
+            "FrameDuration".into(),
+            Value::Real(f64::from(delay) / 1000.0),
+        );
+        entry.insert("HotSpotX".into(), Value::Real(f64::from(hotspot_x)));
+        entry.insert("HotSpotY".into(), Value::Real(f64::from(hotspot_y)));
+        entry.insert("PointsHigh".into(), Value::Real(f64::from(points_high)));
+        entry.insert("PointsWide".into(), Value::Real(f64::from(points_wide)));
+        entry.insert("Representations".into(), Value::Array(representations));
+
+        Ok(Value::Dictionary(entry))
+    }
+
+    /// Helper function for [`Self::new`].
+    ///
+    /// Returns the 2x images of `cursor`, if any. Mousecape only defines 1x and 2x
+    /// representations, so other scales (e.g., 1.5x or 3x) are skipped with a warning.
+    fn retina_images(cursor: &GenericCursor) -> Option<&CursorImages> {
+        let base_size = cursor.base_images().first().nominal_size();
+        let mut retina = None;
+
+        for images in cursor.scaled_images() {
+            let size = images.first().nominal_size();
+
+            if size == base_size * 2 && retina.is_none() {
+                retina = Some(images);
+            } else {
+                warn!("cape only stores 1x and 2x, skipping {size}px images (1x is {base_size}px)");
+            }
+        }
+
+        retina
+    }
+
+    /// Encodes `images` as one PNG, with each frame stacked vertically.
+    fn stacked_png(images: &CursorImages) -> Result<Vec<u8>> {
+        let (width, height) = images.first().dimensions();
+        let num_frames = u32::try_from(images.len())?;
+
+        // frames share dimensions, so rows line up when concatenated
+        let rgba: Vec<u8> = images
+            .inner()
+            .iter()
+            .flat_map(|image| image.rgba().iter().copied())
+            .collect();
+
+        let image = IconImage::from_rgba_data(width, height * num_frames, rgba);
+        let mut png = Vec::new();
+        image.write_png(&mut png)?;
+
+        Ok(png)
+    }
+
+    /// Writes `self` to `path` as an XML property list.
+    ///
+    /// This is written atomically, so `path` is never left partially written.
+    ///
+    /// ## Errors
+    ///
+    /// If filesystem operations fail.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_atomic(path.as_ref(), |writer| self.write(writer))
+    }
+
+    /// Writes `self` to `writer` as an XML property list.
+    ///
+    /// ## Errors
+    ///
+    /// If writing fails.
+    pub fn write<W: Write>(&self, writer: W) -> Result<()> {
+        self.inner.to_writer_xml(writer)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cursors::{decode::Limits, generic_cursor::tests::black_and_white},
+        themes::theme::TypedCursor,
+    };
+
+    use fast_image_resize::{FilterType, ResizeAlg};
+
+    /// Writes a theme as a cape and checks the property list read back from it.
+    #[test]
+    fn write_cape() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cursors = vec![
+            TypedCursor::new(black_and_white(), CursorType::Arrow, "arrow.ani".into()),
+            TypedCursor::new(black_and_white(), CursorType::Pencil, "pencil.ani".into()),
+        ];
+
+        let mut theme = CursorTheme::new(cursors, "My Theme".into())?;
+        let algorithm = ResizeAlg::Convolution(FilterType::Box);
+        theme.add_scale(1.5, algorithm, &Limits::default())?;
+        theme.add_scale(2.0, algorithm, &Limits::default())?;
+        theme.save_as_cape(dir.path())?;
+
+        let cape = Value::from_file(dir.path().join("My Theme.cape"))?;
+        let cape = cape.as_dictionary().unwrap();
+        assert_eq!(cape["CapeName"].as_string(), Some("My Theme"));
+        assert_eq!(
+            cape["Identifier"].as_string(),
+            Some("local.currust.My-Theme")
+        );
+        assert_eq!(cape["HiDPI"].as_boolean(), Some(true));
+
+        // pencils have no identifiers, and arrows have two
+        let cursors = cape["Cursors"].as_dictionary().unwrap();
+        let ids: Vec<_> = cursors.keys().map(String::as_str).collect();
+        assert_eq!(ids, get_identifiers(&CursorType::Arrow));
+        assert_eq!(
+            cursors["com.apple.coregraphics.Arrow"],
+            cursors["com.apple.coregraphics.Move"]
+        );
+
+        let arrow = cursors["com.apple.coregraphics.Arrow"]
+            .as_dictionary()
+            .unwrap();
+        assert_eq!(arrow["FrameCount"].as_unsigned_integer(), Some(10));
+        assert_eq!(arrow["FrameDuration"].as_real(), Some(0.1));
+        assert_eq!(arrow["PointsWide"].as_real(), Some(32.0));
+        assert_eq!(arrow["PointsHigh"].as_real(), Some(32.0));
+
+        // frames are stacked vertically, once for each scale (without 1.5x)
+        let representations = arrow["Representations"].as_array().unwrap();
+        let sizes: Vec<_> = representations
+            .iter()
+            .map(|png| {
+                ico::IconImage::read_png(png.as_data().unwrap()).map(|i| (i.width(), i.height()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(sizes, [(32, 320), (64, 640)]);
+
+        Ok(())
+    }
+}
@@ -2,15 +2,13 @@
 //!
 //! Pretty sure CRS is just TOML with required sections.
 
-use crate::{
-    themes::theme::{CursorMapping, CursorType},
-    warn,
-};
+use crate::themes::theme::{CursorMapping, CursorType};
 
 use std::{fs, path::Path};
 
 use anyhow::{Result, anyhow, bail};
 use configparser::ini::Ini;
+use log::warn;
 
 /// Section names in CRS files.
 fn section_to_type(section: &str) -> Option<CursorType> {
@@ -0,0 +1,181 @@
+//! Module for detecting the format of cursor files from their contents.
+//!
+//! Files are identified by their signature (or "magic bytes"), so that misnamed
+//! files (e.g., an ANI file named "arrow.cur") are still read correctly. The
+//! extension is only used as a fallback, when the signature isn't recognised.
+//!
+//! ```text
+//! ANI      "RIFF" <size: 4 bytes> "ACON"
+//! CUR      00 00 02 00 <count: 2 bytes>
+//! ICO      00 00 01 00 <count: 2 bytes>
+//! Xcursor  "Xcur"
+//! PNG      89 "PNG" 0D 0A 1A 0A
+//! GIF      "GIF87a" or "GIF89a"
+//! BMP      "BM" <size, reserved, offset: 12 bytes> <info header size: 4 bytes>
+//! ```
+
+use crate::formats::registry::Registry;
+
+use std::{ffi::OsStr, fs::File, io::Read, path::Path};
+
+use anyhow::{Context, Result};
+
+/// Formats of files that may contain cursors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Format {
+    /// Windows animated cursor.
+    Ani,
+    /// Windows static cursor.
+    Cur,
+    /// Windows icon, which is a cursor without a hotspot.
+    Ico,
+    /// X11 cursor.
+    Xcursor,
+    /// PNG image.
+    Png,
+    /// GIF image, which isn't read as a cursor.
+    Gif,
+    /// BMP image.
+    Bmp,
+}
+
+impl Format {
+    /// Detects the format from the first bytes of a file, `header`.
+    ///
+    /// Returns [`None`] if the signature isn't recognised, or `header` is too short.
+    #[must_use]
+    pub fn sniff(header: &[u8]) -> Option<Self> {
+        match header {
+            [
+                b'R',
+                b'I',
+                b'F',
+                b'F',
+                _,
+                _,
+                _,
+                _,
+                b'A',
+                b'C',
+                b'O',
+                b'N',
+                ..,
+            ] => Some(Self::Ani),
+            // a count of zero is unlikely to be an icon directory
+            [0, 0, 2, 0, count @ ..] if Self::has_entries(count) => Some(Self::Cur),
+            [0, 0, 1, 0, count @ ..] if Self::has_entries(count) => Some(Self::Ico),
+            [b'X', b'c', b'u', b'r', ..] => Some(Self::Xcursor),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(Self::Png),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::Gif),
+            // "BM" alone is too common, so the size of the info header is checked too
+            [b'B', b'M', _, _, _, _, _, _, _, _, _, _, _, _, size @ ..]
+                if Self::is_dib_header_size(size) =>
+            {
+                Some(Self::Bmp)
+            }
+            _ => None,
+        }
+    }
+
+    /// Helper function for [`Self::sniff`].
+    fn has_entries(count: &[u8]) -> bool {
+        matches!(count, [lo, hi, ..] if u16::from_le_bytes([*lo, *hi]) != 0)
+    }
+
+    /// Helper function for [`Self::sniff`].
+    ///
+    /// Returns true if `size` starts with the size of a known DIB header (e.g., 40 for
+    /// `BITMAPINFOHEADER`), from `BITMAPCOREHEADER` up to `BITMAPV5HEADER`.
+    fn is_dib_header_size(size: &[u8]) -> bool {
+        matches!(size, [a, b, c, d, ..]
+            if matches!(u32::from_le_bytes([*a, *b, *c, *d]), 12 | 40 | 52 | 56 | 64 | 108 | 124))
+    }
+
+    /// Returns true if files of this format can be converted to cursors.
+    ///
+    /// GIF images are only detected, not decoded, and Xcursor is only ever an output.
+    #[must_use]
+    pub const fn is_cursor(self) -> bool {
+        matches!(
+            self,
+            Self::Ani | Self::Cur | Self::Ico | Self::Png | Self::Bmp
+        )
+    }
+
+    /// Detects the format from the extension `ext` (case-insensitively).
+    #[must_use]
+    pub fn from_extension(ext: &OsStr) -> Option<Self> {
+        let ext = ext.to_ascii_lowercase();
+
+        let format = match ext.to_str()? {
+            "ani" => Self::Ani,
+            "cur" => Self::Cur,
+            "ico" => Self::Ico,
+            "png" => Self::Png,
+            "gif" => Self::Gif,
+            "bmp" => Self::Bmp,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    /// Detects the format of the file at `path`.
+    ///
+    /// The contents are checked first, with [`Self::sniff`]. If the signature isn't
+    /// recognised, the extension is used instead. Returns [`None`] if neither are.
+    ///
+    /// Note that the contents take priority, even if they don't match the extension.
+    ///
+    /// ## Errors
+    ///
+    /// If `path` can't be opened or read.
+    pub fn detect(path: &Path) -> Result<Option<Self>> {
+        let header = Self::read_header(path)?;
+
+        Ok(Self::sniff(&header).or_else(|| path.extension().and_then(Self::from_extension)))
+    }
+
+    /// Reads the first [`Registry::HEADER_LEN`] bytes of the file at `path`, or
+    /// fewer if the file is shorter. This is shared with [`Registry::detect`], so
+    /// both see the same bytes.
+    ///
+    /// ## Errors
+    ///
+    /// If `path` can't be opened or read.
+    pub fn read_header(path: &Path) -> Result<Vec<u8>> {
+        let mut header = Vec::with_capacity(Registry::HEADER_LEN);
+        File::open(path)
+            .and_then(|f| f.take(Registry::HEADER_LEN as u64).read_to_end(&mut header))
+            .with_context(|| format!("(filesystem) failed to read path={}", path.display()))?;
+
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks signatures of each format, and some near misses.
+    #[test]
+    fn sniff_signatures() {
+        use Format::*;
+
+        assert_eq!(Format::sniff(b"RIFF\x10\0\0\0ACONanih"), Some(Ani));
+        assert_eq!(Format::sniff(b"RIFF\x10\0\0\0WAVEfmt "), None);
+        assert_eq!(Format::sniff(&[0, 0, 2, 0, 1, 0, 32, 32]), Some(Cur));
+        assert_eq!(Format::sniff(&[0, 0, 1, 0, 3, 0, 16, 16]), Some(Ico));
+        assert_eq!(Format::sniff(&[0, 0, 1, 0, 0, 0, 0, 0]), None);
+        assert_eq!(Format::sniff(b"Xcur\x10\0\0\0"), Some(Xcursor));
+        assert_eq!(Format::sniff(b"\x89PNG\r\n\x1a\n\0\0\0\x0d"), Some(Png));
+        assert_eq!(Format::sniff(b"GIF89a\x20\0"), Some(Gif));
+        assert_eq!(
+            Format::sniff(b"BM\x36\x10\0\0\0\0\0\0\x36\0\0\0\x28\0\0\0"),
+            Some(Bmp)
+        );
+        assert_eq!(Format::sniff(b"BM\x36\x10\0\0"), None);
+        assert_eq!(Format::sniff(b"BMP files are bitmaps"), None);
+        assert_eq!(Format::sniff(b"RIF"), None);
+    }
+}
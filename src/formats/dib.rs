@@ -0,0 +1,460 @@
+//! Module for parsing DIBs (device-independent bitmaps), as stored in ICO/CUR entries.
+//!
+//! Decoding is mostly left to [`ico`], but it hides some details that are needed for
+//! handling older cursors (e.g., the bit depth of cursor entries, or the AND mask).
+//!
+//! A DIB stored in an ICO/CUR entry is laid out as such:
+//!
+//! 1) The `BITMAPINFOHEADER`, modeled by [`DibHeader`].
+//! 2) The color table, for bit depths of 8 or less.
+//! 3) The XOR (color) bitmap, stored bottom-up.
+//! 4) The AND (transparency) mask, which is 1 bit per pixel, also stored bottom-up.
+//!
+//! Each row of both bitmaps is padded to a multiple of four bytes.
+//!
+//! ## References
+//!
+//! - [BITMAPINFOHEADER](https://learn.microsoft.com/en-us/windows/win32/api/wingdi/ns-wingdi-bitmapinfoheader)
+//! - [The evolution of the ICO file format](https://devblogs.microsoft.com/oldnewthing/20101018-00/?p=12513)
+
+use std::io::Cursor;
+
+use anyhow::{Context, Result, bail};
+use binrw::BinRead;
+use ico::IconDirEntry;
+
+/// Models the `BITMAPINFOHEADER` struct.
+///
+/// ```text
+/// typedef struct {
+///     DWORD biSize;           // Size of this header, usually 40.
+///     LONG  biWidth;
+///     LONG  biHeight;         // Doubled for ICO/CUR (XOR + AND bitmaps).
+///     WORD  biPlanes;         // Always 1.
+///     WORD  biBitCount;       // Bits per pixel.
+///     DWORD biCompression;
+///     DWORD biSizeImage;
+///     LONG  biXPelsPerMeter;  // Not used.
+///     LONG  biYPelsPerMeter;  // Not used.
+///     DWORD biClrUsed;
+///     DWORD biClrImportant;   // Not used.
+/// } BITMAPINFOHEADER;
+/// ```
+#[derive(Debug, BinRead)]
+#[br(little)]
+pub struct DibHeader {
+    /// Size of the header, which may be larger for newer versions.
+    pub header_size: u32,
+    /// Width in pixels.
+    pub width: i32,
+    /// Height in pixels, which is doubled for ICO/CUR entries.
+    pub height: i32,
+    /// Number of color planes. Should always be 1.
+    pub planes: u16,
+    /// Bits per pixel, i.e, the color depth.
+    pub bit_count: u16,
+    /// Compression used. Only `BI_RGB` (0) and `BI_BITFIELDS` (3) are expected.
+    pub compression: u32,
+    /// Size of the image data, which may be zero for uncompressed images.
+
+    // skips unused fields: biXPelsPerMeter, biYPelsPerMeter
+    #[br(pad_after = 8)]
+    pub image_size: u32,
+
+    /// Number of colors used in the color table, or zero for the maximum.
+    pub colors_used: u32,
+    /// Number of important colors. Not used.
+    pub colors_important: u32,
+}
+
+impl DibHeader {
+    /// Reads the header at the start of `dib`.
+    ///
+    /// ## Errors
+    ///
+    /// If `dib` is too short to contain the header.
+    pub fn read(dib: &[u8]) -> Result<Self> {
+        Self::read_le(&mut Cursor::new(dib)).context("failed to read DIB header")
+    }
+
+    /// Returns the dimensions as (width, height), where height isn't doubled.
+    ///
+    /// ## Errors
+    ///
+    /// If the width or (halved) height isn't positive.
+    pub fn dimensions(&self) -> Result<(u32, u32)> {
+        // negative heights (top-down bitmaps) aren't valid for icons
+        let (width, height) = (self.width, self.height / 2);
+
+        if width <= 0 || height <= 0 {
+            bail!("invalid DIB dimensions, width={width}, height={height}");
+        }
+
+        Ok((width.unsigned_abs(), height.unsigned_abs()))
+    }
+
+    /// Returns the offset of the XOR bitmap, i.e, the size of the header and color table.
+    ///
+    /// ## Errors
+    ///
+    /// If the offset overflows (e.g., from a huge `colors_used`), or [`TryInto`] conversions fail.
+    pub fn xor_offset(&self) -> Result<usize> {
+        // BI_BITFIELDS stores three color masks after the (original) header
+        const BI_BITFIELDS: u32 = 3;
+
+        let num_colors = match (self.colors_used, self.bit_count) {
+            (0, bits @ 1..=8) => 1 << bits,
+            (n, 1..=8) => n,
+            _ => 0,
+        };
+
+        let masks_size = if self.compression == BI_BITFIELDS && self.header_size == 40 {
+            12
+        } else {
+            0
+        };
+
+        // `colors_used` isn't trusted, so this may overflow
+        let Some(offset) = num_colors
+            .checked_mul(4)
+            .and_then(|size| size.checked_add(self.header_size))
+            .and_then(|size| size.checked_add(masks_size))
+        else {
+            bail!(
+                "invalid DIB color table, header_size={}, colors_used={}",
+                self.header_size,
+                self.colors_used
+            );
+        };
+
+        Ok(usize::try_from(offset)?)
+    }
+
+    /// Reads the AND mask of `dib`, where `true` means the pixel is transparent.
+    ///
+    /// Unlike how it's stored, this is in row-major order starting from the _top_ row.
+    ///
+    /// ## Errors
+    ///
+    /// If `dib` is too short to contain the mask, or [`TryInto`] conversions fail.
+    pub fn read_and_mask(&self, dib: &[u8]) -> Result<Vec<bool>> {
+        let (width, height) = self.dimensions()?;
+        let (width, height) = (usize::try_from(width)?, usize::try_from(height)?);
+        let bit_count = usize::from(self.bit_count);
+
+        // rows are padded to multiples of four bytes
+        let xor_stride = (width * bit_count).div_ceil(32) * 4;
+        let and_stride = width.div_ceil(32) * 4;
+        let and_offset = self.xor_offset()? + xor_stride * height;
+
+        let Some(and_mask) = dib.get(and_offset..and_offset + and_stride * height) else {
+            bail!(
+                "DIB of len={} too short for AND mask at offset={and_offset}",
+                dib.len()
+            );
+        };
+
+        let mask = (0..height)
+            .rev() // bottom-up
+            .flat_map(|row| {
+                let row = &and_mask[row * and_stride..];
+                (0..width).map(move |x| (row[x / 8] >> (7 - x % 8)) & 1 == 1)
+            })
+            .collect();
+
+        Ok(mask)
+    }
+}
+
+/// Returns the bit depth of `entry`, for choosing between entries of the same size.
+///
+/// Cursor entries don't store this (the hotspot is stored instead), so it's read from
+/// the DIB. PNG entries are treated as 32-bpp, and unreadable DIBs as 0-bpp.
+#[must_use]
+pub fn bit_depth(entry: &IconDirEntry) -> u16 {
+    if entry.is_png() {
+        return 32;
+    }
+
+    DibHeader::read(entry.data()).map_or(0, |header| header.bit_count)
+}
+
+/// Returns true if `data` starts with an ICO/CUR header.
+#[must_use]
+pub fn is_icon_dir(data: &[u8]) -> bool {
+    // reserved, followed by type (1 = ICO, 2 = CUR)
+    matches!(data, [0, 0, 1 | 2, 0, ..])
+}
+
+/// Wraps a raw bitmap in a single-entry CUR file with `hotspot`, so it can be read
+/// by [`ico::IconDir::read`]. This is for ANI frames when `AF_ICON` is unset.
+///
+/// Since `hotspot` usually comes from another frame, it's clamped to the dimensions.
+///
+/// Raw bitmaps may or may not have their height doubled with an AND mask following the
+/// XOR bitmap like ICO entries do. If not, the height is doubled and an opaque AND mask
+/// is appended. A leading `BITMAPFILEHEADER` (starting with "BM") is also removed.
+///
+/// ## Errors
+///
+/// If the header can't be read, or [`TryInto`] conversions fail.
+pub fn wrap_as_cur(raw: &[u8], hotspot: (u16, u16)) -> Result<Vec<u8>> {
+    const FILE_HEADER_SIZE: usize = 14;
+
+    let mut dib = if raw.starts_with(b"BM") && raw.len() > FILE_HEADER_SIZE {
+        raw[FILE_HEADER_SIZE..].to_vec()
+    } else {
+        raw.to_vec()
+    };
+
+    let header = DibHeader::read(&dib)?;
+    let (width, height) = (header.width, header.height);
+
+    if width <= 0 || height <= 0 {
+        bail!("invalid raw DIB dimensions, width={width}, height={height}");
+    }
+
+    let (width, height) = (
+        usize::try_from(width.unsigned_abs())?,
+        usize::try_from(height.unsigned_abs())?,
+    );
+
+    let xor_stride = (width * usize::from(header.bit_count)).div_ceil(32) * 4;
+    let and_stride = width.div_ceil(32) * 4;
+    let xor_offset = header.xor_offset()?;
+
+    // if doubled, the XOR bitmap and AND mask each take half of the height.
+    // otherwise, every row is XOR data (with or without a mask after it)
+    let doubled_len = xor_offset + (xor_stride + and_stride) * (height / 2);
+    let single_len = xor_offset + xor_stride * height;
+
+    if dib.len() < doubled_len.min(single_len) {
+        bail!(
+            "raw DIB of len={} too short for width={width}, height={height}",
+            dib.len()
+        );
+    }
+
+    // both are the same size for 1-bpp, and cursors are usually square
+    let is_doubled = if doubled_len == single_len {
+        height == width * 2
+    } else {
+        height % 2 == 0 && dib.len() < single_len
+    };
+
+    let height = if is_doubled {
+        height / 2
+    } else {
+        let doubled = i32::try_from(height * 2)?;
+        dib[8..12].copy_from_slice(&doubled.to_le_bytes());
+        dib.resize(dib.len() + and_stride * height, 0);
+        height
+    };
+
+    let hotspot = (
+        hotspot.0.min(u16::try_from(width).unwrap_or(u16::MAX)),
+        hotspot.1.min(u16::try_from(height).unwrap_or(u16::MAX)),
+    );
+
+    let (width, height) = (
+        u8::try_from(width).unwrap_or(0),
+        u8::try_from(height).unwrap_or(0),
+    );
+    let dib_len = u32::try_from(dib.len())?;
+
+    // ICONDIR, then one ICONDIRENTRY (0 means 256+ for dimensions)
+    let mut cur = Vec::with_capacity(22 + dib.len());
+    cur.extend_from_slice(&[0, 0, 2, 0, 1, 0]);
+    cur.extend_from_slice(&[width, height, 0, 0]);
+    cur.extend_from_slice(&hotspot.0.to_le_bytes());
+    cur.extend_from_slice(&hotspot.1.to_le_bytes());
+    cur.extend_from_slice(&dib_len.to_le_bytes());
+    cur.extend_from_slice(&22_u32.to_le_bytes());
+    cur.extend_from_slice(&dib);
+
+    Ok(cur)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use ico::IconDir;
+
+    /// Builds a 4-bpp DIB of `width` by `height`, where every pixel is `color` (BGRA).
+    #[must_use]
+    #[expect(clippy::missing_panics_doc)]
+    pub fn dib_4bpp(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+        let xor_stride = (width * 4).div_ceil(32) * 4;
+        let and_stride = width.div_ceil(32) * 4;
+
+        let mut dib = Vec::new();
+        dib.extend_from_slice(&40_u32.to_le_bytes());
+        dib.extend_from_slice(&width.to_le_bytes());
+        dib.extend_from_slice(&(height * 2).to_le_bytes());
+        dib.extend_from_slice(&1_u16.to_le_bytes());
+        dib.extend_from_slice(&4_u16.to_le_bytes());
+        dib.extend_from_slice(&[0; 24]);
+
+        // every pixel uses the first color of the table
+        dib.extend_from_slice(&color);
+        dib.extend_from_slice(&[0; 60]);
+        dib.resize(
+            dib.len() + usize::try_from((xor_stride + and_stride) * height).unwrap(),
+            0,
+        );
+
+        dib
+    }
+
+    /// Builds a CUR file with an entry for each of `dibs`, with (0, 0) as the hotspot.
+    #[must_use]
+    #[expect(clippy::missing_panics_doc)]
+    pub fn cur(dibs: &[Vec<u8>]) -> Vec<u8> {
+        let count = u16::try_from(dibs.len()).unwrap();
+        let mut cur = vec![0, 0, 2, 0];
+        cur.extend_from_slice(&count.to_le_bytes());
+
+        let mut offset = 6 + 16 * u32::from(count);
+
+        for dib in dibs {
+            let header = DibHeader::read(dib).unwrap();
+            let (width, height) = header.dimensions().unwrap();
+            let size = u32::try_from(dib.len()).unwrap();
+
+            cur.extend_from_slice(&[
+                u8::try_from(width).unwrap_or(0),
+                u8::try_from(height).unwrap_or(0),
+                0,
+                0,
+            ]);
+            cur.extend_from_slice(&[0; 4]);
+            cur.extend_from_slice(&size.to_le_bytes());
+            cur.extend_from_slice(&offset.to_le_bytes());
+            offset += size;
+        }
+
+        cur.extend(dibs.concat());
+        cur
+    }
+
+    /// Builds a 32-bpp DIB from `pixels` (BGRA, top row first) with an optional `and_mask`.
+    ///
+    /// The height stored in the header is doubled if `and_mask` is provided, like in ICO/CUR.
+    #[must_use]
+    #[expect(clippy::missing_panics_doc)]
+    pub fn dib_32bpp(width: u32, pixels: &[[u8; 4]], and_mask: Option<&[bool]>) -> Vec<u8> {
+        let height = u32::try_from(pixels.len()).unwrap() / width;
+        let stored_height = if and_mask.is_some() {
+            height * 2
+        } else {
+            height
+        };
+        let rows = |len| {
+            (0..height as usize)
+                .rev()
+                .map(move |y| y * len..(y + 1) * len)
+        };
+
+        let mut dib = Vec::new();
+        dib.extend_from_slice(&40_u32.to_le_bytes());
+        dib.extend_from_slice(&width.to_le_bytes());
+        dib.extend_from_slice(&stored_height.to_le_bytes());
+        dib.extend_from_slice(&1_u16.to_le_bytes());
+        dib.extend_from_slice(&32_u16.to_le_bytes());
+        dib.extend_from_slice(&[0; 24]);
+
+        for row in rows(width as usize) {
+            dib.extend(pixels[row].iter().flatten());
+        }
+
+        if let Some(mask) = and_mask {
+            let stride = width.div_ceil(32) as usize * 4;
+
+            for row in rows(width as usize) {
+                let mut bytes = vec![0_u8; stride];
+                for (x, _) in mask[row].iter().enumerate().filter(|(_, t)| **t) {
+                    bytes[x / 8] |= 0x80 >> (x % 8);
+                }
+
+                dib.extend(bytes);
+            }
+        }
+
+        dib
+    }
+
+    /// Returns the dimensions and RGBA of the only entry in `cur`.
+    fn decode_cur(cur: &[u8]) -> ((u32, u32), Vec<u8>) {
+        let dir = IconDir::read(Cursor::new(cur)).unwrap();
+        let entry = &dir.entries()[0];
+        let rgba = entry.decode().unwrap().into_rgba_data();
+
+        ((entry.width(), entry.height()), rgba)
+    }
+
+    /// Wraps raw DIBs with and without an AND mask following the XOR bitmap.
+    #[test]
+    fn wrap_raw_dibs() {
+        const RED: [u8; 4] = [0, 0, 255, 255];
+        const BLUE: [u8; 4] = [255, 0, 0, 255];
+
+        // 2x3, so the height isn't simply double the width
+        let pixels = [RED, BLUE, BLUE, RED, RED, RED];
+        let expected_rgba: Vec<u8> = pixels
+            .iter()
+            .flat_map(|[b, g, r, a]| [*r, *g, *b, *a])
+            .collect();
+
+        let doubled = dib_32bpp(2, &pixels, Some(&[false; 6]));
+        let single = dib_32bpp(2, &pixels, None);
+        assert_eq!(DibHeader::read(&doubled).unwrap().height, 6);
+        assert_eq!(DibHeader::read(&single).unwrap().height, 3);
+
+        for dib in [doubled, single] {
+            let cur = wrap_as_cur(&dib, (1, 2)).unwrap();
+            assert_eq!(decode_cur(&cur), ((2, 3), expected_rgba.clone()));
+            assert_eq!(DibHeader::read(&cur[22..]).unwrap().height, 6);
+        }
+
+        // 2x4 without a mask, so the height is double the width but isn't doubled
+        let tall = [RED; 8];
+        let cur = wrap_as_cur(&dib_32bpp(2, &tall, None), (0, 0)).unwrap();
+        assert_eq!(decode_cur(&cur).0, (2, 4));
+
+        // truncated XOR bitmap
+        let single = dib_32bpp(2, &pixels, None);
+        assert!(wrap_as_cur(&single[..48], (0, 0)).is_err());
+
+        // with a leading BITMAPFILEHEADER
+        let mut file = b"BM".to_vec();
+        file.extend_from_slice(&[0; 12]);
+        file.extend(dib_32bpp(2, &pixels, None));
+        let cur = wrap_as_cur(&file, (0, 0)).unwrap();
+        assert_eq!(decode_cur(&cur), ((2, 3), expected_rgba));
+    }
+
+    /// Reads an AND mask that isn't the same for every row.
+    #[test]
+    fn read_and_mask() {
+        let mask = [true, false, false, false, true, true];
+        let dib = dib_32bpp(3, &[[0; 4]; 6], Some(&mask));
+
+        let header = DibHeader::read(&dib).unwrap();
+        assert_eq!(header.read_and_mask(&dib).unwrap(), mask);
+        assert!(header.read_and_mask(&dib[..dib.len() - 1]).is_err());
+    }
+
+    /// Rejects a color table too large to be addressed, rather than overflowing.
+    #[test]
+    fn huge_color_table() {
+        let mut dib = dib_32bpp(2, &[[0; 4]; 4], None);
+        dib[14..16].copy_from_slice(&8_u16.to_le_bytes()); // biBitCount
+        dib[32..36].copy_from_slice(&u32::MAX.to_le_bytes()); // biClrUsed
+
+        let header = DibHeader::read(&dib).unwrap();
+        assert!(header.xor_offset().is_err());
+        assert!(header.read_and_mask(&dib).is_err());
+        assert!(wrap_as_cur(&dib, (0, 0)).is_err());
+    }
+}
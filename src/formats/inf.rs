@@ -1,14 +1,171 @@
 //! Parses INF installer files for cursor themes.
 
-use crate::{
-    themes::theme::{CursorMapping, CursorType},
-    warn,
-};
+use crate::themes::theme::{CursorMapping, CursorType};
 
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result, anyhow, bail};
+use anyhow::{Result, anyhow, bail};
 use configparser::ini::Ini; // inf is an "ini-like" format
+use log::warn;
+use thiserror::Error;
+
+/// An error from parsing an INF file, see [`parse_inf_installer`].
+///
+/// This points to the line of the offending entry, if it can be found.
+#[derive(Debug)]
+pub struct InfError {
+    /// The INF file being parsed.
+    pub path: PathBuf,
+    /// Line number (starting from 1) of the offending entry, if it can be found.
+    pub line: Option<usize>,
+    /// What went wrong.
+    pub kind: InfErrorKind,
+}
+
+/// Kinds of [`InfError`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum InfErrorKind {
+    /// The file isn't valid INI syntax.
+    #[error("malformed INF: {message}")]
+    Syntax { message: String },
+    /// A required section doesn't exist.
+    #[error("missing section [{section}]")]
+    MissingSection { section: String },
+    /// A required key doesn't exist or has no value.
+    #[error("missing key={key} in section [{section}]")]
+    MissingKey { section: String, key: String },
+    /// None of the registry entries install a cursor scheme.
+    #[error("no cursor scheme found in 'AddReg' sections")]
+    NoScheme,
+    /// The theme name in the cursor scheme isn't quoted.
+    #[error("expected theme name to be quoted, instead got {value:?}")]
+    UnquotedName { value: String },
+    /// A path in the cursor scheme is malformed.
+    #[error("malformed path={value:?} in cursor scheme")]
+    MalformedPath { value: String },
+    /// Valid INF syntax that isn't supported yet.
+    #[error("unsupported syntax in {value:?}")]
+    Unsupported { value: String },
+    /// A cursor in the scheme isn't copied by any `CopyFiles` section.
+    #[error("no source file for file={file:?} in 'CopyFiles' sections")]
+    MissingFile { file: String },
+    /// A '%' delimiter isn't closed.
+    #[error("unclosed '%' delimiter at index={index} of {value:?}")]
+    UnclosedDelimiter { value: String, index: usize },
+    /// A '%' delimited key isn't in the `Strings` section.
+    #[error("no substitution exists for key={key}")]
+    MissingSubstitution { key: String },
+}
+
+impl fmt::Display for InfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in path={}", self.kind, self.path.display())?;
+
+        if let Some(line) = self.line {
+            write!(f, ", line={line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for InfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.kind.source()
+    }
+}
+
+/// The INF being parsed, used for locating errors.
+struct Source<'a> {
+    path: &'a Path,
+    text: &'a str,
+}
+
+impl Source<'_> {
+    /// Creates an error of `kind`, on the first line containing `needle` (case-insensitively).
+    ///
+    /// If `needle` is empty, or isn't found, there's no line.
+    fn error(&self, kind: InfErrorKind, needle: &str) -> InfError {
+        let needle = needle.to_ascii_lowercase();
+        let line = (!needle.is_empty())
+            .then(|| {
+                self.text
+                    .lines()
+                    .position(|l| l.to_ascii_lowercase().contains(&needle))
+            })
+            .flatten()
+            .map(|i| i + 1);
+
+        InfError {
+            path: self.path.to_path_buf(),
+            line,
+            kind,
+        }
+    }
+
+    /// Creates an error of `kind`, on the line of `key` in the section `section`
+    /// (both case-insensitively).
+    ///
+    /// If the key isn't found, this is the line of the section header instead.
+    fn error_at(&self, kind: InfErrorKind, section: &str, key: &str) -> InfError {
+        let mut header = None;
+        let mut line = None;
+
+        for (i, l) in self.text.lines().enumerate() {
+            let l = l.trim();
+
+            if let Some(name) = l.strip_prefix('[').and_then(|l| l.split_once(']')) {
+                if header.is_some() {
+                    break; // past the section
+                }
+
+                if name.0.trim().eq_ignore_ascii_case(section) {
+                    header = Some(i + 1);
+                }
+            } else if header.is_some()
+                && l.split_once('=')
+                    .is_some_and(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+            {
+                line = Some(i + 1);
+                break;
+            }
+        }
+
+        InfError {
+            path: self.path.to_path_buf(),
+            line: line.or(header),
+            kind,
+        }
+    }
+
+    /// Returns the value of `key` in `section` (named `section_name`).
+    fn get_value<'a>(
+        &self,
+        section: &'a HashMap<String, Option<String>>,
+        section_name: &str,
+        key: &str,
+    ) -> Result<&'a String, InfError> {
+        section
+            .get(&key.to_ascii_lowercase())
+            .and_then(Option::as_ref)
+            .ok_or_else(|| {
+                self.error_at(
+                    InfErrorKind::MissingKey {
+                        section: section_name.to_string(),
+                        key: key.to_string(),
+                    },
+                    section_name,
+                    key,
+                )
+            })
+    }
+}
 
 /// Attempts to parse `inf_path` as an installer file for a cursor theme.
 ///
@@ -50,24 +207,30 @@ use configparser::ini::Ini; // inf is an "ini-like" format
 /// ```
 pub fn parse_inf_installer(inf_path: &Path) -> Result<(String, Vec<CursorMapping>)> {
     let inf_string = fs::read_to_string(inf_path)?;
+    let source = Source {
+        path: inf_path,
+        text: &inf_string,
+    };
 
     let parent = inf_path
         .parent()
         .ok_or_else(|| anyhow!("no parent for inf_path={}", inf_path.display()))?;
 
     let inf: HashMap<String, HashMap<String, Option<String>>> = Ini::new()
-        .read(inf_string)
-        .map_err(|e| anyhow!("failed to read inf, error e={e}"))?;
+        .read(inf_string.clone())
+        .map_err(|message| source.error(InfErrorKind::Syntax { message }, ""))?;
 
-    let defaultinstall: &HashMap<String, Option<String>> = inf
-        .get("defaultinstall")
-        .ok_or_else(|| anyhow!("no defaultinstall section found"))?;
+    let defaultinstall: &HashMap<String, Option<String>> =
+        inf.get("defaultinstall").ok_or_else(|| {
+            source.error(
+                InfErrorKind::MissingSection {
+                    section: String::from("DefaultInstall"),
+                },
+                "",
+            )
+        })?;
 
-    let addreg = defaultinstall
-        .get("addreg")
-        .ok_or_else(|| anyhow!("no addreg key found in defaultinstall"))?
-        .as_ref()
-        .ok_or_else(|| anyhow!("no value for addreg key"))?;
+    let addreg = source.get_value(defaultinstall, "DefaultInstall", "AddReg")?;
 
     // find the right registry entries (the ones we can parse)
     // https://github.com/quantum5/win2xcur/blob/c8a390b79456a45104fe42133b9d7eb4ce7c8638/win2xcur/parser/inf.py#L47-L50
@@ -76,21 +239,27 @@ pub fn parse_inf_installer(inf_path: &Path) -> Result<(String, Vec<CursorMapping
         .filter_map(|k| inf.get(&k.to_ascii_lowercase()))
         .flat_map(|v| v.keys())
         .find(|k| k.contains(r#""control panel\cursors\schemes","#))
-        .ok_or_else(|| anyhow!("couldn't find cursor mappings"))?;
+        .ok_or_else(|| source.error(InfErrorKind::NoScheme, "addreg"))?;
 
     let subs = inf.get("strings");
-    let expanded_reg = expand_scheme(scheme, subs)?;
+    let expanded_reg = expand_scheme(&source, scheme, subs)?;
     let mut reg_info = expanded_reg.split(',');
 
     reg_info.next(); // root key, e.g., hkcu, hklm
     reg_info.next(); // subkey
 
-    let name = reg_info
-        .next()
-        .ok_or_else(|| anyhow!("couldn't parse theme name; reg_info doesn't have enough info"))?
+    let name = reg_info.next().unwrap_or_default();
+    let name = name
         .strip_prefix('"') // refrain from trim_matches; only one quote should be removed
         .and_then(|n| n.strip_suffix('"'))
-        .ok_or_else(|| anyhow!("expected theme name to be quoted"))?
+        .ok_or_else(|| {
+            source.error(
+                InfErrorKind::UnquotedName {
+                    value: name.to_string(),
+                },
+                scheme,
+            )
+        })?
         .to_string();
 
     reg_info.next(); // flags
@@ -98,18 +267,35 @@ pub fn parse_inf_installer(inf_path: &Path) -> Result<(String, Vec<CursorMapping
     let mut paths: Vec<_> = reg_info
         .map(|s| {
             s.rsplit_once('\\')
-                .ok_or_else(|| anyhow!("failed to extract filename from path, s={s}"))
                 .map(|s| s.1.to_ascii_lowercase())
+                .ok_or_else(|| {
+                    source.error(
+                        InfErrorKind::MalformedPath {
+                            value: s.to_string(),
+                        },
+                        scheme,
+                    )
+                })
         })
-        .collect::<Result<_>>()?;
+        .collect::<Result<_, _>>()?;
 
-    let end = paths.len() - 1;
-    paths[end] = paths[paths.len() - 1]
+    let Some(last) = paths.last_mut() else {
+        bail!(source.error(InfErrorKind::NoScheme, scheme));
+    };
+
+    *last = last
         .strip_suffix('"')
-        .ok_or_else(|| anyhow!("expected closing quotation for paths, didn't find it"))?
+        .ok_or_else(|| {
+            source.error(
+                InfErrorKind::MalformedPath {
+                    value: last.clone(),
+                },
+                scheme,
+            )
+        })?
         .to_string();
 
-    let paths = resolve_paths(&inf, defaultinstall, &paths)?;
+    let paths = resolve_paths(&source, &inf, defaultinstall, &paths)?;
 
     let mappings: Vec<_> = paths
         .into_iter()
@@ -147,15 +333,23 @@ const fn index_to_cursor_type(index: usize) -> CursorType {
 
 /// Resolves destination paths to source paths.
 fn resolve_paths(
+    source: &Source,
     inf: &HashMap<String, HashMap<String, Option<String>>>,
     defaultinstall: &HashMap<String, Option<String>>,
     paths: &[String],
-) -> Result<Vec<String>> {
-    let copyfiles = defaultinstall
-        .get("copyfiles")
-        .cloned()
-        .flatten()
-        .ok_or_else(|| anyhow!("no copyfiles section"))?;
+) -> Result<Vec<String>, InfError> {
+    let copyfiles = source.get_value(defaultinstall, "DefaultInstall", "CopyFiles")?;
+
+    // TODO: Implement this later.
+    if copyfiles.starts_with('@') {
+        return Err(source.error_at(
+            InfErrorKind::Unsupported {
+                value: copyfiles.clone(),
+            },
+            "DefaultInstall",
+            "CopyFiles",
+        ));
+    }
 
     // paths are coerced to lowercase because they're "keys" (from configparser's perspective).
     // this most likely causes some extra lookups, since the initial path most likely has
@@ -163,25 +357,23 @@ fn resolve_paths(
     let mut mappings = HashMap::with_capacity(paths.len());
 
     for field in copyfiles.split(',') {
-        // TODO: Implement this later.
-        if matches!(copyfiles.chars().next(), Some('@')) {
-            bail!("unsupported '@' syntax in copyfiles");
-        }
-
         let field = field.trim();
 
-        let section = inf
-            .get(&field.to_ascii_lowercase())
-            .ok_or_else(|| anyhow!("copyfiles specifies '{field}' should exist, but doesn't"))?;
+        let section = inf.get(&field.to_ascii_lowercase()).ok_or_else(|| {
+            source.error_at(
+                InfErrorKind::MissingSection {
+                    section: field.to_string(),
+                },
+                "DefaultInstall",
+                "CopyFiles",
+            )
+        })?;
 
         for k in section.keys() {
             // destination-file-name[,[source-file-name][,[unused][,flag]]]
+            // (split always returns at least one field)
             let entry: Vec<_> = k.split(',').map(|f| f.replace('\\', "/")).collect();
 
-            if entry.is_empty() {
-                bail!("empty entry in section={field}");
-            }
-
             if entry.len() == 1 {
                 mappings.insert(dequote(&entry[0]), dequote(&entry[0]));
             } else {
@@ -193,12 +385,13 @@ fn resolve_paths(
     let mut new = Vec::with_capacity(paths.len());
 
     for p in paths {
-        new.push(
-            mappings
-                .get(p.as_str())
-                .ok_or_else(|| anyhow!("missing mapping for {p}"))?
-                .clone(),
-        );
+        // there's no entry for the file, so this points to where it should've been copied
+        let missing = || {
+            let kind = InfErrorKind::MissingFile { file: p.clone() };
+            source.error_at(kind, "DefaultInstall", "CopyFiles")
+        };
+
+        new.push(mappings.get(p.as_str()).ok_or_else(missing)?.clone());
     }
 
     Ok(new)
@@ -207,19 +400,19 @@ fn resolve_paths(
 /// Helper function for [`parse_inf_installer`].
 ///
 /// This expands `Scheme.Reg` if needed.
-fn expand_scheme(reg: &str, subs: Option<&HashMap<String, Option<String>>>) -> Result<String> {
-    let Some(subs) = subs else {
-        let empty: HashMap<String, String> = HashMap::new();
-        return expand(reg, &empty).with_context(|| format!("for input reg={reg}"));
-    };
-
+fn expand_scheme(
+    source: &Source,
+    reg: &str,
+    subs: Option<&HashMap<String, Option<String>>>,
+) -> Result<String, InfError> {
     let subs: HashMap<_, _> = subs
-        .iter()
+        .into_iter()
+        .flatten()
         .filter_map(dequote_value)
         .map(|(k, v)| (format!("%{k}%"), v))
         .collect();
 
-    expand(reg, &subs).with_context(|| format!("for input reg={reg}"))
+    expand(reg, &subs).map_err(|kind| source.error(kind, reg))
 }
 
 /// Dequotes following INF spec.
@@ -258,7 +451,7 @@ fn dequote_value(entry: (&String, &Option<String>)) -> Option<(String, String)>
 ///
 /// `subs` keys must contain the delimiters (e.g., "%name%" => "hachispin").
 /// This also does not expand recursively - hopefully there's no need for that.
-fn expand(input: &str, subs: &HashMap<String, String>) -> Result<String> {
+fn expand(input: &str, subs: &HashMap<String, String>) -> Result<String, InfErrorKind> {
     let mut expanded = String::with_capacity(input.len());
     let mut chars = input.char_indices();
 
@@ -271,7 +464,10 @@ fn expand(input: &str, subs: &HashMap<String, String>) -> Result<String> {
         let start = i;
 
         let Some((end, _)) = chars.find(|(_, c)| *c == '%') else {
-            bail!("unclosed '%' delimiter starting at i={i}");
+            return Err(InfErrorKind::UnclosedDelimiter {
+                value: input.to_string(),
+                index: i,
+            });
         };
 
         let key = &input[start..=end].to_ascii_lowercase();
@@ -288,7 +484,7 @@ fn expand(input: &str, subs: &HashMap<String, String>) -> Result<String> {
                     None
                 }
             })
-            .ok_or_else(|| anyhow!("no substitution exists for key={key}"))?;
+            .ok_or_else(|| InfErrorKind::MissingSubstitution { key: key.clone() })?;
 
         expanded.push_str(value);
     }
@@ -299,7 +495,7 @@ fn expand(input: &str, subs: &HashMap<String, String>) -> Result<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::from_root;
+    use crate::{from_root, unwrap_err_as};
 
     /// Various tests for the [`expand`] function that should all return [`Ok`].
     #[test]
@@ -341,7 +537,10 @@ mod tests {
         assert!(expand(value, &subs).is_err());
 
         let value = "The last didn't read the documentation. How %pitiful%.";
-        assert!(expand(value, &subs).is_err());
+        assert!(matches!(
+            expand(value, &subs),
+            Err(InfErrorKind::MissingSubstitution { key }) if key == "%pitiful%"
+        ));
     }
 
     /// Golden file test for INF fixture.
@@ -377,4 +576,30 @@ mod tests {
 
         assert_eq!(mappings, expected_mappings);
     }
+
+    /// Checks that errors have the right kind and line.
+    #[test]
+    fn error_lines() {
+        const INF: &str = include_str!(from_root!("/testing/fixtures/neuro/Install.inf"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let parse = |text: &str| {
+            let inf_path = dir.path().join("Install.inf");
+            fs::write(&inf_path, text).unwrap();
+
+            unwrap_err_as::<InfError>(parse_inf_installer(&inf_path))
+        };
+
+        // the file name is also in the "Strings" section, which shouldn't be pointed to
+        let err = parse(&INF.replace("Neuro alt.ani\r\n", ""));
+        assert_eq!(err.line, Some(5));
+        assert!(matches!(
+            err.kind,
+            InfErrorKind::MissingFile { file } if file == "neuro alt.ani"
+        ));
+
+        let err = parse(&INF.replace("AddReg    = Scheme.Reg", ""));
+        assert_eq!(err.line, Some(4));
+        assert!(matches!(err.kind, InfErrorKind::MissingKey { key, .. } if key == "AddReg"));
+    }
 }
@@ -1,6 +1,10 @@
 //! Groups modules that handle file formats, such as ANI and Xcursor.
 
 pub mod ani;
+pub mod cape;
 pub mod crs;
+pub mod detect;
+pub mod dib;
 pub mod inf;
+pub mod registry;
 pub mod xcursor;
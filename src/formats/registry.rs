@@ -0,0 +1,622 @@
+//! Module for the [`CursorDecoder`] and [`CursorEncoder`] traits, and the [`Registry`] of them.
+//!
+//! Decoders recognise the files they read by their signature (see [`CursorDecoder::sniff`]),
+//! falling back on their extensions. Encoders are keyed by their name, which is what's
+//! passed to `--to`.
+//!
+//! Adding a format means implementing one of these traits and registering it in a
+//! [`Registry`], e.g., one made with [`Registry::with_builtins`]. Anything reading
+//! cursors through that registry (including the CLI) then accepts it for input/output.
+
+use crate::{
+    cursors::{decode::DecodeOptions, generic_cursor::GenericCursor},
+    formats::detect::Format,
+    fs_utils::write_atomic,
+    themes::theme::{CursorTheme, CursorType},
+};
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fmt::Debug,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use anyhow::{Context, Result, bail};
+use log::warn;
+
+/// A reader that can also seek, since trait objects can only have one non-auto trait.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Reads cursors from files of one or more formats.
+pub trait CursorDecoder: Debug + Send + Sync {
+    /// Name of the input format, used to identify it (e.g., "ani").
+    fn name(&self) -> &'static str;
+
+    /// Extensions (lowercase, without the dot) of files this decodes.
+    ///
+    /// These are only used when no decoder recognises the contents of a file.
+    fn extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Returns true if `header`, the first bytes of a file, is a signature this decodes.
+    ///
+    /// `header` is at most [`Registry::HEADER_LEN`] bytes, and shorter if the file is.
+    fn sniff(&self, header: &[u8]) -> bool;
+
+    /// Returns true if files this decodes are cursors, see [`Format::is_cursor`].
+    ///
+    /// Directories are only expanded to files that are, while
+    /// other files are only read if they're passed explicitly.
+    fn is_cursor_format(&self) -> bool {
+        true
+    }
+
+    /// Reads and parses a cursor from `reader`, which has been detected
+    /// as a file this decodes.
+    ///
+    /// ## Errors
+    ///
+    /// If the data can't be read or decoded.
+    fn decode(&self, reader: &mut dyn ReadSeek, options: &DecodeOptions) -> Result<GenericCursor>;
+
+    /// Reads and parses the cursor at `path`, which has been detected
+    /// as a file this decodes.
+    ///
+    /// By default, this opens `path` and calls [`Self::decode`]. Decoders that
+    /// use other files (e.g., hotspot sidecars) should override this.
+    ///
+    /// ## Errors
+    ///
+    /// If the file can't be read or decoded.
+    fn decode_path(&self, path: &Path, options: &DecodeOptions) -> Result<GenericCursor> {
+        let handle = File::open(path).context("(filesystem) failed to read")?;
+
+        self.decode(&mut BufReader::new(handle), options)
+    }
+}
+
+/// Writes cursors and themes in some output format.
+pub trait CursorEncoder: Debug + Send + Sync {
+    /// Name of the output format, used to select it (e.g., "xcursor").
+    fn name(&self) -> &'static str;
+
+    /// Returns true if only themes can be written, not individual cursors.
+    fn theme_only(&self) -> bool {
+        false
+    }
+
+    /// Writes `cursor` to `writer`.
+    ///
+    /// ## Errors
+    ///
+    /// If writing fails, or this is [`Self::theme_only`].
+    fn write_cursor(&self, cursor: &GenericCursor, writer: &mut dyn Write) -> Result<()> {
+        let _ = (cursor, writer);
+        bail!("{} output requires a theme", self.name())
+    }
+
+    /// Writes `cursor` to the file at `path`, see [`Self::write_cursor`].
+    ///
+    /// ## Errors
+    ///
+    /// If writing fails, or this is [`Self::theme_only`].
+    fn encode_cursor(&self, cursor: &GenericCursor, path: &Path) -> Result<()> {
+        if self.theme_only() {
+            bail!("{} output requires a theme", self.name());
+        }
+
+        write_atomic(path, |writer| self.write_cursor(cursor, writer))
+    }
+
+    /// Writes `theme` at `path`, usually from [`Self::theme_path`].
+    ///
+    /// ## Errors
+    ///
+    /// If writing fails.
+    fn encode_theme(&self, theme: &CursorTheme, path: &Path) -> Result<()>;
+
+    /// Returns the path inside of `dir` to write a theme named `name` to.
+    ///
+    /// `name` should already be sanitized, see [`CursorTheme::sanitized_name`].
+    fn theme_path(&self, name: &str, dir: &Path) -> PathBuf {
+        dir.join(name)
+    }
+
+    /// Returns the files that [`Self::encode_theme`] writes for `theme` at `path`,
+    /// see [`Self::theme_path`]. This is used to plan conversions without writing.
+    fn theme_files(&self, theme: &CursorTheme, path: &Path) -> Vec<ThemeFile>;
+}
+
+/// A file written by [`CursorEncoder::encode_theme`], see [`CursorEncoder::theme_files`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThemeFile {
+    /// Where the file is written.
+    pub path: PathBuf,
+    /// The cursor that the file is for, if it's not for the whole theme.
+    pub cursor_type: Option<CursorType>,
+    /// Other paths that refer to this file (e.g., symlinks).
+    pub aliases: Vec<PathBuf>,
+}
+
+/// Decoder for [`Format::Ani`], see [`GenericCursor::from_ani_path`].
+#[derive(Debug)]
+struct AniDecoder;
+
+impl CursorDecoder for AniDecoder {
+    fn name(&self) -> &'static str {
+        "ani"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ani"]
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        matches!(Format::sniff(header), Some(Format::Ani))
+    }
+
+    fn decode(&self, reader: &mut dyn ReadSeek, options: &DecodeOptions) -> Result<GenericCursor> {
+        GenericCursor::from_ani_reader(reader, options)
+    }
+
+    fn decode_path(&self, path: &Path, options: &DecodeOptions) -> Result<GenericCursor> {
+        GenericCursor::from_ani_path(path, options)
+    }
+}
+
+/// Decoder for [`Format::Cur`], see [`GenericCursor::from_cur_reader`].
+#[derive(Debug)]
+struct CurDecoder;
+
+impl CursorDecoder for CurDecoder {
+    fn name(&self) -> &'static str {
+        "cur"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["cur"]
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        matches!(Format::sniff(header), Some(Format::Cur))
+    }
+
+    fn decode(&self, reader: &mut dyn ReadSeek, options: &DecodeOptions) -> Result<GenericCursor> {
+        GenericCursor::from_cur_reader(reader, options)
+    }
+}
+
+/// Decoder for static images, see [`GenericCursor::from_image_path`].
+#[derive(Debug)]
+struct ImageDecoder;
+
+impl CursorDecoder for ImageDecoder {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ico", "png", "bmp"]
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        matches!(
+            Format::sniff(header),
+            Some(Format::Ico | Format::Png | Format::Bmp)
+        )
+    }
+
+    fn decode(&self, reader: &mut dyn ReadSeek, options: &DecodeOptions) -> Result<GenericCursor> {
+        GenericCursor::from_image_reader(reader, None, options)
+    }
+
+    fn decode_path(&self, path: &Path, options: &DecodeOptions) -> Result<GenericCursor> {
+        GenericCursor::from_image_path(path, options)
+    }
+}
+
+/// Encoder for Xcursor files and X11 cursor themes, used on Linux.
+#[derive(Debug)]
+struct XcursorEncoder;
+
+impl CursorEncoder for XcursorEncoder {
+    fn name(&self) -> &'static str {
+        "xcursor"
+    }
+
+    fn write_cursor(&self, cursor: &GenericCursor, writer: &mut dyn Write) -> Result<()> {
+        cursor.write_xcursor(writer)
+    }
+
+    fn encode_theme(&self, theme: &CursorTheme, path: &Path) -> Result<()> {
+        theme.save_as_x11_theme_at(path)
+    }
+
+    fn theme_files(&self, theme: &CursorTheme, path: &Path) -> Vec<ThemeFile> {
+        theme.x11_theme_files(path)
+    }
+}
+
+/// Encoder for Mousecape capes, used on macOS.
+#[derive(Debug)]
+struct CapeEncoder;
+
+impl CursorEncoder for CapeEncoder {
+    fn name(&self) -> &'static str {
+        "cape"
+    }
+
+    fn theme_only(&self) -> bool {
+        true
+    }
+
+    fn encode_theme(&self, theme: &CursorTheme, path: &Path) -> Result<()> {
+        theme.save_as_cape_at(path)
+    }
+
+    fn theme_path(&self, name: &str, dir: &Path) -> PathBuf {
+        dir.join(format!("{name}.cape"))
+    }
+
+    fn theme_files(&self, _: &CursorTheme, path: &Path) -> Vec<ThemeFile> {
+        // every cursor is in the one file
+        vec![ThemeFile {
+            path: path.to_path_buf(),
+            cursor_type: None,
+            aliases: Vec::new(),
+        }]
+    }
+}
+
+/// Collection of decoders (in order of registration) and encoders (keyed by name).
+#[derive(Debug, Default)]
+pub struct Registry {
+    decoders: Vec<Box<dyn CursorDecoder>>,
+    encoders: BTreeMap<&'static str, Box<dyn CursorEncoder>>,
+}
+
+impl Registry {
+    /// Number of bytes read from the start of a file for [`CursorDecoder::sniff`], see
+    /// [`Format::read_header`]. This is more than [`Format::sniff`] needs, so that
+    /// decoders from outside of the crate can check longer signatures.
+    pub const HEADER_LEN: usize = 64;
+
+    /// Creates a registry with the formats that currust supports.
+    #[must_use]
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+
+        registry.register_decoder(AniDecoder);
+        registry.register_decoder(CurDecoder);
+        registry.register_decoder(ImageDecoder);
+
+        registry.register_encoder(XcursorEncoder);
+        registry.register_encoder(CapeEncoder);
+
+        registry
+    }
+
+    /// Returns the shared registry of [`Self::with_builtins`].
+    #[must_use]
+    pub fn builtin() -> &'static Self {
+        static BUILTIN: LazyLock<Registry> = LazyLock::new(Registry::with_builtins);
+
+        &BUILTIN
+    }
+
+    /// Registers `decoder`, replacing an existing one with the same name.
+    ///
+    /// Decoders are tried in the order they were registered, so
+    /// signatures that others also match should be registered last.
+    pub fn register_decoder(&mut self, decoder: impl CursorDecoder + 'static) {
+        let decoder = Box::new(decoder);
+
+        match self
+            .decoders
+            .iter_mut()
+            .find(|d| d.name() == decoder.name())
+        {
+            Some(existing) => *existing = decoder,
+            None => self.decoders.push(decoder),
+        }
+    }
+
+    /// Registers `encoder` by its name, replacing an existing one.
+    pub fn register_encoder(&mut self, encoder: impl CursorEncoder + 'static) {
+        self.encoders.insert(encoder.name(), Box::new(encoder));
+    }
+
+    /// Returns the decoder named `name`, if any.
+    #[must_use]
+    pub fn decoder(&self, name: &str) -> Option<&dyn CursorDecoder> {
+        self.decoders
+            .iter()
+            .find(|d| d.name() == name)
+            .map(AsRef::as_ref)
+    }
+
+    /// Returns the encoder named `name`, if any.
+    #[must_use]
+    pub fn encoder(&self, name: &str) -> Option<&dyn CursorEncoder> {
+        self.encoders.get(name).map(AsRef::as_ref)
+    }
+
+    /// Returns the names of all encoders, sorted.
+    pub fn encoder_names(&self) -> impl Iterator<Item = &'static str> {
+        self.encoders.keys().copied()
+    }
+
+    /// Returns the decoder that recognises `header`, see [`CursorDecoder::sniff`].
+    #[must_use]
+    pub fn sniff(&self, header: &[u8]) -> Option<&dyn CursorDecoder> {
+        self.decoders
+            .iter()
+            .find(|d| d.sniff(header))
+            .map(AsRef::as_ref)
+    }
+
+    /// Returns the decoder with the extension `ext` (case-insensitively), if any.
+    #[must_use]
+    pub fn by_extension(&self, ext: &OsStr) -> Option<&dyn CursorDecoder> {
+        let ext = ext.to_str()?;
+
+        self.decoders
+            .iter()
+            .find(|d| d.extensions().iter().any(|e| e.eq_ignore_ascii_case(ext)))
+            .map(AsRef::as_ref)
+    }
+
+    /// Returns the decoder for the file at `path`, if any.
+    ///
+    /// This is detected by the contents of the file (see [`Self::sniff`]), falling back on
+    /// its extension (see [`Self::by_extension`]) if no decoder recognises them. Built-in
+    /// decoders sniff with [`Format::sniff`], so they agree with [`Format::detect`].
+    ///
+    /// ## Errors
+    ///
+    /// If `path` can't be read.
+    pub fn detect(&self, path: &Path) -> Result<Option<&dyn CursorDecoder>> {
+        let header = Format::read_header(path)?;
+
+        Ok(self
+            .sniff(&header)
+            .or_else(|| path.extension().and_then(|e| self.by_extension(e))))
+    }
+
+    /// Reads and parses the cursor at `path` with the decoder for its format.
+    ///
+    /// ## Errors
+    ///
+    /// - If the format of `path` isn't recognised, see [`Self::detect`].
+    /// - If propagated from [`CursorDecoder::decode_path`].
+    /// - If the cursor exceeds [`DecodeOptions::limits`].
+    pub fn decode(&self, path: &Path, options: &DecodeOptions) -> Result<GenericCursor> {
+        let decoder = self.detect(path)?.with_context(|| {
+            format!(
+                "unrecognised format; {} (by contents or extension)",
+                self.supported()
+            )
+        })?;
+
+        if let Some(ext_decoder) = path.extension().and_then(|e| self.by_extension(e))
+            && ext_decoder.name() != decoder.name()
+        {
+            warn!(
+                "file has the extension of {}, but contains {}",
+                ext_decoder.name(),
+                decoder.name()
+            );
+        }
+
+        let cursor = decoder.decode_path(path, options)?;
+        options.limits.check_total(cursor.num_pixels())?;
+
+        Ok(cursor)
+    }
+
+    /// Reads and parses a cursor from `reader` with the decoder for its format.
+    ///
+    /// Unlike [`Self::decode`], there's no extension to fall back on,
+    /// so the format must be recognised by [`Self::sniff`].
+    ///
+    /// ## Errors
+    ///
+    /// Same as [`Self::decode`].
+    pub fn decode_reader(
+        &self,
+        reader: &mut dyn ReadSeek,
+        options: &DecodeOptions,
+    ) -> Result<GenericCursor> {
+        let start = reader.stream_position()?;
+        let mut header = Vec::with_capacity(Self::HEADER_LEN);
+        reader
+            .take(Self::HEADER_LEN as u64)
+            .read_to_end(&mut header)?;
+        reader.seek(SeekFrom::Start(start))?;
+
+        let decoder = self
+            .sniff(&header)
+            .with_context(|| format!("unrecognised format; {}", self.supported()))?;
+        let cursor = decoder.decode(reader, options)?;
+        options.limits.check_total(cursor.num_pixels())?;
+
+        Ok(cursor)
+    }
+
+    /// Helper function for [`Self::decode`] and [`Self::decode_reader`].
+    fn supported(&self) -> String {
+        let names: Vec<_> = self.decoders.iter().map(|d| d.name()).collect();
+
+        format!("expected one of {}", names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        formats::dib::tests::{cur, dib_4bpp},
+        from_root,
+    };
+
+    use std::{fs, io::Cursor};
+
+    /// Decoder for CUR files behind a "TCUR" signature, standing in for an in-house format.
+    #[derive(Debug)]
+    struct TaggedCur;
+
+    impl CursorDecoder for TaggedCur {
+        fn name(&self) -> &'static str {
+            "tagged-cur"
+        }
+
+        fn extensions(&self) -> &'static [&'static str] {
+            &["tcur"]
+        }
+
+        fn sniff(&self, header: &[u8]) -> bool {
+            header.starts_with(b"TCUR")
+        }
+
+        fn decode(
+            &self,
+            reader: &mut dyn ReadSeek,
+            options: &DecodeOptions,
+        ) -> Result<GenericCursor> {
+            let mut blob = Vec::new();
+            reader.read_to_end(&mut blob)?;
+
+            GenericCursor::from_cur_reader(Cursor::new(blob.split_off(4)), options)
+        }
+    }
+
+    /// Checks that built-in formats are registered, and that registering replaces them.
+    #[test]
+    fn register_formats() {
+        #[derive(Debug)]
+        struct Null;
+
+        impl CursorEncoder for Null {
+            fn name(&self) -> &'static str {
+                "cape"
+            }
+
+            fn encode_theme(&self, _: &CursorTheme, _: &Path) -> Result<()> {
+                Ok(())
+            }
+
+            fn theme_files(&self, _: &CursorTheme, _: &Path) -> Vec<ThemeFile> {
+                Vec::new()
+            }
+        }
+
+        let mut registry = Registry::with_builtins();
+
+        assert!(registry.decoder("ani").is_some());
+        assert!(
+            registry
+                .sniff(b"BM\0\0\0\0\0\0\0\0\0\0\0\0\x28\0\0\0")
+                .is_some()
+        );
+        assert!(registry.sniff(b"Xcur\x10\0\0\0").is_none());
+        assert!(
+            registry
+                .by_extension(OsStr::new("PNG"))
+                .is_some_and(|d| d.name() == "image")
+        );
+        assert_eq!(
+            registry.encoder_names().collect::<Vec<_>>(),
+            ["cape", "xcursor"]
+        );
+        assert!(
+            registry
+                .encoder("cape")
+                .is_some_and(CursorEncoder::theme_only)
+        );
+        assert!(registry.encoder("xcursor").is_some_and(|e| !e.theme_only()));
+
+        registry.register_encoder(Null);
+        assert!(registry.encoder("cape").is_some_and(|e| !e.theme_only()));
+    }
+
+    /// Checks that built-in decoders agree with [`Format::sniff`], other than for Xcursor and GIF.
+    #[test]
+    fn builtins_agree_with_format() {
+        let registry = Registry::with_builtins();
+        let headers: [&[u8]; _] = [
+            b"RIFF\x10\0\0\0ACONanih",
+            &[0, 0, 2, 0, 1, 0, 32, 32],
+            &[0, 0, 1, 0, 3, 0, 16, 16],
+            b"Xcur\x10\0\0\0",
+            b"\x89PNG\r\n\x1a\n\0\0\0\x0d",
+            b"GIF89a\x20\0",
+            b"BM\x36\x10\0\0\0\0\0\0\x36\0\0\0\x28\0\0\0",
+            b"BMP files are bitmaps",
+        ];
+
+        for header in headers {
+            let format = Format::sniff(header);
+            let decoder = registry.sniff(header);
+
+            assert_eq!(
+                decoder.is_some(),
+                format.is_some_and(|f| !matches!(f, Format::Xcursor | Format::Gif)),
+                "{header:?}"
+            );
+            assert_eq!(
+                decoder.is_some_and(CursorDecoder::is_cursor_format),
+                format.is_some_and(Format::is_cursor),
+                "{header:?}"
+            );
+        }
+    }
+
+    /// Checks that a decoder from outside of the crate is detected by its signature and extension.
+    #[test]
+    fn custom_decoder() {
+        let blob = [
+            b"TCUR".as_slice(),
+            &cur(&[dib_4bpp(8, 8, [0, 0, 255, 255])]),
+        ]
+        .concat();
+        let options = DecodeOptions::default();
+
+        let dir = tempfile::tempdir().unwrap();
+        let tagged = dir.path().join("tagged.bin");
+        let by_ext = dir.path().join("tagged.TCUR");
+        fs::write(&tagged, &blob).unwrap();
+        fs::write(&by_ext, b"not a cursor").unwrap();
+
+        let builtin = Registry::with_builtins();
+        assert!(builtin.detect(&tagged).unwrap().is_none());
+        assert!(builtin.decode(&tagged, &options).is_err());
+
+        let mut registry = Registry::with_builtins();
+        registry.register_decoder(TaggedCur);
+
+        let detected = |path| registry.detect(path).unwrap().map(CursorDecoder::name);
+        assert_eq!(detected(&tagged), Some("tagged-cur"));
+        assert_eq!(detected(&by_ext), Some("tagged-cur"));
+        assert_eq!(
+            detected(Path::new(from_root!(
+                "/testing/fixtures/neuro/Neuro alt.ani"
+            ))),
+            Some("ani")
+        );
+
+        let cursor = registry.decode(&tagged, &options).unwrap();
+        assert_eq!(cursor.num_pixels(), 8 * 8);
+
+        let cursor = registry
+            .decode_reader(&mut Cursor::new(&blob), &options)
+            .unwrap();
+        assert_eq!(cursor.num_pixels(), 8 * 8);
+    }
+}
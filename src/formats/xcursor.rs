@@ -1,4 +1,5 @@
-//! Module used to write Xcursor from [`GenericCursor`].
+//! Module used to write Xcursor from [`GenericCursor`], and to list the chunks of
+//! existing Xcursor files with [`read_chunks`].
 //!
 //! The Xcursor format is stored as such:
 //!
@@ -18,11 +19,16 @@
 
 use crate::cursors::{cursor_image::CursorImage, generic_cursor::GenericCursor};
 
-use std::fmt;
+use std::{
+    error::Error,
+    fmt,
+    io::{Read, Seek, SeekFrom},
+};
 
-use anyhow::Result;
-use binrw::binwrite;
+use anyhow::{Context, Result, bail};
+use binrw::{BinReaderExt, binwrite};
 use bytemuck;
+use thiserror::Error;
 
 /// Versions numbers. May be subject to change.
 mod versions {
@@ -31,6 +37,12 @@ mod versions {
     pub const IMAGE: u32 = 1;
 }
 
+/// Limits on images, which are checked before writing.
+mod limits {
+    pub const IMAGE_SIZE: u32 = 2048;
+    pub const DELAY: u32 = 60_000;
+}
+
 /// Sizes (of fixed-size fields) for position calculations.
 mod sizes {
     pub const XCURSOR: u32 = 16;
@@ -138,16 +150,16 @@ struct ImageChunk {
     #[bw(calc = versions::IMAGE)]
     version: u32,
 
-    #[bw(assert(*width != 0, *width <= 2048))]
+    #[bw(assert(*width != 0, *width <= limits::IMAGE_SIZE))]
     width: u32,
-    #[bw(assert(*height != 0, *height <= 2048))]
+    #[bw(assert(*height != 0, *height <= limits::IMAGE_SIZE))]
     height: u32,
     #[bw(assert(hotspot_x <= width))]
     hotspot_x: u32,
     #[bw(assert(hotspot_y <= height))]
     hotspot_y: u32,
     /// Uses milliseconds.
-    #[bw(assert(*delay <= 60_000))]
+    #[bw(assert(*delay <= limits::DELAY))]
     delay: u32,
 
     /// Pre-multiplied big-endian ARGB image data.
@@ -208,6 +220,51 @@ impl fmt::Debug for ImageChunk {
     }
 }
 
+/// An error from converting a cursor to Xcursor, see [`Xcursor::new`].
+///
+/// This points to the chunk of the output that couldn't be written, by its offset.
+#[derive(Debug)]
+pub struct XcursorError {
+    /// Offset in the output of the offending chunk.
+    pub offset: u64,
+    /// What went wrong.
+    pub kind: XcursorErrorKind,
+}
+
+/// Kinds of [`XcursorError`].
+///
+/// Images are referred to by their index in [`GenericCursor::joined_images`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum XcursorErrorKind {
+    /// An image is larger than Xcursor allows.
+    #[error("image={index} ({width}x{height}) exceeds max size={max}")]
+    ImageTooLarge {
+        index: usize,
+        width: u32,
+        height: u32,
+        max: u32,
+    },
+    /// An image has a delay longer than Xcursor allows.
+    #[error("image={index} has delay={delay}ms, which exceeds max delay={max}ms")]
+    DelayTooLong { index: usize, delay: u32, max: u32 },
+    /// The file would be too large for the offsets in the table of contents.
+    #[error("file would exceed 4 GiB, which Xcursor offsets can't address")]
+    FileTooLarge,
+}
+
+impl fmt::Display for XcursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset={:#x}", self.kind, self.offset)
+    }
+}
+
+impl Error for XcursorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.kind.source()
+    }
+}
+
 /// Models the Xcursor format.
 ///
 /// This should produce a valid file when written.
@@ -250,8 +307,7 @@ impl Xcursor {
     ///
     /// ## Errors
     ///
-    /// If [`TryInto`] conversions fail between primitive
-    /// types. No other error can occur other than this.
+    /// If any images can't be stored as Xcursor, see [`XcursorError`].
     pub fn new(cursor: &GenericCursor) -> Result<Self> {
         let num_toc = cursor.num_images() + cursor.info().map_or(0, |_| 1);
         let num_toc_u32 = u32::try_from(num_toc)?;
@@ -271,14 +327,43 @@ impl Xcursor {
             None
         };
 
-        for image in cursor.joined_images() {
+        for (index, image) in cursor.joined_images().enumerate() {
+            let error = |kind| XcursorError {
+                offset: u64::from(position),
+                kind,
+            };
+
+            let (width, height) = image.dimensions();
+            let delay = image.delay();
+
+            if width > limits::IMAGE_SIZE || height > limits::IMAGE_SIZE {
+                return Err(error(XcursorErrorKind::ImageTooLarge {
+                    index,
+                    width,
+                    height,
+                    max: limits::IMAGE_SIZE,
+                })
+                .into());
+            }
+
+            if delay > limits::DELAY {
+                return Err(error(XcursorErrorKind::DelayTooLong {
+                    index,
+                    delay,
+                    max: limits::DELAY,
+                })
+                .into());
+            }
+
             let image_chunk_size = sizes::IMAGE + u32::try_from(image.rgba().len())?;
             let (chunk, toc_entry) = ImageChunk::new(image, position);
 
             toc.push(toc_entry);
             images.push(chunk);
 
-            position += image_chunk_size;
+            position = position
+                .checked_add(image_chunk_size)
+                .ok_or_else(|| error(XcursorErrorKind::FileTooLarge))?;
         }
 
         Ok(Self {
@@ -289,14 +374,126 @@ impl Xcursor {
     }
 }
 
+/// A chunk of an Xcursor file, without any pixel data. See [`read_chunks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XcursorChunk {
+    /// A comment, e.g., the title and author of the cursor.
+    Comment {
+        /// 1 for copyright, 2 for license, 3 for anything else.
+        role: u32,
+        /// The comment itself, with invalid UTF-8 replaced.
+        text: String,
+    },
+    /// An image.
+    Image {
+        /// The size this image is used for, usually the max of its dimensions.
+        nominal_size: u32,
+        /// Image dimensions as (width, height).
+        dimensions: (u32, u32),
+        /// Hotspot coordinates as (x, y).
+        hotspot: (u32, u32),
+        /// Delay in milliseconds.
+        delay: u32,
+    },
+}
+
+/// Reads the table of contents of the Xcursor file in `reader`,
+/// and the header of each chunk it points to.
+///
+/// Unknown chunk types are skipped. Pixel data isn't read.
+///
+/// ## Errors
+///
+/// If the magic bytes are wrong, or the table of contents or
+/// any chunk is malformed or extends beyond the file.
+pub fn read_chunks<R: Read + Seek>(reader: &mut R) -> Result<Vec<XcursorChunk>> {
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    reader.rewind()?;
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != *b"Xcur" {
+        bail!("expected magic=\"Xcur\", found {magic:02x?}");
+    }
+
+    let _header_size: u32 = reader.read_le()?;
+    let _version: u32 = reader.read_le()?;
+    let num_toc: u32 = reader.read_le()?;
+
+    if u64::from(num_toc) * u64::from(sizes::TOC) > stream_len {
+        bail!("num_toc={num_toc} is too large for file of size={stream_len}");
+    }
+
+    let toc: Vec<(u32, u32)> = (0..num_toc)
+        .map(|_| -> Result<_> {
+            let r#type: u32 = reader.read_le()?;
+            let _subtype: u32 = reader.read_le()?;
+            let position: u32 = reader.read_le()?;
+            Ok((r#type, position))
+        })
+        .collect::<Result<_>>()
+        .context("failed to read table of contents")?;
+
+    let mut chunks = Vec::with_capacity(toc.len());
+
+    for (r#type, position) in toc {
+        reader.seek(SeekFrom::Start(u64::from(position)))?;
+        let read_chunk = |reader: &mut R| -> Result<Option<XcursorChunk>> {
+            let _header_size: u32 = reader.read_le()?;
+            let _type: u32 = reader.read_le()?;
+            let subtype: u32 = reader.read_le()?;
+            let _version: u32 = reader.read_le()?;
+
+            if r#type == ChunkType::Comment as u32 {
+                let length: u32 = reader.read_le()?;
+                if u64::from(position) + u64::from(length) > stream_len {
+                    bail!("comment of length={length} extends beyond file");
+                }
+
+                let mut text = vec![0; usize::try_from(length)?];
+                reader.read_exact(&mut text)?;
+
+                Ok(Some(XcursorChunk::Comment {
+                    role: subtype,
+                    text: String::from_utf8_lossy(&text).into_owned(),
+                }))
+            } else if r#type == ChunkType::Image as u32 {
+                let [width, height, hotspot_x, hotspot_y, delay]: [u32; 5] = reader.read_le()?;
+
+                Ok(Some(XcursorChunk::Image {
+                    nominal_size: subtype,
+                    dimensions: (width, height),
+                    hotspot: (hotspot_x, hotspot_y),
+                    delay,
+                }))
+            } else {
+                Ok(None)
+            }
+        };
+
+        let chunk = read_chunk(reader)
+            .with_context(|| format!("failed to read chunk at offset={position:#x}"))?;
+        chunks.extend(chunk);
+    }
+
+    Ok(chunks)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{cursors::generic_cursor::tests::black_and_white, from_root};
+    use crate::{
+        cursors::{
+            cursor_image::CursorImages,
+            decode::{DecodeOptions, Limits},
+            generic_cursor::tests::black_and_white,
+        },
+        from_root, unwrap_err_as,
+    };
 
     use std::{
         fmt::Write,
-        io::{BufWriter, Seek, SeekFrom},
+        io::{BufWriter, Cursor},
         os::fd::IntoRawFd,
         ptr::NonNull,
     };
@@ -388,9 +585,11 @@ mod tests {
             argb: Vec::new(), // stored somewhere else
         };
 
-        let cursor =
-            GenericCursor::from_ani_path(from_root!("/testing/fixtures/neuro/Neuro help.ani"))
-                .unwrap();
+        let cursor = GenericCursor::from_ani_path(
+            from_root!("/testing/fixtures/neuro/Neuro help.ani"),
+            &DecodeOptions::default(),
+        )
+        .unwrap();
 
         let xcursor = Xcursor::new(&cursor).unwrap();
 
@@ -417,4 +616,57 @@ mod tests {
 
         assert_eq!(argb, EXPECTED_IMAGE_ARGB);
     }
+
+    /// Reads the chunks of a written Xcursor, checking them against the cursor.
+    #[test]
+    fn read_written_chunks() {
+        let mut cursor = black_and_white();
+        cursor
+            .add_scale(
+                2.0,
+                fast_image_resize::ResizeAlg::Nearest,
+                &Limits::default(),
+            )
+            .unwrap();
+
+        let mut data = Vec::new();
+        cursor.write_xcursor(&mut data).unwrap();
+        let chunks = read_chunks(&mut Cursor::new(data)).unwrap();
+
+        assert_eq!(chunks.len(), cursor.num_images());
+
+        for (chunk, image) in chunks.iter().zip(cursor.joined_images()) {
+            let expected = XcursorChunk::Image {
+                nominal_size: image.nominal_size(),
+                dimensions: image.dimensions(),
+                hotspot: image.hotspot(),
+                delay: image.delay(),
+            };
+
+            assert_eq!(*chunk, expected);
+        }
+
+        assert!(read_chunks(&mut Cursor::new(b"Xcux")).is_err());
+    }
+
+    /// Rejects images larger than libXcursor reads, pointing to the first image chunk.
+    #[test]
+    fn image_too_large() {
+        let image = CursorImage::new(4096, 1, 0, 0, vec![0; 4096 * 4], 0).unwrap();
+        let cursor =
+            GenericCursor::new_unscaled(CursorImages::try_from(vec![image]).unwrap(), None);
+
+        let err = unwrap_err_as::<XcursorError>(Xcursor::new(&cursor));
+
+        assert_eq!(err.offset, u64::from(sizes::XCURSOR + sizes::TOC));
+        assert!(matches!(
+            err.kind,
+            XcursorErrorKind::ImageTooLarge {
+                index: 0,
+                width: 4096,
+                height: 1,
+                max: limits::IMAGE_SIZE,
+            }
+        ));
+    }
 }
@@ -1,14 +1,18 @@
-//! Utilities related to paths.
+//! Utilities related to paths and writing files.
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
+use log::warn;
 
 use std::{
     borrow::ToOwned,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, BufWriter, ErrorKind, Write},
     path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::warn;
-
 /// Resolves each component in `path` case-insensitively.
 ///
 /// Mostly used for Windows to Linux path conversions.
@@ -53,28 +57,147 @@ pub fn resolve_icase(path: &Path) -> Result<Option<PathBuf>> {
     Ok(Some(resolved))
 }
 
-/// Attempts to find files in `dir` with file extensions in `extensions`.
+/// Attempts to find files in `dir` that match `predicate`.
 ///
-/// This assumes that `dir` exists and does not search recursively for the given extensions.
-/// The extensions must _not_ be prefixed with a dot (e.g., "png" instead of ".png").
+/// This assumes that `dir` exists and does not search recursively.
 ///
 /// ## Errors
 ///
 /// - if `dir` is not a directory
 /// - if [`Path::read_dir`] fails
-pub fn find_extensions_icase(
+pub fn find_files(
     dir: &Path,
-    extensions: &[&str],
+    predicate: impl Fn(&Path) -> bool,
 ) -> Result<impl Iterator<Item = PathBuf>> {
     let dir_display = dir.display();
     if !dir.metadata()?.is_dir() {
         bail!("expected dir={dir_display} to be a directory");
     }
 
-    Ok(read_dir(dir, true, false)?.filter(|p| {
-        p.extension()
-            .is_some_and(|ext| extensions.iter().any(|ele| ext.eq_ignore_ascii_case(ele)))
-    }))
+    Ok(read_dir(dir, true, false)?.filter(move |p| predicate(p)))
+}
+
+/// Writes a file to `path` atomically using `write`.
+///
+/// This writes to a temporary file next to `path`, which is renamed to `path` once
+/// `write` succeeds and the data is synced to disk, so `path` is never left partially
+/// written. On failure, the temporary file is removed.
+///
+/// ## Errors
+///
+/// If `write` fails, or for general fs issues.
+pub fn write_atomic(path: &Path, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let (tmp, file) = create_temp_sibling(path, |tmp| {
+        OpenOptions::new().write(true).create_new(true).open(tmp)
+    })?;
+
+    let result = (|| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+
+        let file = writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        file.sync_all()
+            .with_context(|| format!("failed to sync path={}", tmp.display()))?;
+
+        fs::rename(&tmp, path)
+            .with_context(|| format!("failed to rename {} to {}", tmp.display(), path.display()))
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Creates the directory at `path` atomically, filling it with `write`.
+///
+/// Like [`write_atomic`], this fills a temporary directory next to `path`, which replaces
+/// `path` once `write` succeeds. If `path` already exists, it's only removed after the new
+/// directory is in place. On failure, the temporary directory is removed.
+///
+/// ## Errors
+///
+/// If `write` fails, or for general fs issues.
+pub fn create_dir_atomic(path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let (tmp, ()) = create_temp_sibling(path, |tmp| fs::create_dir(tmp))?;
+    let result = write(&tmp).and_then(|()| replace_dir(&tmp, path));
+
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    result
+}
+
+/// Helper function for [`create_dir_atomic`], moving `src` to `dst`, replacing it if it exists.
+fn replace_dir(src: &Path, dst: &Path) -> Result<()> {
+    if !dst.try_exists()? {
+        return fs::rename(src, dst)
+            .with_context(|| format!("failed to rename {} to {}", src.display(), dst.display()));
+    }
+
+    // directories can't be renamed over non-empty ones, so move the old one aside first
+    let old = temp_sibling(dst);
+    fs::rename(dst, &old).with_context(|| format!("failed to move aside dir={}", dst.display()))?;
+
+    if let Err(err) = fs::rename(src, dst) {
+        let _ = fs::rename(&old, dst);
+        return Err(err)
+            .with_context(|| format!("failed to rename {} to {}", src.display(), dst.display()));
+    }
+
+    // the new directory is already in place, so a leftover old one isn't a failure
+    if let Err(err) = fs::remove_dir_all(&old) {
+        warn!("failed to remove old dir={}: {err}", old.display());
+    }
+
+    Ok(())
+}
+
+/// Helper function for [`write_atomic`] and [`create_dir_atomic`], creating a unique
+/// temporary sibling of `path` with `create`, which must fail if the path already exists.
+fn create_temp_sibling<T>(
+    path: &Path,
+    create: impl Fn(&Path) -> io::Result<T>,
+) -> Result<(PathBuf, T)> {
+    if path.file_name().is_none() {
+        bail!("no file name for path={}", path.display());
+    }
+
+    // a previous process with the same id may have left files behind
+    loop {
+        let tmp = temp_sibling(path);
+        match create(&tmp) {
+            Ok(created) => return Ok((tmp, created)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("(filesystem) failed to create path={}", tmp.display())
+                });
+            }
+        }
+    }
+}
+
+/// Helper function returning a hidden path next to `path` that's unique to this call,
+/// as cursors and themes with the same name may be written in parallel.
+///
+/// `path` must have a file name.
+fn temp_sibling(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(
+        ".{}.{}.tmp",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    path.with_file_name(name)
 }
 
 /// Helper function for reading `dir` robustly.
@@ -104,3 +227,53 @@ fn read_dir(
                 .is_ok_and(|m| allow_file && m.is_file() || allow_dir && m.is_dir())
         }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    /// Failed writes leave existing files alone, and same-named writes don't clash.
+    #[test]
+    fn atomic_writes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let theme_dir = dir.path().join("theme");
+
+        fs::create_dir(&theme_dir)?;
+        fs::write(theme_dir.join("old"), "old")?;
+
+        // failures leave the existing dir untouched, with no temporary files
+        let result = create_dir_atomic(&theme_dir, |tmp| {
+            write_atomic(&tmp.join("new"), |w| Ok(w.write_all(b"new")?))?;
+            bail!("failed halfway")
+        });
+
+        assert!(result.is_err());
+        assert!(theme_dir.join("old").exists());
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+
+        create_dir_atomic(&theme_dir, |tmp| {
+            write_atomic(&tmp.join("new"), |w| Ok(w.write_all(b"new")?))
+        })?;
+
+        assert!(!theme_dir.join("old").exists());
+        assert_eq!(fs::read_to_string(theme_dir.join("new"))?, "new");
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+        assert_eq!(fs::read_dir(&theme_dir)?.count(), 1);
+
+        // parallel writes to one path each get their own temporary file
+        let path = theme_dir.join("new");
+        assert_ne!(temp_sibling(&path), temp_sibling(&path));
+
+        (0..16u8)
+            .into_par_iter()
+            .try_for_each(|i| write_atomic(&path, |w| Ok(w.write_all(&[i; 4096])?)))?;
+
+        let data = fs::read(&path)?;
+        assert_eq!(data.len(), 4096);
+        assert!(data.iter().all(|&b| b == data[0]));
+        assert_eq!(fs::read_dir(&theme_dir)?.count(), 1);
+
+        Ok(())
+    }
+}
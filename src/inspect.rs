@@ -0,0 +1,667 @@
+//! Module for [`Command::Inspect`](crate::cli::Command::Inspect).
+//!
+//! Each path is read into an [`Inspection`], which describes the parsed structure of the
+//! file (rather than the converted cursor). These are printed as JSON, or as an indented tree.
+
+use crate::{
+    cli::split_paths_by,
+    cursors::decode::DecodeOptions,
+    formats::{
+        ani::{AniError, AniFile},
+        detect::Format,
+        dib::{self, DibHeader},
+        registry::Registry,
+        xcursor::{XcursorChunk, read_chunks},
+    },
+    fs_utils::resolve_icase,
+    themes::theme::{CursorMapping, parse_installer},
+};
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use ico::IconDir;
+use serde::Serialize;
+use serde_json::Value;
+
+/// The parsed structure of a file.
+#[derive(Debug, Serialize)]
+pub struct Inspection {
+    /// The path that was inspected.
+    path: PathBuf,
+    /// Only present if the file could be read.
+    #[serde(flatten)]
+    report: Option<Report>,
+    /// Why the file couldn't be read, if it couldn't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The parsed structure of a file, for each kind of file.
+#[derive(Debug, Serialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+enum Report {
+    /// A theme installer, i.e, INF or CRS.
+    Installer {
+        /// Name of the theme. CRS files have no name.
+        name: String,
+        mappings: Vec<MappingReport>,
+    },
+    /// An animated cursor.
+    Ani {
+        header: AniHeaderReport,
+        /// Strings in the "INFO" chunk.
+        info: AniInfoReport,
+        /// Fourccs of chunks skipped when parsing leniently.
+        skipped: Vec<String>,
+        /// Per-step timings, in jiffies (1/60s).
+        rate: Option<Vec<u32>>,
+        /// Order in which frames are played.
+        sequence: Option<Vec<u32>>,
+        frames: Vec<FrameReport>,
+    },
+    /// A static cursor.
+    Cur { entries: Vec<EntryReport> },
+    /// An icon, which is a cursor without a hotspot.
+    Ico { entries: Vec<EntryReport> },
+    /// An X11 cursor.
+    Xcursor {
+        comments: Vec<CommentReport>,
+        images: Vec<ImageReport>,
+    },
+    /// A plain image, which is read as a cursor.
+    Image {
+        /// The type of image, e.g., "png".
+        kind: String,
+        frames: Vec<ImageReport>,
+    },
+}
+
+/// A [`CursorMapping`] and where its path resolves to.
+#[derive(Debug, Serialize)]
+struct MappingReport {
+    role: String,
+    /// The path as stored in the installer.
+    path: PathBuf,
+    /// The path found on disk (case-insensitively), if any.
+    resolved: Option<PathBuf>,
+}
+
+/// Fields of an [`crate::formats::ani::AniHeader`].
+#[derive(Debug, Serialize)]
+struct AniHeaderReport {
+    num_frames: u32,
+    num_steps: u32,
+    /// Default rate, in jiffies (1/60s).
+    jiffy_rate: u32,
+    /// `AF_ICON`.
+    icon_frames: bool,
+    /// `AF_SEQUENCE`.
+    sequenced: bool,
+}
+
+/// Strings in the "INFO" chunk of an ANI file.
+#[derive(Debug, Serialize)]
+struct AniInfoReport {
+    title: Option<String>,
+    author: Option<String>,
+    copyright: Option<String>,
+    comments: Option<String>,
+    software: Option<String>,
+}
+
+/// A frame of an ANI file, and the images it contains.
+#[derive(Debug, Serialize)]
+struct FrameReport {
+    /// Offset of the frame data from the start of the file.
+    offset: u64,
+    /// Size of the frame data.
+    size: u32,
+    entries: Vec<EntryReport>,
+}
+
+/// An image stored in an ICO or CUR file, or a raw bitmap (without a hotspot).
+#[derive(Debug, Serialize)]
+struct EntryReport {
+    width: u32,
+    height: u32,
+    /// True if stored as PNG, rather than as a bitmap.
+    png: bool,
+    /// Only present for bitmaps.
+    bits_per_pixel: Option<u16>,
+    /// Only present for cursors.
+    hotspot: Option<(u16, u16)>,
+}
+
+/// A comment in an Xcursor file.
+#[derive(Debug, Serialize)]
+struct CommentReport {
+    /// 1 for copyright, 2 for license, 3 for anything else.
+    role: u32,
+    text: String,
+}
+
+/// An image, as read by currust.
+#[derive(Debug, Serialize)]
+struct ImageReport {
+    /// Only present for Xcursor.
+    nominal_size: Option<u32>,
+    width: u32,
+    height: u32,
+    hotspot: (u32, u32),
+    /// In milliseconds.
+    delay: u32,
+}
+
+impl Inspection {
+    /// Inspects the installer or cursor file at `path`.
+    ///
+    /// Formats that are only known to `registry` are reported as they're read by it.
+    /// If `path` can't be read or parsed, this has the error instead of a report.
+    fn new(path: &Path, is_installer: bool, registry: &Registry, options: &DecodeOptions) -> Self {
+        let report = || {
+            if is_installer {
+                Report::installer(path)
+            } else if let Some(format) = Format::detect(path)? {
+                Report::cursor(path, format, registry, options)
+            } else {
+                let decoder = registry
+                    .detect(path)?
+                    .ok_or_else(|| anyhow!("unknown format of path={}", path.display()))?;
+
+                Report::image(path, decoder.name(), registry, options)
+            }
+        };
+
+        let (report, error) = match report() {
+            Ok(report) => (Some(report), None),
+            Err(e) => (None, Some(format!("{e:#}"))),
+        };
+
+        Self {
+            path: path.to_path_buf(),
+            report,
+            error,
+        }
+    }
+}
+
+impl Report {
+    /// Reads the name and mappings of the INF or CRS file at `path`.
+    fn installer(path: &Path) -> Result<Self> {
+        let (name, mappings) = parse_installer(path)?;
+
+        let mappings = mappings
+            .into_iter()
+            .map(|CursorMapping { r#type, path }| {
+                Ok(MappingReport {
+                    role: format!("{type:?}"),
+                    resolved: resolve_icase(&path)?,
+                    path,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self::Installer { name, mappings })
+    }
+
+    /// Reads the structure of the cursor file at `path`, which is in `format`.
+    fn cursor(
+        path: &Path,
+        format: Format,
+        registry: &Registry,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let report = match format {
+            Format::Ani => {
+                // parsing errors don't know which file they're from
+                Self::ani(&mut reader, options).map_err(|e| match e.downcast::<AniError>() {
+                    Ok(e) => e.with_path(path).into(),
+                    Err(e) => e,
+                })?
+            }
+            Format::Cur => Self::Cur {
+                entries: entries(&mut reader)?,
+            },
+            Format::Ico => Self::Ico {
+                entries: entries(&mut reader)?,
+            },
+            Format::Xcursor => Self::xcursor(&mut reader)?,
+            Format::Gif => bail!("GIF images can't be read as cursors"),
+            Format::Png | Format::Bmp => {
+                let kind = format!("{format:?}").to_ascii_lowercase();
+                Self::image(path, &kind, registry, options)?
+            }
+        };
+
+        Ok(report)
+    }
+
+    /// Reads the cursor at `path` with `registry`, reporting its images as `kind`.
+    fn image(
+        path: &Path,
+        kind: &str,
+        registry: &Registry,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let cursor = registry.decode(path, options)?;
+        let frames = cursor
+            .base_images()
+            .inner()
+            .iter()
+            .map(|image| {
+                let (width, height) = image.dimensions();
+
+                ImageReport {
+                    nominal_size: None,
+                    width,
+                    height,
+                    hotspot: image.hotspot(),
+                    delay: image.delay(),
+                }
+            })
+            .collect();
+
+        Ok(Self::Image {
+            kind: kind.to_owned(),
+            frames,
+        })
+    }
+
+    /// Helper function for [`Self::cursor`], for ANI files.
+    fn ani<R: Read + Seek>(reader: &mut R, options: &DecodeOptions) -> Result<Self> {
+        let ani = AniFile::from_reader(reader, options)?;
+        let to_string = |s: &Option<binrw::NullString>| s.as_ref().map(ToString::to_string);
+
+        let frames = ani
+            .ico_frames
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let data = chunk.read(reader)?;
+
+                // like when converting, frames are checked one by one rather than by
+                // AF_ICON, and raw frames are wrapped so their dimensions are the same
+                let entries = if dib::is_icon_dir(&data) {
+                    entries(&mut Cursor::new(data))
+                } else {
+                    dib::wrap_as_cur(&data, (0, 0)).and_then(|cur| {
+                        let mut entries = entries(&mut Cursor::new(cur))?;
+                        for entry in &mut entries {
+                            entry.hotspot = None;
+                        }
+
+                        Ok(entries)
+                    })
+                };
+
+                Ok(FrameReport {
+                    offset: chunk.offset(),
+                    size: chunk.size(),
+                    entries: entries.with_context(|| format!("in frame={i}"))?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self::Ani {
+            header: AniHeaderReport {
+                num_frames: ani.header.num_frames,
+                num_steps: ani.header.num_steps,
+                jiffy_rate: ani.header.jiffy_rate,
+                icon_frames: ani.header.has_icon_frames(),
+                sequenced: ani.header.is_sequenced(),
+            },
+            info: AniInfoReport {
+                title: to_string(&ani.title),
+                author: to_string(&ani.author),
+                copyright: to_string(&ani.copyright),
+                comments: to_string(&ani.comments),
+                software: to_string(&ani.software),
+            },
+            skipped: ani.skipped,
+            rate: ani.rate.map(|r| r.data),
+            sequence: ani.sequence.map(|s| s.data),
+            frames,
+        })
+    }
+
+    /// Helper function for [`Self::cursor`], for Xcursor files.
+    fn xcursor<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let mut comments = Vec::new();
+        let mut images = Vec::new();
+
+        for chunk in read_chunks(reader)? {
+            match chunk {
+                XcursorChunk::Comment { role, text } => comments.push(CommentReport { role, text }),
+                XcursorChunk::Image {
+                    nominal_size,
+                    dimensions: (width, height),
+                    hotspot,
+                    delay,
+                } => images.push(ImageReport {
+                    nominal_size: Some(nominal_size),
+                    width,
+                    height,
+                    hotspot,
+                    delay,
+                }),
+            }
+        }
+
+        Ok(Self::Xcursor { comments, images })
+    }
+}
+
+/// Reads the entries of the ICO or CUR file in `reader`.
+fn entries<R: Read + Seek>(reader: &mut R) -> Result<Vec<EntryReport>> {
+    let icon_dir = IconDir::read(reader).context("failed to read `IconDir`")?;
+
+    Ok(icon_dir
+        .entries()
+        .iter()
+        .map(|e| EntryReport {
+            width: e.width(),
+            height: e.height(),
+            png: e.is_png(),
+            // the entry's own field holds the hotspot for cursors, so read the bitmap instead
+            bits_per_pixel: (!e.is_png())
+                .then(|| DibHeader::read(e.data()).ok())
+                .flatten()
+                .map(|dib| dib.bit_count),
+            hotspot: e.cursor_hotspot(),
+        })
+        .collect())
+}
+
+/// Prints the structure of each installer and cursor file in `paths`, as JSON if `json` is set.
+///
+/// Files that can't be read or parsed are still printed, with the error instead.
+///
+/// ## Errors
+///
+/// If any path can't be read or parsed, after everything is printed.
+pub fn inspect(
+    paths: Vec<PathBuf>,
+    json: bool,
+    registry: &Registry,
+    options: &DecodeOptions,
+) -> Result<()> {
+    // unlike other commands, this includes files that can't be converted (e.g., Xcursor)
+    let (installer_files, cursor_files) =
+        split_paths_by(paths, |path, explicit| match Format::detect(path) {
+            Ok(Some(format)) => explicit || format.is_cursor() || format == Format::Xcursor,
+            _ => registry
+                .detect(path)
+                .is_ok_and(|d| d.is_some_and(|d| explicit || d.is_cursor_format())),
+        })?;
+
+    let inspections: Vec<_> = installer_files
+        .iter()
+        .map(|p| (p, true))
+        .chain(cursor_files.iter().map(|p| (p, false)))
+        .map(|(path, is_installer)| Inspection::new(path, is_installer, registry, options))
+        .collect();
+
+    let mut stdout = io::stdout().lock();
+
+    if json {
+        serde_json::to_writer_pretty(&mut stdout, &inspections)?;
+        writeln!(stdout)?;
+    } else {
+        for (i, inspection) in inspections.iter().enumerate() {
+            if i > 0 {
+                writeln!(stdout)?;
+            }
+
+            write_tree(&mut stdout, &serde_json::to_value(inspection)?, 0)?;
+        }
+    }
+
+    let failed = inspections.iter().filter(|i| i.error.is_some()).count();
+
+    if failed > 0 {
+        bail!(
+            "{failed} of {} path(s) couldn't be inspected",
+            inspections.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Writes `value` as an indented tree of "key: value" lines, starting at `depth`.
+///
+/// Arrays of scalars (e.g., rate tables) are written on one line, and empty values are skipped.
+fn write_tree(writer: &mut impl Write, value: &Value, depth: usize) -> io::Result<()> {
+    let indent = "  ".repeat(depth);
+
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter().filter(|(_, v)| !is_empty(v)) {
+                if is_scalar(value) {
+                    writeln!(writer, "{indent}{key}: {}", scalar(value))?;
+                } else {
+                    writeln!(writer, "{indent}{key}:")?;
+                    write_tree(writer, value, depth + 1)?;
+                }
+            }
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                if is_scalar(value) {
+                    writeln!(writer, "{indent}[{i}] {}", scalar(value))?;
+                } else {
+                    writeln!(writer, "{indent}[{i}]")?;
+                    write_tree(writer, value, depth + 1)?;
+                }
+            }
+        }
+        value => writeln!(writer, "{indent}{}", scalar(value))?,
+    }
+
+    Ok(())
+}
+
+/// Returns true if `value` is null, or an object with only empty values.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Object(map) => map.values().all(is_empty),
+        _ => false,
+    }
+}
+
+/// Returns true if `value` can be written on one line by [`scalar`].
+fn is_scalar(value: &Value) -> bool {
+    match value {
+        Value::Array(values) => values.iter().all(|v| !v.is_array() && !v.is_object()),
+        Value::Object(_) => false,
+        _ => true,
+    }
+}
+
+/// Helper function for [`write_tree`], formatting a scalar (or array of them) `value`.
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Array(values) => {
+            let values: Vec<_> = values.iter().map(scalar).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Value::String(s) => format!("{s:?}"),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use serde_json::json;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/testing/fixtures/neuro/Neuro alt.ani"
+    );
+
+    /// Returns the keys of the object `value`, in order.
+    fn keys(value: &Value) -> Vec<&str> {
+        value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Checks the JSON shape of an inspected ANI file.
+    #[test]
+    fn ani_json() {
+        let registry = Registry::with_builtins();
+        let inspection = Inspection::new(
+            Path::new(FIXTURE),
+            false,
+            &registry,
+            &DecodeOptions::default(),
+        );
+        let value = serde_json::to_value(&inspection).unwrap();
+
+        assert_eq!(
+            keys(&value),
+            [
+                "path", "format", "header", "info", "skipped", "rate", "sequence", "frames"
+            ]
+        );
+        assert_eq!(value["path"], FIXTURE);
+        assert_eq!(value["format"], "ani");
+        assert_eq!(
+            value["header"],
+            json!({
+                "num_frames": 10,
+                "num_steps": 21,
+                "jiffy_rate": 6,
+                "icon_frames": true,
+                "sequenced": true,
+            })
+        );
+        assert_eq!(
+            value["info"],
+            json!({
+                "title": null,
+                "author": null,
+                "copyright": null,
+                "comments": null,
+                "software": null,
+            })
+        );
+        assert_eq!(value["skipped"], json!([]));
+        assert_eq!(value["rate"], Value::Null);
+        assert_eq!(
+            value["sequence"],
+            json!([
+                0, 1, 2, 2, 3, 3, 3, 3, 4, 5, 6, 7, 3, 3, 3, 2, 2, 2, 3, 8, 9
+            ])
+        );
+
+        let frames = value["frames"].as_array().unwrap();
+        assert_eq!(frames.len(), 10);
+
+        // the "fram" chunk is at 56, so the first frame's data is after its headers
+        let frame = &frames[0];
+        assert_eq!(keys(frame), ["offset", "size", "entries"]);
+        assert_eq!(frame["offset"], 56 + 12 + 8);
+
+        for entry in frames.iter().flat_map(|f| f["entries"].as_array().unwrap()) {
+            assert_eq!(
+                keys(entry),
+                ["width", "height", "png", "bits_per_pixel", "hotspot"]
+            );
+            assert!(entry["hotspot"].is_array());
+        }
+    }
+
+    /// Checks that a raw frame is found by its contents even though `AF_ICON` is set,
+    /// and is reported with the same dimensions as when it's converted.
+    #[test]
+    fn raw_frame_json() {
+        let chunk = |fourcc: &[u8; 4], data: &[u8]| {
+            let mut chunk = fourcc.to_vec();
+            chunk.extend_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+            chunk.extend_from_slice(data);
+            chunk
+        };
+
+        // 2x4 at 32-bpp without an AND mask, so the height isn't doubled
+        let mut dib = Vec::new();
+        for field in [40_u32, 2, 4] {
+            dib.extend_from_slice(&field.to_le_bytes());
+        }
+        dib.extend_from_slice(&[1, 0, 32, 0]);
+        dib.extend_from_slice(&[0; 24]);
+        dib.extend_from_slice(&[255; 2 * 4 * 4]);
+
+        // one frame and step, a rate of 10 jiffies, and AF_ICON set
+        let mut anih = Vec::new();
+        for field in [36_u32, 1, 1, 0, 0, 0, 0, 10, 1] {
+            anih.extend_from_slice(&field.to_le_bytes());
+        }
+
+        let fram = [b"fram".as_slice(), &chunk(b"icon", &dib)].concat();
+        let acon = [
+            b"ACON".as_slice(),
+            &chunk(b"anih", &anih),
+            &chunk(b"LIST", &fram),
+        ]
+        .concat();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raw.ani");
+        fs::write(&path, chunk(b"RIFF", &acon)).unwrap();
+
+        let inspection = Inspection::new(
+            &path,
+            false,
+            &Registry::with_builtins(),
+            &DecodeOptions::default(),
+        );
+        let value = serde_json::to_value(&inspection).unwrap();
+
+        assert_eq!(value["header"]["icon_frames"], true);
+        assert_eq!(
+            value["frames"][0]["entries"],
+            json!([{
+                "width": 2,
+                "height": 4,
+                "png": false,
+                "bits_per_pixel": 32,
+                "hotspot": null,
+            }])
+        );
+    }
+
+    /// Checks that files that can't be read have an error instead of a report.
+    #[test]
+    fn error_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.cur");
+        fs::write(&path, [0, 0, 2, 0, 1, 0]).unwrap();
+
+        let inspection = Inspection::new(
+            &path,
+            false,
+            &Registry::with_builtins(),
+            &DecodeOptions::default(),
+        );
+        let value = serde_json::to_value(&inspection).unwrap();
+
+        assert_eq!(keys(&value), ["path", "error"]);
+        assert!(value["error"].is_string());
+    }
+}
@@ -0,0 +1,138 @@
+//! A library for converting cursors between Windows (CUR/ANI), Linux (Xcursor) and macOS (cape).
+//!
+//! Cursors are read into a [`GenericCursor`], which holds every frame and size of a cursor.
+//! These can be read from paths or from anything that implements [`Read`](std::io::Read)
+//! and [`Seek`](std::io::Seek), and written to anything that implements [`Write`](std::io::Write).
+//!
+//! ```no_run
+//! use std::{fs::File, io::BufReader};
+//!
+//! use currust::{DecodeOptions, GenericCursor};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let reader = BufReader::new(File::open("arrow.ani")?);
+//! let cursor = GenericCursor::from_reader(reader, &DecodeOptions::default())?;
+//!
+//! let mut xcursor = Vec::new();
+//! cursor.write_xcursor(&mut xcursor)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Themes are represented by [`CursorTheme`], where each cursor has a [`CursorType`].
+//! For adding formats, see [`Registry`].
+//!
+//! Errors are returned as [`anyhow::Error`]. Errors from parsing and writing formats, and
+//! from creating themes, are typed ([`AniError`], [`InfError`], [`XcursorError`] and
+//! [`ThemeError`]), so use [`anyhow::Error::downcast_ref`] to match on them (e.g., on
+//! [`AniError::kind`]). This works even if context has been added to the error.
+//!
+//! Warnings and other diagnostics are logged with the [`log`] crate, tagged with the
+//! theme and file being processed through [`logging::Context`].
+//!
+//! A C API is also exposed in [`ffi`], with a header at `include/currust.h`.
+//!
+//! The CLI itself is exposed as [`run`], which takes a [`Registry`], so formats
+//! can be added to it without forking the `currust` binary:
+//!
+//! ```no_run
+//! use std::process::ExitCode;
+//!
+//! use currust::Registry;
+//!
+//! fn main() -> anyhow::Result<ExitCode> {
+//!     let mut registry = Registry::with_builtins();
+//!     // registry.register_decoder(MyDecoder);
+//!     currust::run(&registry)
+//! }
+//! ```
+
+#![warn(
+    clippy::pedantic,
+    // nursery lints:
+    clippy::use_self,
+    clippy::or_fun_call,
+    clippy::redundant_clone,
+    clippy::equatable_if_let,
+    clippy::needless_collect,
+    // restriction lints:
+    clippy::redundant_type_annotations,
+    clippy::semicolon_inside_block,
+    clippy::allow_attributes
+)]
+#![allow(
+    clippy::enum_glob_use,
+    reason = "when used, scope is restricted (e.g., inside functions)"
+)]
+
+mod cli;
+mod commands;
+mod config;
+pub mod cursors;
+pub mod ffi;
+pub mod formats;
+pub mod fs_utils;
+mod inspect;
+mod logger;
+pub mod logging;
+pub mod themes;
+
+pub use crate::{
+    commands::run,
+    cursors::{
+        cursor_image::{CursorImage, CursorImages},
+        decode::{DecodeOptions, InvertedPixels, Limits, MissingSizes},
+        generic_cursor::GenericCursor,
+    },
+    formats::{
+        ani::{AniError, AniErrorKind, AniFile},
+        cape::Cape,
+        detect::Format,
+        inf::{InfError, InfErrorKind},
+        registry::{CursorDecoder, CursorEncoder, Registry},
+        xcursor::{Xcursor, XcursorError, XcursorErrorKind},
+    },
+    themes::theme::{CursorTheme, CursorType, ThemeError, TypedCursor},
+};
+
+/// Helper for compile-time paths for tests.
+#[cfg(test)]
+macro_rules! from_root {
+    ($path:literal) => {
+        concat!(env!("CARGO_MANIFEST_DIR"), $path)
+    };
+}
+
+#[cfg(test)]
+use from_root;
+
+/// Helper for tests, returning the error of `result` as the typed error `E`.
+#[cfg(test)]
+fn unwrap_err_as<E>(result: anyhow::Result<impl std::fmt::Debug>) -> E
+where
+    E: std::fmt::Display + std::fmt::Debug + Send + Sync + 'static,
+{
+    result.unwrap_err().downcast().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Context;
+
+    /// Matches on typed errors through [`anyhow::Error`], including with added context.
+    #[test]
+    fn typed_errors() {
+        let err = AniFile::from_blob(b"RIFF\x04\0\0\0WAVE", &DecodeOptions::default())
+            .context("while reading arrow.ani")
+            .unwrap_err();
+        let ani = err.downcast_ref::<AniError>().unwrap();
+        assert!(matches!(ani.kind, AniErrorKind::UnexpectedFourcc { .. }));
+        assert!(err.downcast_ref::<ThemeError>().is_none());
+
+        let err = CursorTheme::new(Vec::new(), String::new()).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ThemeError::Empty)));
+        assert_eq!(err.to_string(), ThemeError::Empty.to_string());
+    }
+}
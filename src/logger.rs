@@ -0,0 +1,196 @@
+//! Module for the [`Logger`] used by the CLI.
+//!
+//! Each message is written to stderr as a single line, tagged with the theme and file from
+//! [`Context::current`], either as text or as a JSON object (i.e., JSON lines).
+
+use crate::logging::Context;
+
+use std::io::{self, Write};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use dialoguer::console::style;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+/// Format of logged messages.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable, e.g., "[warning] message (theme=..., file=...)".
+    Text,
+    /// One JSON object per line, with "level", "message", "theme" and "file" keys.
+    Json,
+}
+
+/// Logger for messages from currust, see [`init`].
+struct Logger {
+    format: LogFormat,
+}
+
+/// A message, as logged with [`LogFormat::Json`].
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    level: String,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // messages from dependencies are ignored
+        metadata.level() <= log::max_level() && metadata.target().starts_with("currust")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let context = Context::current();
+        let line = match self.format {
+            LogFormat::Text => text_line(record, &context),
+            LogFormat::Json => json_line(record, &context),
+        };
+
+        // written all at once, so that lines from different threads don't interleave
+        let _ = io::stderr().lock().write_all(line.as_bytes());
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Helper function for [`Logger`], formatting `record` as text.
+fn text_line(record: &Record, context: &Context) -> String {
+    let message = record.args().to_string();
+    let mut line = match record.level() {
+        Level::Error => format!("{} {}", style("[error]").red(), style(message).red()),
+        Level::Warn => format!(
+            "{} {}",
+            style("[warning]").yellow(),
+            style(message).yellow()
+        ),
+        Level::Info => message,
+        Level::Debug => format!("{} {message}", style("[debug]").dim()),
+        Level::Trace => format!("{} {message}", style("[trace]").dim()),
+    };
+
+    let tags: Vec<_> = [
+        context.theme.as_ref().map(|theme| format!("theme={theme}")),
+        context
+            .file
+            .as_ref()
+            .map(|file| format!("file={}", file.display())),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if !tags.is_empty() {
+        line += &style(format!(" ({})", tags.join(", "))).dim().to_string();
+    }
+
+    line + "\n"
+}
+
+/// Helper function for [`Logger`], formatting `record` as a JSON line.
+fn json_line(record: &Record, context: &Context) -> String {
+    let json = JsonRecord {
+        level: record.level().as_str().to_ascii_lowercase(),
+        message: record.args().to_string(),
+        theme: context.theme.as_deref(),
+        file: context.file.as_ref().map(|f| f.display().to_string()),
+    };
+
+    // serializing strings can't fail
+    serde_json::to_string(&json).unwrap_or_default() + "\n"
+}
+
+/// Returns the level to log at, starting from [`LevelFilter::Info`].
+///
+/// Each `verbose` raises the level (debug, then trace), and each `quiet` lowers it (warnings,
+/// then errors, then nothing).
+#[must_use]
+pub fn level(verbose: u8, quiet: u8) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+
+    let i = (3 + usize::from(verbose)).saturating_sub(usize::from(quiet));
+    LEVELS[i.min(LEVELS.len() - 1)]
+}
+
+/// Sets up logging to stderr, for messages at `level` and above in `format`.
+///
+/// ## Errors
+///
+/// If a logger was already set up.
+pub fn init(level: LevelFilter, format: LogFormat) -> Result<()> {
+    log::set_boxed_logger(Box::new(Logger { format }))?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use serde_json::{Value, json};
+
+    /// Maps `-v` and `-q` counts to levels, clamping at either end.
+    #[test]
+    fn levels() {
+        assert_eq!(level(0, 0), LevelFilter::Info);
+        assert_eq!(level(1, 0), LevelFilter::Debug);
+        assert_eq!(level(2, 0), LevelFilter::Trace);
+        assert_eq!(level(5, 0), LevelFilter::Trace);
+        assert_eq!(level(0, 1), LevelFilter::Warn);
+        assert_eq!(level(0, 2), LevelFilter::Error);
+        assert_eq!(level(0, 3), LevelFilter::Off);
+        assert_eq!(level(0, 5), LevelFilter::Off);
+    }
+
+    /// Formats messages as one JSON object per line, omitting missing context.
+    #[test]
+    fn json_lines() {
+        let context = Context {
+            theme: Some(String::from("Neuro-sama Cursor")),
+            file: Some(PathBuf::from("neuro/Neuro help.ani")),
+        };
+        let record = Record::builder()
+            .args(format_args!("line one\n\"quoted\""))
+            .level(Level::Warn)
+            .build();
+
+        let line = json_line(&record, &context);
+        assert!(line.ends_with('\n'));
+        assert_eq!(line.lines().count(), 1);
+        assert_eq!(
+            serde_json::from_str::<Value>(&line).unwrap(),
+            json!({
+                "level": "warn",
+                "message": "line one\n\"quoted\"",
+                "theme": "Neuro-sama Cursor",
+                "file": "neuro/Neuro help.ani",
+            })
+        );
+
+        let line = json_line(&record, &Context::default());
+        assert_eq!(
+            serde_json::from_str::<Value>(&line).unwrap(),
+            json!({ "level": "warn", "message": "line one\n\"quoted\"" })
+        );
+    }
+}
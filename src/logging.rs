@@ -0,0 +1,89 @@
+//! Module for the [`Context`] that log messages are tagged with.
+//!
+//! Messages are logged with the [`log`] crate, so nothing is printed unless a logger is set up.
+//! Loggers can use [`Context::current`] to tag each message with the theme and file being
+//! processed, as messages from different threads are otherwise hard to tell apart.
+
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+thread_local! {
+    static CURRENT: RefCell<Context> = RefCell::default();
+}
+
+/// The theme and file being processed on the current thread.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Context {
+    /// Name of the theme, if any.
+    pub theme: Option<String>,
+    /// Path of the file, if any.
+    pub file: Option<PathBuf>,
+}
+
+impl Context {
+    /// Returns the context of the current thread.
+    #[must_use]
+    pub fn current() -> Self {
+        CURRENT.with_borrow(Clone::clone)
+    }
+
+    /// Returns `self` with `theme` as the theme name.
+    #[must_use]
+    pub fn with_theme(mut self, theme: impl Into<String>) -> Self {
+        self.theme = Some(theme.into());
+        self
+    }
+
+    /// Returns `self` with `file` as the file path.
+    #[must_use]
+    pub fn with_file(mut self, file: impl AsRef<Path>) -> Self {
+        self.file = Some(file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Runs `f` with `self` as the context of the current thread, restoring the previous one after.
+    ///
+    /// Since contexts are per-thread, work spawned on other threads (e.g., with rayon)
+    /// needs to be scoped again, usually with a clone of [`Context::current`].
+    pub fn scope<T>(self, f: impl FnOnce() -> T) -> T {
+        /// Restores the previous context, even if `f` panics.
+        struct Restore(Option<Context>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                if let Some(previous) = self.0.take() {
+                    CURRENT.set(previous);
+                }
+            }
+        }
+
+        let _restore = Restore(Some(CURRENT.replace(self)));
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scoped_context() {
+        let theme = Context::default().with_theme("theme");
+
+        theme.clone().scope(|| {
+            assert_eq!(Context::current(), theme);
+
+            Context::current().with_file("arrow.ani").scope(|| {
+                let current = Context::current();
+                assert_eq!(current.theme.as_deref(), Some("theme"));
+                assert_eq!(current.file.as_deref(), Some(Path::new("arrow.ani")));
+            });
+
+            assert_eq!(Context::current(), theme);
+        });
+
+        assert_eq!(Context::current(), Context::default());
+    }
+}
@@ -2,22 +2,70 @@
 
 use super::symlinks::get_symlinks;
 use crate::{
-    cursors::generic_cursor::GenericCursor,
-    formats::{crs::parse_crs_installer, inf::parse_inf_installer},
-    fs_utils::resolve_icase,
-    warn,
+    cursors::{
+        decode::{DecodeOptions, Limits},
+        generic_cursor::GenericCursor,
+    },
+    formats::{
+        cape::Cape,
+        crs::parse_crs_installer,
+        inf::parse_inf_installer,
+        registry::{Registry, ThemeFile},
+    },
+    fs_utils::{create_dir_atomic, resolve_icase},
+    logging,
 };
 
 use std::{
     fs::{self, File},
     io::Write,
+    iter,
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result, anyhow, bail};
+use anyhow::{Context, Result, bail};
 use documented::DocumentedVariants;
 use fast_image_resize::ResizeAlg;
-use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
+use log::warn;
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
+use thiserror::Error;
+
+/// An error from creating a [`CursorTheme`], including from reading its installer and cursors.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ThemeError {
+    /// The theme has no cursors.
+    #[error("can't create theme with no cursors (empty)")]
+    Empty,
+    /// The theme has more cursors than there are [`CursorType`] variants.
+    #[error("too many cursors; expected {max} max for theme, got {count}")]
+    TooManyCursors { count: usize, max: usize },
+    /// Multiple cursors have the same [`CursorType`].
+    #[error("duplicate cursor type={cursor_type:?} for path={}", path.display())]
+    DuplicateType {
+        cursor_type: CursorType,
+        path: PathBuf,
+    },
+    /// The installer file isn't INF or CRS.
+    #[error("unsupported installer file path={}, expected INF or CRS", path.display())]
+    UnsupportedInstaller { path: PathBuf },
+    /// A cursor in the installer doesn't exist, even case-insensitively.
+    #[error("cursor path={} for type={cursor_type:?} not found in parent (case-insensitive)", path.display())]
+    CursorNotFound {
+        cursor_type: CursorType,
+        path: PathBuf,
+    },
+    /// A cursor in the installer couldn't be read.
+    #[error("failed to read path={} for type={cursor_type:?}", path.display())]
+    Cursor {
+        cursor_type: CursorType,
+        path: PathBuf,
+        #[source]
+        source: anyhow::Error,
+    },
+}
 
 /// Cursor mappings stored in installer files.
 #[derive(Debug, PartialEq, Eq)]
@@ -132,6 +180,26 @@ impl CursorType {
         Self::Move,
         Self::CenterPtr,
     ];
+
+    /// Returns the variant named `name`, ignoring case, dashes and underscores.
+    ///
+    /// For example, `nwse-resize`, `NWSE_RESIZE` and `NwseResize` all return [`Self::NwseResize`].
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let normalize = |s: &str| s.replace(['-', '_'], "").to_ascii_lowercase();
+        let name = normalize(name);
+
+        Self::VARIANTS
+            .into_iter()
+            .find(|v| normalize(&format!("{v:?}")) == name)
+    }
+
+    /// Returns the Xcursor names of this type. The first is the file that's
+    /// written, and the rest are symlinks to it (aliases).
+    #[must_use]
+    pub const fn xcursor_names(&self) -> &'static [&'static str] {
+        get_symlinks(self)
+    }
 }
 
 /// A [`GenericCursor`] with a [`CursorType`].
@@ -140,10 +208,25 @@ pub struct TypedCursor {
     inner: GenericCursor,
     /// Semantic usage of cursor, e.g for typing.
     r#type: CursorType,
+    /// Path the cursor was read from.
+    path: PathBuf,
 }
 
 impl TypedCursor {
-    /// Creates a cursor from `mapping`.
+    /// Trivial constructor.
+    ///
+    /// `path` is used to match hotspot overrides and for messages,
+    /// so it can be anything that identifies the cursor.
+    #[must_use]
+    pub const fn new(inner: GenericCursor, r#type: CursorType, path: PathBuf) -> Self {
+        Self {
+            inner,
+            r#type,
+            path,
+        }
+    }
+
+    /// Creates a cursor from `mapping`, decoded with `registry`.
     ///
     /// Note that this does a case-insensitive search if the path stored in `mapping` doesn't
     /// exist. This aspect is also why this isn't inside of a [`TryFrom`] implementation.
@@ -152,53 +235,139 @@ impl TypedCursor {
     ///
     /// - if path contained inside of `mapping` doesn't exist, even after a case-insensitive check
     /// - generic cursor parsing fails
-    pub fn from_mapping(mapping: CursorMapping) -> Result<Self> {
+    ///
+    /// These are [`ThemeError`]s.
+    pub fn from_mapping(
+        mapping: CursorMapping,
+        registry: &Registry,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
         let CursorMapping { path, r#type } = mapping;
 
         let path = if path.exists() {
             path
         } else {
-            resolve_icase(&path)?.ok_or_else(|| {
-                anyhow!(
-                    "cursor path, path={} not found in parent (case-insensitive)",
-                    path.display()
-                )
-            })?
+            match resolve_icase(&path)? {
+                Some(path) => path,
+                None => bail!(ThemeError::CursorNotFound {
+                    cursor_type: r#type,
+                    path,
+                }),
+            }
+        };
+
+        let inner = match registry.decode(&path, options) {
+            Ok(inner) => inner,
+            Err(source) => bail!(ThemeError::Cursor {
+                cursor_type: r#type,
+                path,
+                source,
+            }),
         };
 
-        let inner = GenericCursor::from_path(&path)
-            .with_context(|| format!("while reading path={} as generic cursor", path.display()))?;
+        Ok(Self {
+            inner,
+            r#type,
+            path,
+        })
+    }
+
+    /// Returns the file this is saved as in `dir`, with its symlinks as aliases.
+    fn x11_file(&self, dir: &Path) -> ThemeFile {
+        let names = get_symlinks(&self.r#type);
 
-        Ok(Self { inner, r#type })
+        ThemeFile {
+            path: dir.join(names[0]),
+            cursor_type: Some(self.r#type.clone()),
+            aliases: names[1..].iter().map(|name| dir.join(name)).collect(),
+        }
     }
 
-    /// Saves as Xcursor to `dir`, along with symlinks.
-    fn save_as_xcursor(&self, dir: &Path) -> Result<()> {
-        let aliases = get_symlinks(&self.r#type);
-        self.inner.save_as_xcursor(dir.join(aliases[0]))?;
+    /// Saves as Xcursor to `file`, along with symlinks, see [`Self::x11_file`].
+    fn save_as_xcursor(&self, file: &ThemeFile) -> Result<()> {
+        self.inner.save_as_xcursor(&file.path)?;
 
         // relative symlink
         #[cfg(unix)]
-        for symlink in &aliases[1..] {
-            use std::{io, os::unix};
+        if let Some(target) = file.path.file_name() {
+            use std::os::unix;
 
-            match unix::fs::symlink(aliases[0], dir.join(symlink)) {
-                Ok(()) => Ok(()),
-                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
-                Err(e) => Err(e).with_context(|| {
+            for alias in &file.aliases {
+                // themes are written to a new dir, so an existing link means something's wrong
+                unix::fs::symlink(target, alias).with_context(|| {
                     format!(
                         "failed to create symlink {} pointing to {}",
-                        dir.join(symlink).display(),
-                        aliases[0]
+                        alias.display(),
+                        target.display()
                     )
-                }),
-            }?;
+                })?;
+            }
         }
 
         Ok(())
     }
 }
 
+/// Directory of an X11 theme that cursors are written in, see [`CursorTheme::x11_theme_files`].
+const X11_CURSOR_DIR: &str = "cursors";
+
+/// Returns the lowercase extension of `path`, if it's an installer file (INF or CRS).
+fn installer_extension(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+
+    matches!(ext.as_str(), "inf" | "crs").then_some(ext)
+}
+
+/// Returns true if `path` has the extension of an installer file (INF or CRS).
+#[must_use]
+pub fn is_installer(path: &Path) -> bool {
+    installer_extension(path).is_some()
+}
+
+/// Parses the installer file (INF or CRS) at `path`, based on its extension.
+///
+/// Returns the tuple (`theme_name`, `cursor_mappings`), where the name is empty for CRS.
+///
+/// ## Errors
+///
+/// - If `path` isn't an installer file, see [`is_installer`]. This is a [`ThemeError`].
+/// - From [`parse_inf_installer`] and [`parse_crs_installer`].
+pub fn parse_installer(path: &Path) -> Result<(String, Vec<CursorMapping>)> {
+    match installer_extension(path).as_deref() {
+        Some("inf") => parse_inf_installer(path),
+        Some(_) => Ok((String::new(), parse_crs_installer(path)?)),
+        None => bail!(ThemeError::UnsupportedInstaller {
+            path: path.to_path_buf(),
+        }),
+    }
+}
+
+/// Returns the theme `name` as used for saved themes, see [`CursorTheme::sanitized_name`].
+///
+/// This can be used to name a theme before its cursors are read (e.g., from [`parse_installer`]).
+#[must_use]
+pub fn sanitize_name(name: &str) -> String {
+    let name = if name.is_empty() {
+        warn!("blank theme name, using a placeholder name");
+        "placeholder_theme_name"
+    } else {
+        name
+    };
+
+    name.replace(['/', '\\', '.'], "_")
+}
+
+/// Returns the current [`logging::Context`] with the theme `name`, unless it's blank.
+fn theme_context(name: &str) -> logging::Context {
+    let context = logging::Context::current();
+
+    if name.is_empty() {
+        context
+    } else {
+        context.with_theme(name)
+    }
+}
+
 /// Represents a generic cursor theme.
 #[derive(Debug)]
 pub struct CursorTheme {
@@ -214,23 +383,27 @@ impl CursorTheme {
     /// - `cursors` is empty
     /// - more cursors than variants
     /// - duplicate variants
+    ///
+    /// These are [`ThemeError`]s.
     pub fn new(cursors: Vec<TypedCursor>, name: String) -> Result<Self> {
         if cursors.is_empty() {
-            bail!("can't create theme with no cursors (empty)");
+            bail!(ThemeError::Empty);
         }
 
         if cursors.len() > CursorType::NUM_VARIANTS {
-            bail!(
-                "too many cursors; expected {} max for theme, got {}",
-                CursorType::NUM_VARIANTS,
-                cursors.len(),
-            );
+            bail!(ThemeError::TooManyCursors {
+                count: cursors.len(),
+                max: CursorType::NUM_VARIANTS,
+            });
         }
 
         let mut seen = Vec::new();
         for cursor in &cursors {
             if seen.contains(&cursor.r#type) {
-                bail!("duplicate cursor type: {:?}", cursor.r#type);
+                bail!(ThemeError::DuplicateType {
+                    cursor_type: cursor.r#type.clone(),
+                    path: cursor.path.clone(),
+                });
             }
 
             seen.push(cursor.r#type.clone());
@@ -239,31 +412,59 @@ impl CursorTheme {
         Ok(Self { cursors, name })
     }
 
-    /// Reads provided cursors as a path.
+    /// Reads provided cursors as a path, decoding them with `registry`.
     ///
     /// ## Errors
     ///
-    /// Mostly from parsing the INF file and filesystem operations.
-    pub fn from_installer_file(installer_file: impl AsRef<Path>) -> Result<Self> {
-        let installer_file = installer_file.as_ref();
-        let ext = installer_file.extension().ok_or_else(|| {
-            anyhow!(
-                "no extension for installer_file={}",
-                installer_file.display()
-            )
-        })?;
+    /// Mostly from parsing the installer file (see [`parse_installer`]) and filesystem operations.
+    pub fn from_installer_file(
+        installer_file: impl AsRef<Path>,
+        registry: &Registry,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        let (name, mappings) = parse_installer(installer_file.as_ref())?;
+        Self::from_mappings(name, mappings, registry, options)
+    }
 
-        let (name, mappings) = if ext.eq_ignore_ascii_case("inf") {
-            parse_inf_installer(installer_file)?
-        } else if ext.eq_ignore_ascii_case("crs") {
-            (String::new(), parse_crs_installer(installer_file)?)
-        } else {
-            bail!("unsupported installer file extension ext={}", ext.display())
-        };
+    /// Reads the cursors in `mappings` (e.g., from [`parse_installer`]) as a theme
+    /// named `name`, decoding them with `registry`.
+    ///
+    /// ## Errors
+    ///
+    /// If any cursor can't be read, or from [`Self::new`].
+    pub fn from_mappings(
+        name: String,
+        mappings: Vec<CursorMapping>,
+        registry: &Registry,
+        options: &DecodeOptions,
+    ) -> Result<Self> {
+        Self::from_mappings_by(name, mappings, registry, |_, _| options.clone())
+    }
 
+    /// Reads the cursors in `mappings` as a theme named `name`, where `options` returns
+    /// the [`DecodeOptions`] to use for the theme's name and each [`CursorType`].
+    ///
+    /// This is like [`Self::from_mappings`], but allows decoding each cursor differently.
+    ///
+    /// ## Errors
+    ///
+    /// If any cursor can't be read, or from [`Self::new`].
+    pub fn from_mappings_by(
+        name: String,
+        mappings: Vec<CursorMapping>,
+        registry: &Registry,
+        options: impl Fn(&str, &CursorType) -> DecodeOptions,
+    ) -> Result<Self> {
+        let context = theme_context(&name);
         let typed_cursors: Vec<_> = mappings
             .into_iter()
-            .map(TypedCursor::from_mapping)
+            .map(|m| {
+                let options = options(&name, &m.r#type);
+                context
+                    .clone()
+                    .with_file(&m.path)
+                    .scope(|| TypedCursor::from_mapping(m, registry, &options))
+            })
             .collect::<Result<_>>()?;
 
         Self::new(typed_cursors, name)
@@ -274,16 +475,83 @@ impl CursorTheme {
     /// ## Errors
     ///
     /// From [`GenericCursor::add_scale`].
-    pub fn add_scale(&mut self, scale_factor: f64, algorithm: ResizeAlg) -> Result<()> {
-        self.cursors
-            .par_iter_mut()
-            .try_for_each(|c| c.inner.add_scale(scale_factor, algorithm))?;
+    pub fn add_scale(
+        &mut self,
+        scale_factor: f64,
+        algorithm: ResizeAlg,
+        limits: &Limits,
+    ) -> Result<()> {
+        let context = theme_context(&self.name);
+        self.cursors.par_iter_mut().try_for_each(|c| {
+            context
+                .clone()
+                .with_file(&c.path)
+                .scope(|| c.inner.add_scale(scale_factor, algorithm, limits))
+        })?;
 
         Ok(())
     }
 
+    /// Adds scales to all cursors for the current theme, where `scales` returns
+    /// the scale factors and algorithms to use for each [`CursorType`].
+    ///
+    /// This is like [`Self::add_scale`], but allows scaling each cursor differently.
+    ///
+    /// ## Errors
+    ///
+    /// From [`GenericCursor::add_scale`].
+    pub fn add_scales_by(
+        &mut self,
+        scales: impl Fn(&CursorType) -> Vec<(f64, ResizeAlg)> + Sync,
+        limits: &Limits,
+    ) -> Result<()> {
+        let context = theme_context(&self.name);
+        self.cursors.par_iter_mut().try_for_each(|c| {
+            context.clone().with_file(&c.path).scope(|| {
+                scales(&c.r#type)
+                    .into_iter()
+                    .try_for_each(|(sf, algorithm)| c.inner.add_scale(sf, algorithm, limits))
+            })
+        })?;
+
+        Ok(())
+    }
+
+    /// Returns an iterator over each cursor and its [`CursorType`].
+    pub fn cursors(&self) -> impl Iterator<Item = (&CursorType, &GenericCursor)> {
+        self.cursors.iter().map(|c| (&c.r#type, &c.inner))
+    }
+
+    /// Returns an iterator over each cursor mutably, along with
+    /// its [`CursorType`] and the path it was read from.
+    pub fn cursors_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (&CursorType, &Path, &mut GenericCursor)> {
+        self.cursors
+            .iter_mut()
+            .map(|c| (&c.r#type, c.path.as_path(), &mut c.inner))
+    }
+
+    /// Returns the name of the theme, as in its installer (see [`Self::sanitized_name`]).
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name used for the saved theme.
+    ///
+    /// Returns a placeholder if the name is blank, and
+    /// replaces characters that could be misread in paths.
+    #[must_use]
+    pub fn sanitized_name(&self) -> String {
+        sanitize_name(&self.name)
+    }
+
     /// Saves current theme in `dir`, which is created if it doesn't already exist.
     ///
+    /// The theme is written atomically, so an existing theme with the same name is only
+    /// replaced once every cursor is written, and a failed write leaves nothing behind.
+    ///
     /// This creates symlinks unless the target OS is Windows,
     /// in which case, a warning is logged and we continue.
     ///
@@ -291,16 +559,43 @@ impl CursorTheme {
     ///
     /// If writing Xcursor/symlinks fail.
     pub fn save_as_x11_theme(&self, dir: &Path) -> Result<()> {
-        let name = if self.name.is_empty() {
-            warn!("blank theme name, using a placeholder name");
-            String::from("placeholder_theme_name")
-        } else {
-            self.name.clone()
+        self.save_as_x11_theme_at(&dir.join(self.sanitized_name()))
+    }
+
+    /// Saves current theme as the directory `theme_dir`, see [`Self::save_as_x11_theme`].
+    ///
+    /// ## Errors
+    ///
+    /// If writing Xcursor/symlinks fail.
+    pub fn save_as_x11_theme_at(&self, theme_dir: &Path) -> Result<()> {
+        create_dir_atomic(theme_dir, |tmp_dir| self.write_x11_theme(tmp_dir))
+            .with_context(|| format!("failed to write theme_dir={}", theme_dir.display()))
+    }
+
+    /// Returns the files that [`Self::save_as_x11_theme_at`] writes in `theme_dir`, which
+    /// are "index.theme" and then each cursor in "cursors", with its symlinks as aliases.
+    #[must_use]
+    pub fn x11_theme_files(&self, theme_dir: &Path) -> Vec<ThemeFile> {
+        let cursor_dir = theme_dir.join(X11_CURSOR_DIR);
+        let index = ThemeFile {
+            path: theme_dir.join("index.theme"),
+            cursor_type: None,
+            aliases: Vec::new(),
         };
 
-        let sanitized = name.replace(['/', '\\', '.'], "_");
-        let theme_dir = dir.join(sanitized);
-        let cursor_dir = theme_dir.join("cursors");
+        iter::once(index)
+            .chain(self.cursors.iter().map(|c| c.x11_file(&cursor_dir)))
+            .collect()
+    }
+
+    /// Helper function for [`Self::save_as_x11_theme`], writing the theme in `theme_dir`.
+    ///
+    /// The layout is from [`Self::x11_theme_files`].
+    fn write_x11_theme(&self, theme_dir: &Path) -> Result<()> {
+        let files = self.x11_theme_files(theme_dir);
+        let (index, cursor_files) = (&files[0].path, &files[1..]);
+
+        let cursor_dir = theme_dir.join(X11_CURSOR_DIR);
         fs::create_dir_all(&cursor_dir)
             .with_context(|| format!("failed to write cursor_dir={}", cursor_dir.display()))?;
 
@@ -317,12 +612,19 @@ impl CursorTheme {
             self.write_symlink_script(&cursor_dir)?;
         }
 
+        let context = theme_context(&self.name);
         self.cursors
             .par_iter()
-            .try_for_each(|c| c.save_as_xcursor(&cursor_dir))?;
+            .zip(cursor_files)
+            .try_for_each(|(c, file)| {
+                context
+                    .clone()
+                    .with_file(&c.path)
+                    .scope(|| c.save_as_xcursor(file))
+            })?;
 
         /* ... write index.theme ... */
-        let mut f = File::create(theme_dir.join("index.theme"))?;
+        let mut f = File::create(index)?;
         writeln!(
             &mut f,
             "# https://specifications.freedesktop.org/icon-theme/latest/#id-1.5.3.2"
@@ -346,6 +648,29 @@ impl CursorTheme {
         Ok(())
     }
 
+    /// Saves current theme in `dir` as a Mousecape cape, named after the theme.
+    ///
+    /// Cursor types without a macOS equivalent are skipped with a warning.
+    ///
+    /// ## Errors
+    ///
+    /// If encoding or writing the cape fails.
+    pub fn save_as_cape(&self, dir: &Path) -> Result<()> {
+        self.save_as_cape_at(&dir.join(format!("{}.cape", self.sanitized_name())))
+    }
+
+    /// Saves current theme as the Mousecape cape at `path`, see [`Self::save_as_cape`].
+    ///
+    /// ## Errors
+    ///
+    /// If encoding or writing the cape fails.
+    pub fn save_as_cape_at(&self, path: &Path) -> Result<()> {
+        let cape = Cape::new(self, &self.sanitized_name())?;
+
+        cape.save(path)
+            .with_context(|| format!("failed to write cape={}", path.display()))
+    }
+
     /// Writes a bash script to `cursor_dir` that
     /// creates symlinks for windows "compatibility".
     ///
@@ -374,3 +699,81 @@ impl CursorTheme {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_root, unwrap_err_as};
+
+    use std::fs;
+
+    /// Checks that the files of an X11 theme are the ones listed by [`CursorTheme::x11_theme_files`].
+    #[test]
+    fn x11_theme_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme_dir = dir.path().join("theme");
+        let theme = CursorTheme::from_installer_file(
+            from_root!("/testing/fixtures/neuro/Install.inf"),
+            Registry::builtin(),
+            &DecodeOptions::default(),
+        )
+        .unwrap();
+        theme.save_as_x11_theme_at(&theme_dir).unwrap();
+
+        let mut planned: Vec<_> = theme
+            .x11_theme_files(&theme_dir)
+            .into_iter()
+            .flat_map(|f| iter::once(f.path).chain(f.aliases))
+            .collect();
+        planned.sort();
+
+        let cursor_dir = theme_dir.join("cursors");
+        let mut written: Vec<_> = fs::read_dir(&cursor_dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .chain([theme_dir.join("index.theme")])
+            .collect();
+        written.sort();
+
+        assert_eq!(fs::read_dir(&theme_dir).unwrap().count(), 2);
+        assert_eq!(planned, written);
+    }
+
+    /// Checks that errors have the right kind.
+    #[test]
+    fn error_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Registry::builtin();
+        let options = DecodeOptions::default();
+
+        let from_mapping = |path: PathBuf| {
+            let mapping = CursorMapping {
+                r#type: CursorType::Arrow,
+                path,
+            };
+
+            unwrap_err_as::<ThemeError>(TypedCursor::from_mapping(mapping, registry, &options))
+        };
+
+        let missing = dir.path().join("missing.cur");
+        let err = from_mapping(missing.clone());
+        assert!(matches!(
+            err,
+            ThemeError::CursorNotFound { cursor_type: CursorType::Arrow, path } if path == missing
+        ));
+
+        let junk = dir.path().join("junk.cur");
+        fs::write(&junk, b"not a cursor").unwrap();
+        let err = from_mapping(junk.clone());
+        assert!(matches!(err, ThemeError::Cursor { path, .. } if path == junk));
+
+        let theme_txt = dir.path().join("theme.txt");
+        let err = unwrap_err_as::<ThemeError>(CursorTheme::from_installer_file(
+            theme_txt, registry, &options,
+        ));
+        assert!(matches!(err, ThemeError::UnsupportedInstaller { .. }));
+
+        let err = unwrap_err_as::<ThemeError>(CursorTheme::new(Vec::new(), String::new()));
+        assert!(matches!(err, ThemeError::Empty));
+    }
+}
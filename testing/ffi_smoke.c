@@ -0,0 +1,93 @@
+// Smoke test for the C API in include/currust.h.
+//
+// Build and run from the repository root, after `cargo build`:
+//
+//     cc -Wall -Wextra -Iinclude testing/ffi_smoke.c -Ltarget/debug -lcurrust -o ffi_smoke
+//     LD_LIBRARY_PATH=target/debug ./ffi_smoke "testing/fixtures/neuro/Neuro help.ani" testing/fixtures/neuro "$(mktemp -d)"
+
+#include <currust.h>
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define CHECK(cond)                                                         \
+    do {                                                                    \
+        if (!(cond)) {                                                      \
+            const char* err = currust_last_error();                         \
+            fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n",  \
+                __FILE__, __LINE__, #cond, err ? err : "none");             \
+            return 1;                                                       \
+        }                                                                   \
+    } while (0)
+
+// Reads the file at `path` into `*out`, returning its length (or -1 on failure).
+static long read_file(const char* path, uint8_t** out) {
+    FILE* file = fopen(path, "rb");
+
+    if (!file) {
+        return -1;
+    }
+
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    rewind(file);
+
+    *out = malloc((size_t) len);
+
+    if (!*out || fread(*out, 1, (size_t) len, file) != (size_t) len) {
+        fclose(file);
+        return -1;
+    }
+
+    fclose(file);
+    return len;
+}
+
+int main(int argc, char** argv) {
+    if (argc != 4) {
+        fprintf(stderr, "usage: ./ffi_smoke <cursor_file> <theme_dir> <out_dir>\n");
+        return 1;
+    }
+
+    uint8_t* data = NULL;
+    long len = read_file(argv[1], &data);
+    CHECK(len > 0);
+
+    CurrustCursor* cursor = NULL;
+    CHECK(currust_cursor_from_buffer(data, (uintptr_t) len, &cursor) == CURRUST_STATUS_OK);
+    free(data);
+
+    // unknown algorithms are rejected, rather than being undefined behaviour
+    CHECK(currust_cursor_add_scale(cursor, 2.0, 99) == CURRUST_STATUS_INVALID_ARGUMENT);
+    CHECK(currust_last_error() != NULL);
+    CHECK(currust_cursor_add_scale(cursor, 2.0, CURRUST_SCALING_BOX) == CURRUST_STATUS_OK);
+
+    CurrustBuffer buffer = {0};
+    CHECK(currust_cursor_write_xcursor(cursor, &buffer) == CURRUST_STATUS_OK);
+    CHECK(buffer.len > 4 && memcmp(buffer.data, "Xcur", 4) == 0);
+
+    CHECK(currust_cursor_write_xcursor(NULL, &buffer) == CURRUST_STATUS_INVALID_ARGUMENT);
+    CHECK(strcmp(currust_last_error(), "cursor is null") == 0);
+
+    CHECK(currust_cursor_from_buffer((const uint8_t*) "nope", 4, &cursor) == CURRUST_STATUS_ERROR);
+
+    printf("ok: wrote %zu bytes of Xcursor\n", (size_t) buffer.len);
+
+    currust_buffer_free(buffer);
+    currust_cursor_free(cursor);
+
+    const double scales[] = {1.5, 2.0};
+    CHECK(currust_convert_theme(argv[2], argv[3], scales, 2, CURRUST_SCALING_BOX)
+        == CURRUST_STATUS_OK);
+
+    // the output directory has no installer
+    CHECK(currust_convert_theme(argv[3], argv[3], NULL, 0, CURRUST_SCALING_BOX)
+        == CURRUST_STATUS_ERROR);
+    CHECK(currust_convert_theme(argv[2], NULL, NULL, 0, CURRUST_SCALING_BOX)
+        == CURRUST_STATUS_INVALID_ARGUMENT);
+
+    printf("ok: converted theme in %s\n", argv[2]);
+
+    return 0;
+}